@@ -10,11 +10,19 @@ use egui::{
 use egui_extras::{Column, TableBuilder};
 use serde::{Deserialize, Serialize};
 
+use crate::cached_data::CachingDeferredDataSource;
+use crate::critical_path::{critical_path, PathItem, PathStep};
 use crate::data::{
-    DataSourceInfo, EntryID, EntryIndex, EntryInfo, Field, FieldID, FieldSchema, ItemLink,
-    ItemMeta, ItemUID, SlotMetaTileData, SlotTileData, SummaryTileData, TileID, TileSet, UtilPoint,
+    DataSourceError, DataSourceInfo, EntryID, EntryIndex, EntryInfo, EntryLevel, Field, FieldID,
+    FieldSchema, ItemLink, ItemMeta, ItemUID, SearchMode, SearchRequest, SearchResponse,
+    SlotMetaTile, SlotMetaTileData, SlotTileData, SummaryTileData, TileID, TileSet, UtilPoint,
+};
+use crate::deferred_data::{CountingDeferredDataSource, DeferredDataSource, TileResponse};
+use crate::http::schema::TileKind;
+use crate::search::ItemMatcher;
+use crate::statistics::{
+    sort_rows, statistics, Histogram, SlotItems, StatsColumn, StatsItem, StatsRow,
 };
-use crate::deferred_data::{CountingDeferredDataSource, DeferredDataSource};
 use crate::timestamp::{Interval, Timestamp, TimestampParseError};
 
 /// Overview:
@@ -36,7 +44,8 @@ use crate::timestamp::{Interval, Timestamp, TimestampParseError};
 ///   * Window configuration state (i.e., specific to a profile)
 ///
 /// Panel:
-///   * One Panel for each level of nesting in the profile (root, node, kind)
+///   * One Panel for each level of nesting in the profile (e.g., root, node, kind)
+///   * Panels nest to whatever depth the profile requires
 ///   * Table widget for (nested) cells
 ///   * Each row contains: label, content
 ///
@@ -52,6 +61,8 @@ struct Summary {
     entry_id: EntryID,
     color: Color32,
     tiles: BTreeMap<TileID, Option<SummaryTileData>>,
+    // Tiles that failed to load, which stay empty in tiles
+    failed: BTreeSet<TileID>,
     last_view_interval: Option<Interval>,
 }
 
@@ -65,18 +76,27 @@ struct Slot {
     tile_ids: Vec<TileID>,
     tiles: BTreeMap<TileID, Option<SlotTileData>>,
     tile_metas: BTreeMap<TileID, Option<SlotMetaTileData>>,
+    // Same as Summary::failed
+    failed: BTreeSet<TileID>,
+    failed_metas: BTreeSet<TileID>,
     last_view_interval: Option<Interval>,
 }
 
 #[derive(Debug, Clone)]
-struct Panel<S: Entry> {
+struct Panel {
     entry_id: EntryID,
     short_name: String,
     long_name: String,
     expanded: bool,
 
     summary: Option<Summary>,
-    slots: Vec<S>,
+    slots: Vec<PanelChild>,
+}
+
+#[derive(Debug, Clone)]
+enum PanelChild {
+    Panel(Panel),
+    Slot(Slot),
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +107,14 @@ struct ItemLocator {
     irow: Option<usize>,
 }
 
+// A link from an item on screen (though not necessarily in a rendered row)
+#[derive(Debug, Clone)]
+struct FoundLink {
+    link: ItemLink,
+    source: ItemLocator,
+    source_interval: Interval,
+}
+
 #[derive(Debug, Clone)]
 struct SearchCacheItem {
     item_uid: ItemUID,
@@ -113,36 +141,116 @@ struct SearchState {
     last_include_collapsed_entries: bool,
     search_field: FieldID,
     last_search_field: FieldID,
+    search_mode: SearchMode,
+    last_search_mode: SearchMode,
     last_view_interval: Option<Interval>,
 
+    // Compiled form of the query (None if empty or invalid)
+    matcher: Option<ItemMatcher>,
+    query_error: Option<String>,
+
+    // Last request sent to the data source, when it supports search
+    remote_request: Option<SearchRequest>,
+    remote_pending: bool,
+
     // Cache of matching items
     result_set: BTreeSet<ItemUID>,
     result_cache: BTreeMap<EntryID, BTreeMap<TileID, BTreeMap<ItemUID, SearchCacheItem>>>,
-    entry_tree: BTreeMap<u64, BTreeMap<u64, BTreeSet<u64>>>,
+    // Entries with results, plus all of their ancestors
+    entry_tree: BTreeSet<EntryID>,
+
+    // Results to highlight, as an inclusive range of duration histogram
+    // bins (None for all), and the bin where the user started dragging
+    duration_brush: Option<(i32, i32)>,
+    brush_anchor: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+struct LevelFilter {
+    // Range of entries (by index within their parent) to show
+    min_index: u64,
+    max_index: u64,
+    max_slots: u64,
+
+    // Short names that repeat across parents at this level (e.g., the
+    // kinds of processors), if there are few enough to offer as toggles
+    labels: Vec<String>,
+    label_filter: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CriticalPathState {
+    // Shown in the side panel (and highlighted, if requested)
+    active: bool,
+    highlight: bool,
+    interval: Interval,
+
+    // Complete meta tiles for every slot are needed to compute the path,
+    // so we track which are still outstanding
+    pending: BTreeSet<(EntryID, TileID)>,
+    requested: usize,
+    // Tiles that failed to load, in which case the path may be incomplete
+    failed: usize,
+    items: BTreeMap<ItemUID, PathItem>,
+
+    steps: Vec<PathStep>,
+    on_path: BTreeSet<ItemUID>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StatisticsState {
+    // Shown in a window
+    active: bool,
+    // Group slots by kind, rather than showing each slot
+    by_kind: bool,
+    interval: Interval,
+
+    // Slots that passed the filters: entry ID, name, and kind
+    slots: Vec<(EntryID, String, String)>,
+    // As with the critical path, every item is needed, so we fetch complete
+    // meta tiles (only these carry titles) and track which are outstanding
+    pending: BTreeSet<(EntryID, TileID)>,
+    requested: usize,
+    // Tiles that failed to load, in which case results are incomplete
+    failed: usize,
+    items: BTreeMap<EntryID, BTreeMap<ItemUID, StatsItem>>,
+
+    rows: Vec<StatsRow>,
+    // Column, and whether descending
+    sort: Option<(StatsColumn, bool)>,
+    // Set by clicking on a group or title in the table
+    group_filter: Option<String>,
+    title_filter: Option<String>,
 }
 
 struct Config {
     field_schema: FieldSchema,
 
-    // Node selection
-    min_node: u64,
-    max_node: u64,
-
-    // Kind selection
-    kinds: Vec<String>,
-    kind_filter: BTreeSet<String>,
+    // Filters for each level of the entry tree (below the root)
+    level_filters: Vec<LevelFilter>,
 
     // This is just for the local profile
     interval: Interval,
     tile_set: TileSet,
 
-    data_source: CountingDeferredDataSource<Box<dyn DeferredDataSource>>,
+    data_source: CachingDeferredDataSource<CountingDeferredDataSource<Box<dyn DeferredDataSource>>>,
 
     search_state: SearchState,
 
     // When the user clicks on an item, we put it here
     items_selected: BTreeMap<ItemUID, (ItemMeta, ItemLocator)>,
 
+    // Dependencies (i.e., ItemLinks) are always drawn for selected items,
+    // and optionally for everything on screen
+    show_all_links: bool,
+    // Rebuilt every frame as items are rendered
+    link_item_rects: BTreeMap<ItemUID, Rect>,
+    links: BTreeMap<(ItemUID, ItemUID), FoundLink>,
+
+    critical_path: CriticalPathState,
+
+    statistics: StatisticsState,
+
     // When the user clicks "Zoom to Item" or a search result, we put it here
     scroll_to_item: Option<ItemLocator>,
     // Same, but keep it around to highlight the item after arrival
@@ -150,10 +258,18 @@ struct Config {
 
     last_request_interval: Option<Interval>,
     request_tile_cache: Vec<TileID>,
+
+    // Latest revision of a live profile (see DataSource::fetch_info_update)
+    info_revision: u64,
+
+    // Most recent failure reported by the data source (if any), and how many
+    // requests have failed since the user last dismissed the error
+    last_error: Option<DataSourceError>,
+    error_count: u64,
 }
 
 struct Window {
-    panel: Panel<Panel<Panel<Slot>>>, // nodes -> kind -> proc/chan/mem
+    panel: Panel,
     index: u64,
     config: Config,
 }
@@ -162,6 +278,11 @@ struct Window {
 struct ZoomState {
     levels: Vec<Interval>,
     index: usize,
+    // Time of the last pan (in egui's input time) while a pan gesture is in
+    // progress, so that the pans in a gesture update the current level in
+    // place rather than pushing new levels
+    #[serde(skip)]
+    pan_time: Option<f64>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
@@ -208,6 +329,16 @@ struct IntervalSelectState {
     stop_error: Option<IntervalSelectError>,
 }
 
+impl IntervalSelectState {
+    // Show the view interval, discarding whatever the user had entered
+    fn reset(&mut self, interval: Interval) {
+        self.start_buffer = interval.start.to_string();
+        self.stop_buffer = interval.stop.to_string();
+        self.start_error = None;
+        self.stop_error = None;
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 struct Context {
     #[serde(skip)]
@@ -258,6 +389,10 @@ struct ProfApp {
     #[serde(skip)]
     windows: Vec<Window>,
 
+    // Data sources that failed before we could create a window for them.
+    #[serde(skip)]
+    load_errors: Vec<DataSourceError>,
+
     cx: Context,
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -266,7 +401,9 @@ struct ProfApp {
 }
 
 trait Entry {
-    fn new(info: &EntryInfo, entry_id: EntryID) -> Self;
+    fn new(info: &EntryInfo, entry_id: EntryID) -> Self
+    where
+        Self: Sized;
 
     fn entry_id(&self) -> &EntryID;
     fn label_text(&self) -> &str;
@@ -334,8 +471,16 @@ trait Entry {
 }
 
 impl Summary {
-    fn clear(&mut self) {
+    fn clear(&mut self, config: &mut Config, cx: &Context) {
+        // Cancel outstanding requests for tiles we won't be asking for again
+        let keep = config.request_tiles(cx.view_interval);
+        for (tile_id, tile) in &self.tiles {
+            if tile.is_none() && !keep.contains(tile_id) && !self.failed.contains(tile_id) {
+                config.cancel_tile(TileKind::Summary, &self.entry_id, *tile_id);
+            }
+        }
         self.tiles.clear();
+        self.failed.clear();
     }
 
     fn inflate(&mut self, config: &mut Config, cx: &mut Context) {
@@ -346,6 +491,31 @@ impl Summary {
             self.tiles.insert(tile_id, None);
         }
     }
+
+    // Remember a tile that failed to load (unless we still have older data
+    // for it), so that it isn't requested again until the view or the
+    // profile changes
+    fn mark_failed_tile(&mut self, tile_id: TileID) {
+        if let Some(None) = self.tiles.get(&tile_id) {
+            self.failed.insert(tile_id);
+        }
+    }
+
+    // Fetch the tiles overlapping the part of the profile that grew again,
+    // keeping the old data until they arrive (unless the tiles themselves
+    // changed)
+    fn refresh(&mut self, config: &mut Config, grown: Interval, tiles_changed: bool) {
+        if tiles_changed {
+            self.last_view_interval = None;
+            return;
+        }
+        self.failed.retain(|tile_id| !tile_id.0.overlaps(grown));
+        for tile_id in self.tiles.keys().filter(|t| t.0.overlaps(grown)) {
+            config
+                .data_source
+                .fetch_summary_tile(&self.entry_id, *tile_id, false);
+        }
+    }
 }
 
 impl Entry for Summary {
@@ -355,6 +525,7 @@ impl Entry for Summary {
                 entry_id,
                 color: *color,
                 tiles: BTreeMap::new(),
+                failed: BTreeSet::new(),
                 last_view_interval: None,
             }
         } else {
@@ -408,11 +579,8 @@ impl Entry for Summary {
         let response = ui.allocate_rect(rect, egui::Sense::hover());
         let hover_pos = response.hover_pos(); // where is the mouse hovering?
 
-        if self
-            .last_view_interval
-            .map_or(true, |i| i != cx.view_interval)
-        {
-            self.clear();
+        if self.last_view_interval != Some(cx.view_interval) {
+            self.clear(config, cx);
         }
         self.last_view_interval = Some(cx.view_interval);
         if self.tiles.is_empty() {
@@ -519,6 +687,7 @@ impl fmt::Display for Field {
         match self {
             Field::I64(value) => write!(f, "{value}"),
             Field::U64(value) => write!(f, "{value}"),
+            Field::F64(value) => write!(f, "{value}"),
             Field::String(value) => write!(f, "{value}"),
             Field::Interval(value) => write!(f, "{value}"),
             Field::ItemLink(ItemLink { title, .. }) => write!(f, "{title}"),
@@ -536,6 +705,22 @@ impl fmt::Display for Field {
     }
 }
 
+fn item_links(fields: &[(FieldID, Field)]) -> Vec<&ItemLink> {
+    fn collect<'a>(field: &'a Field, links: &mut Vec<&'a ItemLink>) {
+        match field {
+            Field::ItemLink(link) => links.push(link),
+            Field::Vec(fields) => fields.iter().for_each(|f| collect(f, links)),
+            _ => {}
+        }
+    }
+
+    let mut links = Vec::new();
+    for (_, field) in fields {
+        collect(field, &mut links);
+    }
+    links
+}
+
 struct FieldWithName<'a>(&'a str, &'a Field);
 
 impl<'a> fmt::Display for FieldWithName<'a> {
@@ -558,10 +743,24 @@ impl Slot {
         }
     }
 
-    fn clear(&mut self) {
+    fn clear(&mut self, config: &mut Config, cx: &Context) {
+        // Cancel outstanding requests for tiles we won't be asking for again
+        let keep = config.request_tiles(cx.view_interval);
+        for (tile_id, tile) in &self.tiles {
+            if tile.is_none() && !keep.contains(tile_id) && !self.failed.contains(tile_id) {
+                config.cancel_tile(TileKind::Slot, &self.entry_id, *tile_id);
+            }
+        }
+        for (tile_id, tile) in &self.tile_metas {
+            if tile.is_none() && !keep.contains(tile_id) && !self.failed_metas.contains(tile_id) {
+                config.cancel_tile(TileKind::SlotMeta, &self.entry_id, *tile_id);
+            }
+        }
         self.tile_ids.clear();
         self.tiles.clear();
         self.tile_metas.clear();
+        self.failed.clear();
+        self.failed_metas.clear();
     }
 
     fn inflate(&mut self, config: &mut Config, cx: &mut Context) {
@@ -574,6 +773,59 @@ impl Slot {
         }
     }
 
+    // Same as Summary::mark_failed_tile
+    fn mark_failed_tile(&mut self, tile_id: TileID) {
+        if let Some(None) = self.tiles.get(&tile_id) {
+            self.failed.insert(tile_id);
+        }
+    }
+
+    fn mark_failed_meta_tile(&mut self, tile_id: TileID) {
+        if let Some(None) = self.tile_metas.get(&tile_id) {
+            self.failed_metas.insert(tile_id);
+        }
+    }
+
+    // Same as Summary::refresh. Meta tiles are fetched again on demand.
+    fn refresh(&mut self, config: &mut Config, grown: Interval, tiles_changed: bool) {
+        if tiles_changed {
+            self.tile_metas.clear();
+            self.failed_metas.clear();
+            self.last_view_interval = None;
+            return;
+        }
+        self.tile_metas
+            .retain(|tile_id, _| !tile_id.0.overlaps(grown));
+        self.failed_metas
+            .retain(|tile_id| !tile_id.0.overlaps(grown));
+        self.failed.retain(|tile_id| !tile_id.0.overlaps(grown));
+        for tile_id in self.tile_ids.iter().filter(|t| t.0.overlaps(grown)) {
+            config
+                .data_source
+                .fetch_slot_tile(&self.entry_id, *tile_id, false);
+        }
+    }
+
+    // Live profiles may add rows to existing slots
+    fn update(&mut self, info: &EntryInfo) {
+        if let EntryInfo::Slot { max_rows, .. } = info {
+            self.max_rows = *max_rows;
+        }
+    }
+
+    // Screen-space row of an item, if a meta tile holding it is loaded
+    fn item_row(&self, item_uid: ItemUID, interval: Interval) -> Option<usize> {
+        (self.tile_metas.iter())
+            .filter(|(tile_id, _)| tile_id.0.overlaps(interval))
+            .filter_map(|(_, tile)| tile.as_ref())
+            .find_map(|tile| {
+                let rows = tile.items.len();
+                let row = (tile.items.iter())
+                    .position(|row_items| row_items.iter().any(|i| i.item_uid == item_uid))?;
+                Some(rows - row - 1)
+            })
+    }
+
     fn fetch_meta_tile(
         &mut self,
         tile_id: TileID,
@@ -604,6 +856,15 @@ impl Slot {
     ) -> Option<Pos2> {
         // Hack: can't pass this as an argument because it aliases self.
         let tile_id = self.tile_ids[tile_index];
+
+        // Links are stored in item metadata, so we need it to draw them.
+        // Links to selected items are found in whatever metadata is loaded
+        // (see Window::draw_links for links out of them).
+        let show_links = config.show_links();
+        if config.show_all_links && cx.view_interval.overlaps(tile_id.0) {
+            self.fetch_meta_tile(tile_id, config);
+        }
+
         let tile = self.tiles.get(&tile_id).unwrap();
 
         if !tile.is_some() {
@@ -616,6 +877,12 @@ impl Slot {
             return hover_pos;
         }
 
+        if show_links {
+            if let Some(Some(tile_meta)) = self.tile_metas.get(&tile_id) {
+                config.record_links(&self.entry_id, tile_meta, cx.view_interval);
+            }
+        }
+
         // Track which item, if any, we're interacting with
         let mut interact_item = None;
 
@@ -665,18 +932,32 @@ impl Slot {
                 let highlight = config.items_selected.contains_key(&item.item_uid)
                     || config.scroll_to_item_uid == Some(item.item_uid);
 
+                let on_critical_path = config.critical_path.is_highlighted(item.item_uid);
+
                 let mut color = item.color;
                 if !config.search_state.query.is_empty() {
-                    if config.search_state.result_set.contains(&item.item_uid) || highlight {
+                    if config
+                        .search_state
+                        .is_highlighted(&self.entry_id, item.item_uid)
+                        || highlight
+                    {
                         color = Color32::RED;
+                    } else if on_critical_path {
+                        color = Color32::GOLD;
                     } else {
                         color = color.gamma_multiply(0.2);
                     }
                 } else if highlight {
                     color = Color32::RED;
+                } else if on_critical_path {
+                    color = Color32::GOLD;
                 }
 
                 ui.painter().rect(item_rect, 0.0, color, Stroke::NONE);
+
+                if show_links {
+                    config.record_item_rect(item.item_uid, item_rect);
+                }
             }
         }
 
@@ -739,6 +1020,8 @@ impl Entry for Slot {
                 tile_ids: Vec::new(),
                 tiles: BTreeMap::new(),
                 tile_metas: BTreeMap::new(),
+                failed: BTreeSet::new(),
+                failed_metas: BTreeSet::new(),
                 last_view_interval: None,
             }
         } else {
@@ -791,7 +1074,7 @@ impl Entry for Slot {
 
                 for (row, row_items) in tile.items.iter().enumerate() {
                     for item in row_items {
-                        if config.search_state.is_match(item) {
+                        if config.search_state.is_match(&self.entry_id, item) {
                             // Reverse rows because we're in screen space
                             let irow = tile.items.len() - row - 1;
                             config.search_state.insert(self, *tile_id, irow, item);
@@ -816,11 +1099,8 @@ impl Entry for Slot {
         let mut hover_pos = response.hover_pos(); // where is the mouse hovering?
 
         if self.expanded {
-            if self
-                .last_view_interval
-                .map_or(true, |i| i != cx.view_interval)
-            {
-                self.clear();
+            if self.last_view_interval != Some(cx.view_interval) {
+                self.clear(config, cx);
             }
             self.last_view_interval = Some(cx.view_interval);
             if self.tiles.is_empty() {
@@ -853,7 +1133,10 @@ impl Entry for Slot {
     }
 }
 
-impl<S: Entry> Panel<S> {
+impl Panel {
+    const LABEL_WIDTH: f32 = 60.0;
+    const COL_PADDING: f32 = 4.0;
+
     fn render<T: Entry>(
         ui: &mut egui::Ui,
         rect: Rect,
@@ -863,8 +1146,6 @@ impl<S: Entry> Panel<S> {
         config: &mut Config,
         cx: &mut Context,
     ) -> bool {
-        const LABEL_WIDTH: f32 = 60.0;
-        const COL_PADDING: f32 = 4.0;
         const ROW_PADDING: f32 = 4.0;
 
         // Compute the size of this slot
@@ -883,8 +1164,8 @@ impl<S: Entry> Panel<S> {
 
         // Draw label and content
         let label_min = rect.min.x;
-        let label_max = (rect.min.x + LABEL_WIDTH).at_most(rect.max.x);
-        let content_min = (label_max + COL_PADDING).at_most(rect.max.x);
+        let label_max = (rect.min.x + Self::LABEL_WIDTH).at_most(rect.max.x);
+        let content_min = (label_max + Self::COL_PADDING).at_most(rect.max.x);
         let content_max = rect.max.x;
 
         let label_subrect =
@@ -904,23 +1185,214 @@ impl<S: Entry> Panel<S> {
         false
     }
 
-    fn is_slot_visible(slot: &S, config: &Config) -> bool {
-        let level = slot.entry_id().level();
-        if level == 1 {
-            // Apply node filter.
-            let index = slot.entry_id().last_slot_index().unwrap();
-            index >= config.min_node && index <= config.max_node
-        } else if level == 2 {
-            // Apply kind filter.
-            let kind = slot.label_text();
-            config.kind_filter.is_empty() || config.kind_filter.contains(kind)
-        } else {
-            true
+    fn is_slot_visible(slot: &PanelChild, config: &Config) -> bool {
+        let entry_id = slot.entry_id();
+        let Some(filter) = config.level_filters.get(entry_id.level() as usize - 1) else {
+            return true;
+        };
+
+        // Apply index filter.
+        let index = entry_id.last_slot_index().unwrap();
+        if index < filter.min_index || index > filter.max_index {
+            return false;
+        }
+
+        // Apply label filter.
+        let label = slot.label_text();
+        filter.label_filter.is_empty() || filter.label_filter.contains(label)
+    }
+
+    // Add the entries of a live profile that are new in info
+    fn update(&mut self, info: &EntryInfo) {
+        let EntryInfo::Panel { summary, slots, .. } = info else {
+            unreachable!()
+        };
+        if self.summary.is_none() {
+            self.summary = summary
+                .as_ref()
+                .map(|s| Summary::new(s, self.entry_id.summary()));
+        }
+        for (i, slot) in slots.iter().enumerate() {
+            match self.slots.get_mut(i) {
+                Some(PanelChild::Panel(panel)) => panel.update(slot),
+                Some(PanelChild::Slot(child)) => child.update(slot),
+                None => {
+                    let entry_id = self.entry_id.child(i as u64);
+                    self.slots.push(PanelChild::new(slot, entry_id));
+                }
+            }
+        }
+    }
+
+    fn refresh(&mut self, config: &mut Config, grown: Interval, tiles_changed: bool) {
+        if let Some(summary) = &mut self.summary {
+            summary.refresh(config, grown, tiles_changed);
+        }
+        for slot in &mut self.slots {
+            match slot {
+                PanelChild::Panel(panel) => panel.refresh(config, grown, tiles_changed),
+                PanelChild::Slot(slot) => slot.refresh(config, grown, tiles_changed),
+            }
+        }
+    }
+
+    // The slots that Panel::search would visit
+    fn search_slot_ids(&self, config: &Config, result: &mut Vec<EntryID>) {
+        let force = config.search_state.include_collapsed_entries;
+        if self.expanded || force {
+            for slot in &self.slots {
+                // Apply visibility settings
+                if !force && !Self::is_slot_visible(slot, config) {
+                    continue;
+                }
+
+                match slot {
+                    PanelChild::Panel(panel) => panel.search_slot_ids(config, result),
+                    PanelChild::Slot(slot) => result.push(slot.entry_id.clone()),
+                }
+            }
+        }
+    }
+
+    // The slots that pass the filters (whether expanded or not), along with
+    // their names and the short name of their parent (i.e., their kind)
+    fn statistics_slots(&self, config: &Config, result: &mut Vec<(EntryID, String, String)>) {
+        for slot in &self.slots {
+            if !Self::is_slot_visible(slot, config) {
+                continue;
+            }
+
+            match slot {
+                PanelChild::Panel(panel) => panel.statistics_slots(config, result),
+                PanelChild::Slot(slot) => result.push((
+                    slot.entry_id.clone(),
+                    slot.long_name.clone(),
+                    self.short_name.clone(),
+                )),
+            }
+        }
+    }
+
+    fn slot_ids(&self, result: &mut Vec<EntryID>) {
+        for slot in &self.slots {
+            match slot {
+                PanelChild::Panel(panel) => panel.slot_ids(result),
+                PanelChild::Slot(slot) => result.push(slot.entry_id.clone()),
+            }
+        }
+    }
+
+    fn toggle_expanded_by_label(&mut self, level: u64, label: &str, toggle: bool) {
+        for slot in &mut self.slots {
+            if slot.entry_id().level() == level {
+                if slot.is_expanded() == toggle && slot.label_text() == label {
+                    slot.toggle_expanded();
+                }
+            } else if let PanelChild::Panel(panel) = slot {
+                panel.toggle_expanded_by_label(level, label, toggle);
+            }
+        }
+    }
+}
+
+impl PanelChild {
+    fn entry(&self) -> &dyn Entry {
+        match self {
+            PanelChild::Panel(panel) => panel,
+            PanelChild::Slot(slot) => slot,
+        }
+    }
+
+    fn entry_mut(&mut self) -> &mut dyn Entry {
+        match self {
+            PanelChild::Panel(panel) => panel,
+            PanelChild::Slot(slot) => slot,
+        }
+    }
+
+    fn is_expanded(&self) -> bool {
+        match self {
+            PanelChild::Panel(panel) => panel.expanded,
+            PanelChild::Slot(slot) => slot.expanded,
         }
     }
+
+    fn long_name(&self) -> &str {
+        match self {
+            PanelChild::Panel(panel) => &panel.long_name,
+            PanelChild::Slot(slot) => &slot.long_name,
+        }
+    }
+}
+
+impl Entry for PanelChild {
+    fn new(info: &EntryInfo, entry_id: EntryID) -> Self {
+        match info {
+            EntryInfo::Panel { .. } => PanelChild::Panel(Panel::new(info, entry_id)),
+            EntryInfo::Slot { .. } => PanelChild::Slot(Slot::new(info, entry_id)),
+            EntryInfo::Summary { .. } => unreachable!(),
+        }
+    }
+
+    fn entry_id(&self) -> &EntryID {
+        self.entry().entry_id()
+    }
+    fn label_text(&self) -> &str {
+        self.entry().label_text()
+    }
+    fn hover_text(&self) -> &str {
+        self.entry().hover_text()
+    }
+
+    fn find_slot(&mut self, entry_id: &EntryID, level: u64) -> Option<&mut Slot> {
+        self.entry_mut().find_slot(entry_id, level)
+    }
+
+    fn find_summary(&mut self, entry_id: &EntryID, level: u64) -> Option<&mut Summary> {
+        self.entry_mut().find_summary(entry_id, level)
+    }
+
+    fn expand_slot(&mut self, entry_id: &EntryID, level: u64) {
+        self.entry_mut().expand_slot(entry_id, level)
+    }
+
+    fn inflate_meta(&mut self, config: &mut Config, cx: &mut Context) {
+        self.entry_mut().inflate_meta(config, cx)
+    }
+
+    fn search(&mut self, config: &mut Config) {
+        self.entry_mut().search(config)
+    }
+
+    fn label(&mut self, ui: &mut egui::Ui, rect: Rect, cx: &Context) {
+        self.entry_mut().label(ui, rect, cx)
+    }
+
+    fn content(
+        &mut self,
+        ui: &mut egui::Ui,
+        rect: Rect,
+        viewport: Rect,
+        config: &mut Config,
+        cx: &mut Context,
+    ) {
+        self.entry_mut().content(ui, rect, viewport, config, cx)
+    }
+
+    fn height(&self, prefix: Option<&EntryID>, config: &Config, cx: &Context) -> f32 {
+        self.entry().height(prefix, config, cx)
+    }
+
+    fn is_expandable(&self) -> bool {
+        self.entry().is_expandable()
+    }
+
+    fn toggle_expanded(&mut self) {
+        self.entry_mut().toggle_expanded()
+    }
 }
 
-impl<S: Entry> Entry for Panel<S> {
+impl Entry for Panel {
     fn new(info: &EntryInfo, entry_id: EntryID) -> Self {
         if let EntryInfo::Panel {
             short_name,
@@ -929,14 +1401,18 @@ impl<S: Entry> Entry for Panel<S> {
             slots,
         } = info
         {
-            let expanded = entry_id.level() != 2;
+            // Panels that directly contain slots (e.g., the processors of a
+            // given kind) start collapsed, except the root, which has no
+            // label to click on.
+            let expanded =
+                entry_id.level() == 0 || slots.iter().any(|s| matches!(s, EntryInfo::Panel { .. }));
             let summary = summary
                 .as_ref()
                 .map(|s| Summary::new(s, entry_id.summary()));
             let slots = slots
                 .iter()
                 .enumerate()
-                .map(|(i, s)| S::new(s, entry_id.child(i as u64)))
+                .map(|(i, s)| PanelChild::new(s, entry_id.child(i as u64)))
                 .collect();
             Self {
                 entry_id,
@@ -1107,11 +1583,22 @@ impl SearchState {
             last_include_collapsed_entries: false,
             search_field: title_id,
             last_search_field: title_id,
+            search_mode: SearchMode::Substring,
+            last_search_mode: SearchMode::Substring,
             last_view_interval: None,
 
+            matcher: None,
+            query_error: None,
+
+            remote_request: None,
+            remote_pending: false,
+
             result_set: BTreeSet::new(),
             result_cache: BTreeMap::new(),
-            entry_tree: BTreeMap::new(),
+            entry_tree: BTreeSet::new(),
+
+            duration_brush: None,
+            brush_anchor: None,
         }
     }
 
@@ -1119,20 +1606,32 @@ impl SearchState {
         self.result_set.clear();
         self.result_cache.clear();
         self.entry_tree.clear();
+        self.remote_request = None;
+        self.remote_pending = false;
     }
 
-    fn ensure_valid_cache(&mut self, cx: &Context) {
+    fn ensure_valid_cache(&mut self, field_schema: &FieldSchema, cx: &Context) {
         let mut invalidate = false;
+        let mut recompile = false;
 
         // Invalidate when the search query changes.
         if self.query != self.last_query {
             invalidate = true;
+            recompile = true;
             self.last_query = self.query.clone();
         }
 
+        // Invalidate when the search mode changes.
+        if self.search_mode != self.last_search_mode {
+            invalidate = true;
+            recompile = true;
+            self.last_search_mode = self.search_mode;
+        }
+
         // Invalidate when the search field changes.
         if self.search_field != self.last_search_field {
             invalidate = true;
+            recompile = true;
             self.last_search_field = self.search_field;
         }
 
@@ -1151,33 +1650,34 @@ impl SearchState {
             self.last_view_interval = Some(cx.view_interval);
         }
 
+        if recompile {
+            self.matcher = None;
+            self.query_error = None;
+            self.duration_brush = None;
+            if !self.query.is_empty() {
+                let field = self.field();
+                match ItemMatcher::new(self.search_mode, &self.query, field, field_schema) {
+                    Ok(matcher) => self.matcher = Some(matcher),
+                    Err(e) => self.query_error = Some(e),
+                }
+            }
+        }
+
         if invalidate {
             self.clear();
         }
     }
 
-    fn is_string_match(&self, s: &str) -> bool {
-        s.contains(&self.query)
-    }
-
-    fn is_field_match(&self, field: &Field) -> bool {
-        match field {
-            Field::String(s) => self.is_string_match(s),
-            Field::ItemLink(ItemLink { title, .. }) => self.is_string_match(title),
-            Field::Vec(fields) => fields.iter().any(|f| self.is_field_match(f)),
-            _ => false,
-        }
+    // The search field, or None when searching titles (which aren't
+    // fields as far as the data source is concerned)
+    fn field(&self) -> Option<FieldID> {
+        Some(self.search_field).filter(|field| *field != self.title_field)
     }
 
-    fn is_match(&self, item: &ItemMeta) -> bool {
-        let field = self.search_field;
-        if field == self.title_field {
-            item.title.contains(&self.query)
-        } else if let Some((_, value)) = item.fields.iter().find(|(x, _)| *x == field) {
-            self.is_field_match(value)
-        } else {
-            false
-        }
+    fn is_match(&self, entry_id: &EntryID, item: &ItemMeta) -> bool {
+        self.matcher
+            .as_ref()
+            .map_or(false, |matcher| matcher.is_match(entry_id, item))
     }
 
     const MAX_SEARCH_RESULTS: usize = 100_000;
@@ -1192,7 +1692,7 @@ impl SearchState {
         if !self.result_cache.contains_key(entry.entry_id()) {
             self.result_cache
                 .entry(entry.entry_id().clone())
-                .or_insert_with(BTreeMap::new);
+                .or_default();
         }
 
         // Always recurse into tiles, because results can be fetched
@@ -1214,7 +1714,7 @@ impl SearchState {
             .and_modify(|_| {
                 result = false;
             })
-            .or_insert_with(BTreeMap::new);
+            .or_default();
         result
     }
 
@@ -1239,59 +1739,329 @@ impl SearchState {
         }
     }
 
-    fn build_entry_tree(&mut self) {
-        for (entry_id, cache) in &self.result_cache {
-            let cache_size: u64 = cache.values().map(|x| x.len() as u64).sum();
-            if cache_size == 0 {
-                continue;
-            }
+    fn is_highlighted(&self, entry_id: &EntryID, item_uid: ItemUID) -> bool {
+        if !self.result_set.contains(&item_uid) {
+            return false;
+        }
+        let Some((first, last)) = self.duration_brush else {
+            return true;
+        };
+        // Need the cached item for its original (unsliced) duration
+        (self.result_cache.get(entry_id))
+            .and_then(|cache| cache.values().find_map(|items| items.get(&item_uid)))
+            .map_or(false, |item| {
+                (first..=last).contains(&Histogram::bin(item.interval.duration_ns()))
+            })
+    }
 
-            let level0_index = entry_id.slot_index(0).unwrap();
-            let level1_index = entry_id.slot_index(1).unwrap();
-            let level2_index = entry_id.slot_index(2).unwrap();
+    fn histogram(&self) -> Histogram {
+        Histogram::new(
+            (self.result_cache.values())
+                .flat_map(|cache| cache.values())
+                .flat_map(|items| items.values())
+                .map(|item| item.interval.duration_ns()),
+        )
+    }
 
-            let level0_subtree = self
-                .entry_tree
-                .entry(level0_index)
-                .or_insert_with(BTreeMap::new);
-            let level1_subtree = level0_subtree
-                .entry(level1_index)
-                .or_insert_with(BTreeSet::new);
-            level1_subtree.insert(level2_index);
+    fn request(&self, interval: Interval, entry_ids: Option<Vec<EntryID>>) -> SearchRequest {
+        SearchRequest {
+            mode: self.search_mode,
+            query: self.query.clone(),
+            field: self.field(),
+            interval,
+            entry_ids,
+            max_results: Self::MAX_SEARCH_RESULTS,
         }
     }
-}
 
-impl Config {
-    fn new(data_source: Box<dyn DeferredDataSource>, info: DataSourceInfo) -> Self {
-        let max_node = info.entry_info.nodes();
-        let kinds = info.entry_info.kinds();
-        let interval = info.interval;
-        let tile_set = info.tile_set;
+    // Results from the data source replace whatever we have, as long as
+    // they're for the latest request
+    fn receive(&mut self, response: SearchResponse) {
+        if self.remote_request.as_ref() != Some(&response.request) {
+            return;
+        }
+        self.remote_pending = false;
+        self.result_set.clear();
+        self.result_cache.clear();
+        self.entry_tree.clear();
 
-        let mut field_schema = info.field_schema;
-        assert!(!field_schema.contains_name("Title"));
+        // Results aren't associated with tiles, so file them under the
+        // whole search interval
+        let tile_id = TileID(response.request.interval);
+        for result in response.results {
+            if self.result_set.len() >= Self::MAX_SEARCH_RESULTS {
+                break;
+            }
+            if self.result_set.insert(result.item_uid) {
+                self.result_cache
+                    .entry(result.entry_id)
+                    .or_default()
+                    .entry(tile_id)
+                    .or_default()
+                    .insert(
+                        result.item_uid,
+                        SearchCacheItem {
+                            item_uid: result.item_uid,
+                            irow: result.row,
+                            interval: result.original_interval,
+                            title: result.title,
+                        },
+                    );
+            }
+        }
+    }
+
+    fn build_entry_tree(&mut self) {
+        for (entry_id, cache) in &self.result_cache {
+            let cache_size: u64 = cache.values().map(|x| x.len() as u64).sum();
+            if cache_size == 0 {
+                continue;
+            }
+
+            let mut entry_id = Some(entry_id.clone());
+            while let Some(id) = entry_id {
+                entry_id = id.parent();
+                if !self.entry_tree.insert(id) {
+                    // Ancestors were already inserted
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl CriticalPathState {
+    fn is_highlighted(&self, item_uid: ItemUID) -> bool {
+        self.active && self.highlight && self.on_path.contains(&item_uid)
+    }
+
+    fn receive(&mut self, response: &TileResponse<SlotMetaTile>) {
+        if !(self.pending).remove(&(response.entry_id.clone(), response.tile_id)) {
+            return;
+        }
+
+        match &response.result {
+            Ok(tile) => self.add_tile(tile),
+            Err(_) => self.failed += 1,
+        }
+
+        if self.pending.is_empty() {
+            self.steps = critical_path(&self.items, self.interval);
+            self.on_path = self.steps.iter().map(|step| step.item_uid).collect();
+            self.items.clear();
+        }
+    }
+
+    fn add_tile(&mut self, tile: &SlotMetaTile) {
+        let rows = tile.data.items.len();
+        for (row, row_items) in tile.data.items.iter().enumerate() {
+            for item in row_items {
+                // Items that cross tile boundaries show up more than once
+                self.items.entry(item.item_uid).or_insert_with(|| PathItem {
+                    item_uid: item.item_uid,
+                    entry_id: tile.entry_id.clone(),
+                    // Reverse rows because we're in screen space
+                    row: rows - row - 1,
+                    interval: item.original_interval,
+                    title: item.title.clone(),
+                    links: item_links(&item.fields)
+                        .iter()
+                        .map(|link| link.item_uid)
+                        .collect(),
+                });
+            }
+        }
+    }
+}
+
+impl StatisticsState {
+    fn receive(&mut self, response: &TileResponse<SlotMetaTile>) {
+        if !(self.pending).remove(&(response.entry_id.clone(), response.tile_id)) {
+            return;
+        }
+
+        match &response.result {
+            Ok(tile) => {
+                let items = self.items.entry(tile.entry_id.clone()).or_default();
+                for item in tile.data.items.iter().flatten() {
+                    // Items that cross tile boundaries show up more than once
+                    items.entry(item.item_uid).or_insert_with(|| StatsItem {
+                        interval: item.original_interval,
+                        title: item.title.clone(),
+                    });
+                }
+            }
+            Err(_) => self.failed += 1,
+        }
+
+        if self.pending.is_empty() {
+            self.compute();
+        }
+    }
+
+    // Items are kept around so that switching the grouping doesn't need
+    // the tiles again
+    fn compute(&mut self) {
+        let slots: Vec<_> = (self.slots.iter())
+            .map(|(entry_id, name, kind)| SlotItems {
+                group: if self.by_kind { kind } else { name }.clone(),
+                items: (self.items.get(entry_id))
+                    .map(|items| items.values().cloned().collect())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        self.rows = statistics(&slots, self.interval);
+        if let Some((column, descending)) = self.sort {
+            sort_rows(&mut self.rows, column, descending);
+        }
+    }
+
+    fn is_row_visible(&self, row: &StatsRow) -> bool {
+        self.group_filter.as_ref().map_or(true, |g| *g == row.group)
+            && (self.title_filter.as_ref()).map_or(true, |t| row.title.as_ref() == Some(t))
+    }
+}
+
+impl LevelFilter {
+    const MAX_LABELS: usize = 32;
+
+    fn new(level: EntryLevel) -> Self {
+        // Only offer labels as filters when they repeat (otherwise the
+        // index range does the same job).
+        let labels = if (level.short_names.len() as u64) < level.entries
+            && level.short_names.len() <= Self::MAX_LABELS
+        {
+            level.short_names
+        } else {
+            Vec::new()
+        };
+        Self {
+            min_index: 0,
+            max_index: level.max_slots.saturating_sub(1),
+            max_slots: level.max_slots,
+            labels,
+            label_filter: BTreeSet::new(),
+        }
+    }
+
+    // The level gained entries (in a live profile). Keep the user's
+    // choices, but extend the range if it went up to the last entry.
+    fn update(&mut self, level: EntryLevel) {
+        let updated = Self::new(level);
+        if self.max_index + 1 >= self.max_slots {
+            self.max_index = updated.max_index;
+        }
+        self.max_slots = updated.max_slots;
+        self.labels = updated.labels;
+    }
+}
+
+impl Config {
+    const TILE_CACHE_CAPACITY: usize = 256 << 20; // bytes
+
+    fn new(data_source: Box<dyn DeferredDataSource>, info: DataSourceInfo) -> Self {
+        let level_filters = info
+            .entry_info
+            .levels()
+            .into_iter()
+            .map(LevelFilter::new)
+            .collect();
+        let interval = info.interval;
+        let tile_set = info.tile_set;
+
+        let mut field_schema = info.field_schema;
+        assert!(!field_schema.contains_name("Title"));
         let title_id = field_schema.insert("Title".to_owned(), true);
         let search_state = SearchState::new(title_id);
 
         Self {
             field_schema,
-            min_node: 0,
-            max_node,
-            kinds,
-            kind_filter: BTreeSet::new(),
+            level_filters,
             interval,
             tile_set,
-            data_source: CountingDeferredDataSource::new(data_source),
+            data_source: CachingDeferredDataSource::new(
+                CountingDeferredDataSource::new(data_source),
+                Self::TILE_CACHE_CAPACITY,
+            ),
             search_state,
             items_selected: BTreeMap::new(),
+            show_all_links: false,
+            link_item_rects: BTreeMap::new(),
+            links: BTreeMap::new(),
+            critical_path: CriticalPathState::default(),
+            statistics: StatisticsState::default(),
             scroll_to_item: None,
             scroll_to_item_uid: None,
             last_request_interval: None,
             request_tile_cache: Vec::new(),
+            info_revision: 0,
+            last_error: None,
+            error_count: 0,
+        }
+    }
+
+    fn full_tiles(&self) -> Vec<TileID> {
+        self.tile_set.full_tiles(self.interval)
+    }
+
+    fn show_links(&self) -> bool {
+        self.show_all_links || !self.items_selected.is_empty()
+    }
+
+    fn record_item_rect(&mut self, item_uid: ItemUID, item_rect: Rect) {
+        // Items that cross tile boundaries get rendered in pieces
+        self.link_item_rects
+            .entry(item_uid)
+            .and_modify(|r| *r = r.union(item_rect))
+            .or_insert(item_rect);
+    }
+
+    // Goes through every row of the tile, since the source of a link to a
+    // selected item may be in a row that isn't rendered
+    fn record_links(&mut self, entry_id: &EntryID, tile: &SlotMetaTileData, view: Interval) {
+        let rows = tile.items.len();
+        for (row, row_items) in tile.items.iter().enumerate() {
+            for item in row_items {
+                // Links out of selected items are handled in
+                // Window::draw_links, because they're needed even when the
+                // item is out of view
+                if !item.original_interval.overlaps(view)
+                    || self.items_selected.contains_key(&item.item_uid)
+                {
+                    continue;
+                }
+                for link in item_links(&item.fields) {
+                    if self.show_all_links || self.items_selected.contains_key(&link.item_uid) {
+                        self.links
+                            .entry((item.item_uid, link.item_uid))
+                            .or_insert_with(|| FoundLink {
+                                link: link.clone(),
+                                source: ItemLocator {
+                                    entry_id: entry_id.clone(),
+                                    // Reverse rows because we're in screen space
+                                    irow: Some(rows - row - 1),
+                                },
+                                source_interval: item.original_interval,
+                            });
+                    }
+                }
+            }
         }
     }
 
+    // Cancel the request for a tile that is no longer being displayed.
+    // Views never ask for full tiles, so requests made by the critical path
+    // or statistics for the same tile are left alone.
+    fn cancel_tile(&mut self, kind: TileKind, entry_id: &EntryID, tile_id: TileID) {
+        self.data_source.cancel_tile(kind, entry_id, tile_id, false);
+    }
+
+    fn report_error(&mut self, error: DataSourceError) {
+        log::error!("data source error: {}", error);
+        self.last_error = Some(error);
+        self.error_count += 1;
+    }
+
     fn request_tiles(&mut self, request_interval: Interval) -> Vec<TileID> {
         if self.last_request_interval == Some(request_interval) {
             return self.request_tile_cache.clone();
@@ -1339,6 +2109,33 @@ impl Window {
         }
     }
 
+    // A live profile changed: take in the new interval and entries, and
+    // fetch what's on screen again, since any tile may have changed
+    fn update_info(&mut self, info: DataSourceInfo) {
+        self.panel.update(&info.entry_info);
+        let config = &mut self.config;
+        for (i, level) in info.entry_info.levels().into_iter().enumerate() {
+            match config.level_filters.get_mut(i) {
+                Some(filter) => filter.update(level),
+                None => config.level_filters.push(LevelFilter::new(level)),
+            }
+        }
+
+        // Only the part of the profile after the old end can change
+        let grown = Interval::new(config.interval.stop, info.interval.stop);
+        let tiles_changed = config.tile_set.tiles != info.tile_set.tiles;
+        config.interval = info.interval;
+        config.tile_set = info.tile_set;
+        config.last_request_interval = None;
+        config.data_source.invalidate(grown);
+        self.panel.refresh(config, grown, tiles_changed);
+
+        // Results may be missing new items
+        if !config.search_state.query.is_empty() {
+            config.search_state.clear();
+        }
+    }
+
     fn find_slot(&mut self, entry_id: &EntryID) -> Option<&mut Slot> {
         self.panel.find_slot(entry_id, 0)
     }
@@ -1357,6 +2154,8 @@ impl Window {
             ui.label(cx.view_interval.to_string())
         });
 
+        self.error_banner(ui);
+
         ScrollArea::vertical()
             .auto_shrink([false; 2])
             .show_viewport(ui, |ui, viewport| {
@@ -1376,72 +2175,577 @@ impl Window {
                     self.config.scroll_to_item = None;
                 }
 
+                self.config.link_item_rects.clear();
+                self.config.links.clear();
+
                 // Root panel has no label
                 self.panel.content(ui, rect, viewport, &mut self.config, cx);
+
+                if self.config.show_links() {
+                    self.draw_links(ui, rect, cx);
+                }
             });
     }
 
-    fn node_selection(&mut self, ui: &mut egui::Ui, cx: &Context) {
-        ui.subheading("Node Selection", cx);
-        let total = self.panel.slots.len().saturating_sub(1) as u64;
-        let min_node = &mut self.config.min_node;
-        let max_node = &mut self.config.max_node;
-        ui.add(Slider::new(min_node, 0..=total).text("First"));
-        if *min_node > *max_node {
-            *max_node = *min_node;
+    fn draw_links(&mut self, ui: &mut egui::Ui, rect: Rect, cx: &Context) {
+        // Find the rows of link targets that weren't rendered. Only links
+        // out of selected items are worth fetching metadata for, and only
+        // for tiles on screen (otherwise the target is off screen anyway).
+        let selected_links: Vec<_> = (self.config.items_selected.values())
+            .flat_map(|(item_meta, _)| item_links(&item_meta.fields))
+            .cloned()
+            .collect();
+        let other_links: Vec<_> = (self.config.links.values())
+            .map(|found| found.link.clone())
+            .collect();
+        let mut target_rows = BTreeMap::new();
+        for (link, fetch) in
+            (selected_links.iter().map(|l| (l, true))).chain(other_links.iter().map(|l| (l, false)))
+        {
+            if self.config.link_item_rects.contains_key(&link.item_uid) {
+                continue;
+            }
+            let Some(slot) = self.panel.find_slot(&link.entry_id, 0) else {
+                continue;
+            };
+            if fetch {
+                for tile_id in slot.tile_ids.clone() {
+                    if tile_id.0.overlaps(link.interval) {
+                        slot.fetch_meta_tile(tile_id, &mut self.config);
+                    }
+                }
+            }
+            if let Some(irow) = slot.item_row(link.item_uid, link.interval) {
+                target_rows.insert(link.item_uid, irow);
+            }
+        }
+
+        let mut prefix_heights = BTreeMap::new();
+        let mut endpoint =
+            |item_uid, entry_id: &EntryID, irow: Option<usize>, interval: Interval| {
+                if let Some(item_rect) = self.config.link_item_rects.get(&item_uid) {
+                    return *item_rect;
+                }
+                let irow = irow.or_else(|| target_rows.get(&item_uid).copied());
+
+                // The item wasn't rendered this frame (e.g., because it's
+                // scrolled out of view). Estimate where it would be from the
+                // position of its entry, the same way scroll_to_item does.
+                let prefix_height = *prefix_heights
+                    .entry(entry_id.clone())
+                    .or_insert_with(|| self.panel.height(Some(entry_id), &self.config, cx));
+                let min_y = rect.min.y + prefix_height + irow.unwrap_or(0) as f32 * cx.row_height;
+                let label_width = Panel::LABEL_WIDTH + Panel::COL_PADDING;
+                let min_x =
+                    (rect.min.x + entry_id.level() as f32 * label_width).at_most(rect.max.x);
+                let content = Rect::from_min_max(
+                    Pos2::new(min_x, min_y),
+                    Pos2::new(rect.max.x, min_y + cx.row_height),
+                );
+                let start = cx.view_interval.unlerp(interval.start).clamp(-1.0, 2.0);
+                let stop = cx.view_interval.unlerp(interval.stop).clamp(-1.0, 2.0);
+                Rect::from_min_max(
+                    content.lerp_inside(Vec2::new(start, 0.05)),
+                    content.lerp_inside(Vec2::new(stop, 0.95)),
+                )
+            };
+
+        let mut arrows = Vec::new();
+        for (item_meta, item_loc) in self.config.items_selected.values() {
+            let from = endpoint(
+                item_meta.item_uid,
+                &item_loc.entry_id,
+                item_loc.irow,
+                item_meta.original_interval,
+            );
+            for link in item_links(&item_meta.fields) {
+                let to = endpoint(link.item_uid, &link.entry_id, None, link.interval);
+                arrows.push((from, to, true));
+            }
+        }
+        for ((item_uid, _), found) in &self.config.links {
+            // Only links to selected items are drawn from sources that
+            // weren't rendered
+            let link = &found.link;
+            let selected = self.config.items_selected.contains_key(&link.item_uid);
+            if !selected && !self.config.link_item_rects.contains_key(item_uid) {
+                continue;
+            }
+            let from = endpoint(
+                *item_uid,
+                &found.source.entry_id,
+                found.source.irow,
+                found.source_interval,
+            );
+            let to = endpoint(link.item_uid, &link.entry_id, None, link.interval);
+            arrows.push((from, to, selected));
+        }
+
+        let painter = ui.painter();
+        let weak_color = ui.visuals().text_color().gamma_multiply(0.5);
+        for (from, to, selected) in arrows {
+            let color = if selected { Color32::RED } else { weak_color };
+            Self::draw_arrow(painter, from, to, Stroke::new(1.5, color));
         }
-        ui.add(Slider::new(max_node, 0..=total).text("Last"));
-        if *min_node > *max_node {
-            *min_node = *max_node;
+    }
+
+    fn draw_arrow(painter: &egui::Painter, from: Rect, to: Rect, stroke: Stroke) {
+        const ARROW_SIZE: f32 = 6.0;
+        const MIN_BEND: f32 = 20.0;
+
+        // Leave from whichever side of the source faces the target, so that
+        // (e.g.) a dependence on an earlier item points backwards in time.
+        let (start, end) = if to.max.x <= from.min.x {
+            (from.left_center(), to.right_center())
+        } else {
+            (from.right_center(), to.left_center())
+        };
+        let dir = if end.x < start.x { -1.0 } else { 1.0 };
+
+        // Route as a curve with horizontal tangents so that arrows read
+        // clearly as they cross slots
+        let bend = ((end.x - start.x).abs() / 2.0).at_least(MIN_BEND);
+        let control1 = start + Vec2::new(dir * bend, 0.0);
+        let control2 = end - Vec2::new(dir * bend, 0.0);
+        painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+            [start, control1, control2, end],
+            false,
+            Color32::TRANSPARENT,
+            stroke,
+        ));
+
+        let tangent = (end - control2).normalized();
+        let normal = tangent.rot90();
+        let base = end - tangent * ARROW_SIZE;
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                end,
+                base + normal * (ARROW_SIZE / 2.0),
+                base - normal * (ARROW_SIZE / 2.0),
+            ],
+            stroke.color,
+            Stroke::NONE,
+        ));
+    }
+
+    fn error_banner(&mut self, ui: &mut egui::Ui) {
+        let Some(error) = &self.config.last_error else {
+            return;
+        };
+
+        let mut dismiss = false;
+        ui.horizontal(|ui| {
+            let text = if self.config.error_count > 1 {
+                format!(
+                    "Error: {} ({} requests failed)",
+                    error, self.config.error_count
+                )
+            } else {
+                format!("Error: {}", error)
+            };
+            ui.label(RichText::new(text).color(Color32::RED));
+            dismiss = ui.button("✖").clicked();
+        });
+        if dismiss {
+            self.config.last_error = None;
+            self.config.error_count = 0;
         }
     }
 
-    fn filter_by_kind(&mut self, ui: &mut egui::Ui, cx: &Context) {
-        ui.subheading("Filter by Kind", cx);
-        ui.horizontal_wrapped(|ui| {
-            for kind in &self.config.kinds {
-                let initial = self.config.kind_filter.contains(kind);
-                let mut enabled = initial;
-                ui.toggle_value(&mut enabled, kind);
-                if initial != enabled {
-                    if enabled {
-                        self.config.kind_filter.insert(kind.clone());
-                    } else {
-                        self.config.kind_filter.remove(kind);
-                    }
+    fn level_filters(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        for (i, filter) in self.config.level_filters.iter_mut().enumerate() {
+            if filter.max_slots <= 1 && filter.labels.is_empty() {
+                continue;
+            }
+
+            ui.subheading(format!("Filter Level {}", i + 1), cx);
+
+            if filter.max_slots > 1 {
+                let total = filter.max_slots - 1;
+                let min_index = &mut filter.min_index;
+                let max_index = &mut filter.max_index;
+                ui.add(Slider::new(min_index, 0..=total).text("First"));
+                if *min_index > *max_index {
+                    *max_index = *min_index;
+                }
+                ui.add(Slider::new(max_index, 0..=total).text("Last"));
+                if *min_index > *max_index {
+                    *min_index = *max_index;
                 }
             }
-        });
+
+            ui.horizontal_wrapped(|ui| {
+                for label in &filter.labels {
+                    let initial = filter.label_filter.contains(label);
+                    let mut enabled = initial;
+                    ui.toggle_value(&mut enabled, label);
+                    if initial != enabled {
+                        if enabled {
+                            filter.label_filter.insert(label.clone());
+                        } else {
+                            filter.label_filter.remove(label);
+                        }
+                    }
+                }
+            });
+        }
     }
 
     fn expand_collapse(&mut self, ui: &mut egui::Ui, cx: &Context) {
-        let mut toggle_all = |label, toggle| {
-            for node in &mut self.panel.slots {
-                for kind in &mut node.slots {
-                    if kind.expanded == toggle && kind.label_text() == label {
-                        kind.toggle_expanded();
+        let mut toggle = None;
+        for (i, filter) in self.config.level_filters.iter().enumerate() {
+            if filter.labels.is_empty() {
+                continue;
+            }
+
+            let level = i as u64 + 1;
+            ui.subheading(format!("Expand/Collapse Level {}", level), cx);
+            ui.label("Expand by label:");
+            ui.horizontal_wrapped(|ui| {
+                for label in &filter.labels {
+                    if ui.button(label).clicked() {
+                        toggle = Some((level, label.clone(), false));
                     }
                 }
+            });
+            ui.label("Collapse by label:");
+            ui.horizontal_wrapped(|ui| {
+                for label in &filter.labels {
+                    if ui.button(label).clicked() {
+                        toggle = Some((level, label.clone(), true));
+                    }
+                }
+            });
+        }
+
+        if let Some((level, label, toggle)) = toggle {
+            self.panel.toggle_expanded_by_label(level, &label, toggle);
+        }
+    }
+
+    fn dependencies(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        ui.subheading("Dependencies", cx);
+        ui.label("Arrows are drawn between selected items and the items they link to.");
+        ui.checkbox(
+            &mut self.config.show_all_links,
+            "Show dependencies for all visible items",
+        );
+    }
+
+    fn start_critical_path(&mut self) {
+        let mut entry_ids = Vec::new();
+        self.panel.slot_ids(&mut entry_ids);
+        let tile_ids = self.config.full_tiles();
+
+        let state = &mut self.config.critical_path;
+        *state = CriticalPathState {
+            active: true,
+            highlight: true,
+            interval: self.config.interval,
+            ..Default::default()
+        };
+        for entry_id in entry_ids {
+            for tile_id in &tile_ids {
+                self.config
+                    .data_source
+                    .fetch_slot_meta_tile(&entry_id, *tile_id, true);
+                state.pending.insert((entry_id.clone(), *tile_id));
+            }
+        }
+        state.requested = state.pending.len();
+        if state.pending.is_empty() {
+            // Nothing to load, so there is no path
+            state.steps.clear();
+        }
+    }
+
+    fn critical_path_controls(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        ui.subheading("Critical Path", cx);
+        ui.horizontal(|ui| {
+            if ui.button("Compute Critical Path").clicked() {
+                self.start_critical_path();
+            }
+            let state = &mut self.config.critical_path;
+            if state.active {
+                ui.checkbox(&mut state.highlight, "Highlight");
+            }
+        });
+    }
+
+    fn critical_path_results(&mut self, ui: &mut egui::Ui, cx: &mut Context) {
+        let state = &mut self.config.critical_path;
+        ui.horizontal(|ui| {
+            ui.heading(format!("Profile {}: Critical Path", self.index));
+            if ui.button("✖").clicked() {
+                state.active = false;
             }
+        });
+
+        if !state.pending.is_empty() {
+            let received = state.requested - state.pending.len();
+            ui.label(format!(
+                "Loading tiles: {} / {} received",
+                received, state.requested
+            ));
+            return;
+        }
+        if state.failed > 0 {
+            let text = format!(
+                "{} tiles failed to load, so the path may be incomplete.",
+                state.failed
+            );
+            ui.colored_label(Color32::RED, text);
+        }
+
+        let (Some(first), Some(last)) = (state.steps.first(), state.steps.last()) else {
+            ui.label("No items found.");
+            return;
         };
 
-        ui.subheading("Expand/Collapse", cx);
-        ui.label("Expand by kind:");
-        ui.horizontal_wrapped(|ui| {
-            for kind in &self.config.kinds {
-                if ui.button(kind).clicked() {
-                    toggle_all(kind.to_lowercase(), false);
-                }
+        let length = last.interval.stop.0 - first.interval.start.0;
+        let idle: i64 = state.steps.iter().map(|step| step.gap_ns).sum();
+        ui.label(format!("Steps: {}", state.steps.len()));
+        ui.label(format!("Length: {}", Timestamp(length)));
+        ui.label(format!("Idle: {}", Timestamp(idle)));
+
+        let mut clicked = None;
+        let row_height = ui.text_style_height(&TextStyle::Body);
+        ui.push_id(self.index, |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto())
+                .column(Column::remainder().clip(true))
+                .column(Column::auto())
+                .column(Column::auto())
+                .header(row_height, |mut header| {
+                    header.col(|ui| {
+                        ui.strong("#");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Item");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Duration");
+                    });
+                    header.col(|ui| {
+                        ui.strong("Idle Before");
+                    });
+                })
+                .body(|body| {
+                    body.rows(row_height, state.steps.len(), |index, mut row| {
+                        let step = &state.steps[index];
+                        row.col(|ui| {
+                            ui.label(index.to_string());
+                        });
+                        row.col(|ui| {
+                            let button = egui::widgets::Button::new(&step.title).small();
+                            let response = ui.add(button);
+                            if response.clicked() {
+                                clicked = Some(index);
+                            }
+                            if step.via_link {
+                                response.on_hover_text("Linked to the next step");
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(Timestamp(step.interval.duration_ns()).to_string());
+                        });
+                        row.col(|ui| {
+                            ui.label(Timestamp(step.gap_ns).to_string());
+                        });
+                    });
+                });
+        });
+
+        if let Some(index) = clicked {
+            let step = &self.config.critical_path.steps[index];
+            let entry_id = step.entry_id.clone();
+            let interval = step.interval.grow(step.interval.duration_ns() / 20);
+            ProfApp::zoom(cx, interval);
+            self.config.scroll_to_item = Some(ItemLocator {
+                entry_id: entry_id.clone(),
+                irow: Some(step.row),
+            });
+            self.config.scroll_to_item_uid = Some(step.item_uid);
+            self.expand_slot(&entry_id);
+        }
+    }
+
+    fn start_statistics(&mut self, cx: &Context) {
+        let mut slots = Vec::new();
+        self.panel.statistics_slots(&self.config, &mut slots);
+        let view = cx.view_interval;
+        let tile_ids: Vec<_> = (self.config.tile_set.full_tiles(view).into_iter())
+            .filter(|tile_id| tile_id.0.overlaps(view))
+            .collect();
+
+        let state = &mut self.config.statistics;
+        *state = StatisticsState {
+            active: true,
+            by_kind: state.by_kind,
+            interval: view,
+            sort: state.sort,
+            ..Default::default()
+        };
+        for (entry_id, _, _) in &slots {
+            for tile_id in &tile_ids {
+                self.config
+                    .data_source
+                    .fetch_slot_meta_tile(entry_id, *tile_id, true);
+                state.pending.insert((entry_id.clone(), *tile_id));
+            }
+        }
+        state.slots = slots;
+        state.requested = state.pending.len();
+        if state.pending.is_empty() {
+            state.compute();
+        }
+    }
+
+    fn statistics_controls(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        ui.subheading("Statistics", cx);
+        ui.label("Aggregates over the current view and filters.");
+        if ui.button("Compute Statistics").clicked() {
+            self.start_statistics(cx);
+        }
+    }
+
+    fn statistics_results(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        let mut recompute = false;
+        ui.horizontal(|ui| {
+            let state = &mut self.config.statistics;
+            ui.label("Group by:");
+            let slot = ui.radio_value(&mut state.by_kind, false, "Slot");
+            let kind = ui.radio_value(&mut state.by_kind, true, "Kind");
+            if (slot.changed() || kind.changed()) && state.pending.is_empty() {
+                state.compute();
             }
+            recompute = ui
+                .button("Recompute")
+                .on_hover_text("Use the current view and filters")
+                .clicked();
         });
-        ui.label("Collapse by kind:");
-        ui.horizontal_wrapped(|ui| {
-            for kind in &self.config.kinds {
-                if ui.button(kind).clicked() {
-                    toggle_all(kind.to_lowercase(), true);
+        if recompute {
+            self.start_statistics(cx);
+        }
+
+        let state = &mut self.config.statistics;
+        ui.label(format!("Interval: {}", state.interval));
+        if !state.pending.is_empty() {
+            let received = state.requested - state.pending.len();
+            ui.label(format!(
+                "Loading tiles: {} / {} received",
+                received, state.requested
+            ));
+            return;
+        }
+        if state.failed > 0 {
+            let text = format!(
+                "{} tiles failed to load, so results are incomplete.",
+                state.failed
+            );
+            ui.colored_label(Color32::RED, text);
+        }
+
+        if state.group_filter.is_some() || state.title_filter.is_some() {
+            ui.horizontal(|ui| {
+                let group = state.group_filter.as_deref().unwrap_or("any");
+                let title = state.title_filter.as_deref().unwrap_or("any");
+                ui.label(format!("Group: {}, Title: {}", group, title));
+                if ui.button("Clear Filter").clicked() {
+                    state.group_filter = None;
+                    state.title_filter = None;
                 }
-            }
+            });
+        }
+
+        let visible: Vec<_> = (0..state.rows.len())
+            .filter(|i| state.is_row_visible(&state.rows[*i]))
+            .collect();
+
+        let mut sort_by = None;
+        let mut group_clicked = None;
+        let mut title_clicked = None;
+        let row_height = ui.text_style_height(&TextStyle::Body);
+        ui.push_id(self.index, |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto().clip(true))
+                .column(Column::remainder().clip(true))
+                .columns(Column::auto(), StatsColumn::ALL.len() - 2)
+                .header(row_height, |mut header| {
+                    for column in StatsColumn::ALL {
+                        header.col(|ui| {
+                            let mut text = column.name().to_owned();
+                            match state.sort {
+                                Some((c, true)) if c == column => text.push_str(" ⏷"),
+                                Some((c, false)) if c == column => text.push_str(" ⏶"),
+                                _ => {}
+                            }
+                            let button = egui::Button::new(RichText::new(text).strong());
+                            if ui.add(button.frame(false)).clicked() {
+                                sort_by = Some(column);
+                            }
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(row_height, visible.len(), |index, mut row| {
+                        let stats = &state.rows[visible[index]];
+                        row.col(|ui| {
+                            let button = egui::Button::new(&stats.group).small();
+                            if ui.add(button).on_hover_text("Filter to group").clicked() {
+                                group_clicked = Some(stats.group.clone());
+                            }
+                        });
+                        row.col(|ui| match &stats.title {
+                            Some(title) => {
+                                let button = egui::Button::new(title).small();
+                                if ui.add(button).on_hover_text("Filter to title").clicked() {
+                                    title_clicked = Some(title.clone());
+                                }
+                            }
+                            None => {
+                                ui.label(RichText::new("All items").italics());
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(stats.count.to_string());
+                        });
+                        for ns in [
+                            Some(stats.busy_ns),
+                            stats.idle_ns,
+                            Some(stats.mean_ns),
+                            Some(stats.median_ns),
+                            Some(stats.p99_ns),
+                        ] {
+                            row.col(|ui| {
+                                if let Some(ns) = ns {
+                                    ui.label(Timestamp(ns).to_string());
+                                }
+                            });
+                        }
+                    });
+                });
         });
+
+        if let Some(column) = sort_by {
+            let descending = match state.sort {
+                Some((c, descending)) if c == column => !descending,
+                // Largest first, except for names
+                _ => !matches!(column, StatsColumn::Group | StatsColumn::Title),
+            };
+            state.sort = Some((column, descending));
+            sort_rows(&mut state.rows, column, descending);
+        }
+        if group_clicked.is_some() {
+            state.group_filter = group_clicked;
+        }
+        if title_clicked.is_some() {
+            state.title_filter = title_clicked;
+        }
     }
 
     fn select_interval(&mut self, ui: &mut egui::Ui, cx: &mut Context) {
@@ -1514,23 +2818,57 @@ impl Window {
         const WIDGET_PADDING: f32 = 8.0;
         ui.heading(format!("Profile {}: Controls", self.index));
         ui.add_space(WIDGET_PADDING);
-        self.node_selection(ui, cx);
-        ui.add_space(WIDGET_PADDING);
-        self.filter_by_kind(ui, cx);
+        self.level_filters(ui, cx);
         ui.add_space(WIDGET_PADDING);
         self.expand_collapse(ui, cx);
         ui.add_space(WIDGET_PADDING);
+        self.dependencies(ui, cx);
+        ui.add_space(WIDGET_PADDING);
+        self.critical_path_controls(ui, cx);
+        ui.add_space(WIDGET_PADDING);
+        self.statistics_controls(ui, cx);
+        ui.add_space(WIDGET_PADDING);
         self.select_interval(ui, cx);
+        if cx.debug {
+            ui.add_space(WIDGET_PADDING);
+            self.tile_cache_stats(ui, cx);
+        }
+    }
+
+    fn tile_cache_stats(&mut self, ui: &mut egui::Ui, cx: &Context) {
+        const MB: f64 = (1 << 20) as f64;
+        let stats = self.config.data_source.stats();
+        ui.subheading("Tile Cache", cx);
+        ui.label(format!("Hits: {}", stats.hits));
+        ui.label(format!("Misses: {}", stats.misses));
+        ui.label(format!(
+            "Size: {} tiles, {:.1} / {:.1} MB",
+            stats.tiles,
+            stats.bytes as f64 / MB,
+            stats.capacity as f64 / MB
+        ));
     }
 
     fn search(&mut self, cx: &mut Context) {
         // Invalidate cache if the search query changed.
-        self.config.search_state.ensure_valid_cache(cx);
+        let config = &mut self.config;
+        config
+            .search_state
+            .ensure_valid_cache(&config.field_schema, cx);
 
-        // If search query empty, skip search. (Note: do this after
+        // If search query empty or invalid, skip search. (Note: do this after
         // invalidating cache, otherwise we get leftover search results when
         // clearing the query.)
-        if self.config.search_state.query.is_empty() {
+        if self.config.search_state.query.is_empty()
+            || self.config.search_state.query_error.is_some()
+        {
+            return;
+        }
+
+        // Let the data source search, if it can, rather than downloading
+        // every meta tile.
+        if self.config.data_source.supports_search() {
+            self.remote_search(cx);
             return;
         }
 
@@ -1543,6 +2881,25 @@ impl Window {
         // Cache is now full and we can highlight/render the entries.
     }
 
+    fn remote_search(&mut self, cx: &Context) {
+        let entry_ids = if self.config.search_state.include_collapsed_entries {
+            None
+        } else {
+            let mut entry_ids = Vec::new();
+            self.panel.search_slot_ids(&self.config, &mut entry_ids);
+            Some(entry_ids)
+        };
+        let search_state = &mut self.config.search_state;
+        let request = search_state.request(cx.view_interval, entry_ids);
+        if search_state.remote_request.as_ref() == Some(&request) {
+            return;
+        }
+
+        self.config.data_source.fetch_search(&request);
+        search_state.remote_request = Some(request);
+        search_state.remote_pending = true;
+    }
+
     fn search_box(&mut self, ui: &mut egui::Ui, cx: &mut Context) {
         ui.horizontal(|ui| {
             // Hack: need to estimate the button width or else the text box
@@ -1557,8 +2914,14 @@ impl Window {
             let button_size = button_text.size() + 2.0 * button_padding;
 
             let query_size = ui.available_size().x - button_size.x - ui.spacing().item_spacing.x;
+            let hint_text = if self.config.search_state.search_mode == SearchMode::Query {
+                "title ~ \"gemm\" and duration > 5ms"
+            } else {
+                ""
+            };
             egui::TextEdit::singleline(&mut self.config.search_state.query)
                 .desired_width(query_size)
+                .hint_text(hint_text)
                 .show(ui);
             if ui.button(button_label).clicked() {
                 self.config.search_state.query.clear();
@@ -1567,13 +2930,26 @@ impl Window {
         ui.horizontal(|ui| {
             ui.label("Search field:");
             let schema = &self.config.field_schema;
-            let search_field = &mut self.config.search_state.search_field;
-            egui::ComboBox::from_id_source("Search field")
-                .selected_text(schema.get_name(*search_field).unwrap())
+            let search_state = &mut self.config.search_state;
+            // Queries name their own fields
+            let enabled = search_state.search_mode != SearchMode::Query;
+            let search_field = &mut search_state.search_field;
+            ui.add_enabled_ui(enabled, |ui| {
+                egui::ComboBox::from_id_source("Search field")
+                    .selected_text(schema.get_name(*search_field).unwrap())
+                    .show_ui(ui, |ui| {
+                        for field in schema.searchable() {
+                            let name = schema.get_name(*field).unwrap();
+                            ui.selectable_value(search_field, *field, name);
+                        }
+                    });
+            });
+            let search_mode = &mut self.config.search_state.search_mode;
+            egui::ComboBox::from_id_source("Search mode")
+                .selected_text(search_mode.name())
                 .show_ui(ui, |ui| {
-                    for field in schema.searchable() {
-                        let name = schema.get_name(*field).unwrap();
-                        ui.selectable_value(search_field, *field, name);
+                    for mode in SearchMode::ALL {
+                        ui.selectable_value(search_mode, mode, mode.name());
                     }
                 });
         });
@@ -1583,6 +2959,10 @@ impl Window {
         );
 
         self.search(cx);
+
+        if let Some(error) = &self.config.search_state.query_error {
+            ui.colored_label(Color32::RED, error);
+        }
     }
 
     fn search_results(&mut self, ui: &mut egui::Ui, cx: &mut Context) {
@@ -1591,7 +2971,15 @@ impl Window {
             return;
         }
 
+        if self.config.search_state.query_error.is_some() {
+            return;
+        }
+
         if self.config.search_state.result_set.is_empty() {
+            if self.config.search_state.remote_pending {
+                ui.label("Searching...");
+                return;
+            }
             ui.label("No results found. Expand search to include collapsed processors?");
 
             return;
@@ -1608,55 +2996,137 @@ impl Window {
             ui.label(format!("Found {} results.", num_results));
         }
 
+        egui::CollapsingHeader::new("Durations")
+            .default_open(true)
+            .show(ui, |ui| self.search_histogram(ui));
+
         self.config.search_state.build_entry_tree();
 
+        let mut clicked = None;
         ScrollArea::vertical()
             // Hack: estimate size of bottom UI.
             .max_height(ui.available_height() - 70.0)
             .auto_shrink([false; 2])
             .show(ui, |ui| {
-                let root_tree = &self.config.search_state.entry_tree;
-                for (level0_index, level0_subtree) in root_tree {
-                    let level0_slot = &mut self.panel.slots[*level0_index as usize];
-                    ui.collapsing(&level0_slot.long_name, |ui| {
-                        for (level1_index, level1_subtree) in level0_subtree {
-                            let level1_slot = &mut level0_slot.slots[*level1_index as usize];
-                            ui.collapsing(&level1_slot.long_name, |ui| {
-                                for level2_index in level1_subtree {
-                                    let level2_slot =
-                                        &mut level1_slot.slots[*level2_index as usize];
-                                    ui.collapsing(&level2_slot.long_name, |ui| {
-                                        let cache = &self.config.search_state.result_cache;
-                                        let cache = cache.get(&level2_slot.entry_id).unwrap();
-                                        for tile_cache in cache.values() {
-                                            for item in tile_cache.values() {
-                                                let button =
-                                                    egui::widgets::Button::new(&item.title).small();
-                                                if ui.add(button).clicked() {
-                                                    let interval = item
-                                                        .interval
-                                                        .grow(item.interval.duration_ns() / 20);
-                                                    ProfApp::zoom(cx, interval);
-                                                    self.config.scroll_to_item =
-                                                        Some(ItemLocator {
-                                                            entry_id: level2_slot.entry_id.clone(),
-                                                            irow: Some(item.irow),
-                                                        });
-                                                    self.config.scroll_to_item_uid =
-                                                        Some(item.item_uid);
-                                                    level2_slot.expanded = true;
-                                                    level1_slot.expanded = true;
-                                                    level0_slot.expanded = true;
-                                                }
-                                            }
-                                        }
-                                    });
-                                }
-                            });
+                Self::search_result_tree(ui, &self.panel, &self.config.search_state, &mut clicked);
+            });
+
+        if let Some((entry_id, item)) = clicked {
+            let interval = item.interval.grow(item.interval.duration_ns() / 20);
+            ProfApp::zoom(cx, interval);
+            self.config.scroll_to_item = Some(ItemLocator {
+                entry_id: entry_id.clone(),
+                irow: Some(item.irow),
+            });
+            self.config.scroll_to_item_uid = Some(item.item_uid);
+            self.expand_slot(&entry_id);
+        }
+    }
+
+    // Drag across bins to highlight only the results with those durations
+    fn search_histogram(&mut self, ui: &mut egui::Ui) {
+        const HEIGHT: f32 = 60.0;
+
+        let state = &mut self.config.search_state;
+        let histogram = state.histogram();
+        let bins = histogram.counts.len();
+        if bins == 0 {
+            return;
+        }
+
+        let size = Vec2::new(ui.available_width(), HEIGHT);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let bin_width = rect.width() / bins as f32;
+        let bin_at = |pos: Pos2| {
+            let index = ((pos.x - rect.min.x) / bin_width).floor() as i32;
+            histogram.first_bin + index.clamp(0, bins as i32 - 1)
+        };
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            let bin = bin_at(pos);
+            if response.drag_started() {
+                state.brush_anchor = Some(bin);
+            }
+            if response.clicked() {
+                state.duration_brush = Some((bin, bin));
+            } else if let (true, Some(anchor)) = (response.dragged(), state.brush_anchor) {
+                state.duration_brush = Some((anchor.min(bin), anchor.max(bin)));
+            }
+        }
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        let max_count = *histogram.counts.iter().max().unwrap();
+        for (index, count) in histogram.counts.iter().enumerate() {
+            let bin = histogram.first_bin + index as i32;
+            let brushed = state
+                .duration_brush
+                .map_or(true, |(first, last)| (first..=last).contains(&bin));
+            let color = if brushed {
+                Color32::RED
+            } else {
+                Color32::RED.gamma_multiply(0.2)
+            };
+            let height = rect.height() * (*count as f32 / max_count as f32);
+            let min = Pos2::new(rect.min.x + index as f32 * bin_width, rect.max.y - height);
+            let max = Pos2::new(min.x + bin_width, rect.max.y);
+            let bar = Rect::from_min_max(min, max).shrink2(Vec2::new(0.5, 0.0));
+            painter.rect_filled(bar, 0.0, color);
+        }
+
+        let range = |first: i32, last: i32| {
+            format!(
+                "{} to {}",
+                Timestamp(Histogram::bin_start(first)),
+                Timestamp(Histogram::bin_start(last + 1))
+            )
+        };
+        if let Some(pos) = response.hover_pos() {
+            let bin = bin_at(pos);
+            let count = histogram.counts[(bin - histogram.first_bin) as usize];
+            response.on_hover_text(format!("{}: {} results", range(bin, bin), count));
+        }
+
+        let last_bin = histogram.first_bin + bins as i32 - 1;
+        ui.label(range(histogram.first_bin, last_bin));
+        if let Some((first, last)) = state.duration_brush {
+            ui.horizontal(|ui| {
+                ui.label(format!("Highlighting {}", range(first, last)));
+                if ui.button("Clear").clicked() {
+                    state.duration_brush = None;
+                }
+            });
+        }
+    }
+
+    fn search_result_tree(
+        ui: &mut egui::Ui,
+        panel: &Panel,
+        search_state: &SearchState,
+        clicked: &mut Option<(EntryID, SearchCacheItem)>,
+    ) {
+        for slot in &panel.slots {
+            if !search_state.entry_tree.contains(slot.entry_id()) {
+                continue;
+            }
+
+            ui.collapsing(slot.long_name(), |ui| match slot {
+                PanelChild::Panel(panel) => {
+                    Self::search_result_tree(ui, panel, search_state, clicked);
+                }
+                PanelChild::Slot(slot) => {
+                    let cache = search_state.result_cache.get(&slot.entry_id).unwrap();
+                    for tile_cache in cache.values() {
+                        for item in tile_cache.values() {
+                            let button = egui::widgets::Button::new(&item.title).small();
+                            if ui.add(button).clicked() {
+                                *clicked = Some((slot.entry_id.clone(), item.clone()));
+                            }
                         }
-                    });
+                    }
                 }
             });
+        }
     }
 
     fn search_controls(&mut self, ui: &mut egui::Ui, cx: &mut Context) {
@@ -1716,14 +3186,12 @@ impl ProfApp {
             return;
         }
 
+        cx.zoom_state.pan_time = None;
         cx.view_interval = interval;
         cx.zoom_state.levels.truncate(cx.zoom_state.index + 1);
         cx.zoom_state.levels.push(cx.view_interval);
         cx.zoom_state.index = cx.zoom_state.levels.len() - 1;
-        cx.interval_state.start_buffer = cx.view_interval.start.to_string();
-        cx.interval_state.stop_buffer = cx.view_interval.stop.to_string();
-        cx.interval_state.start_error = None;
-        cx.interval_state.stop_error = None;
+        cx.interval_state.reset(cx.view_interval);
     }
 
     fn undo_zoom(cx: &mut Context) {
@@ -1731,11 +3199,9 @@ impl ProfApp {
             return;
         }
         cx.zoom_state.index -= 1;
+        cx.zoom_state.pan_time = None;
         cx.view_interval = cx.zoom_state.levels[cx.zoom_state.index];
-        cx.interval_state.start_buffer = cx.view_interval.start.to_string();
-        cx.interval_state.stop_buffer = cx.view_interval.stop.to_string();
-        cx.interval_state.start_error = None;
-        cx.interval_state.stop_error = None;
+        cx.interval_state.reset(cx.view_interval);
     }
 
     fn redo_zoom(cx: &mut Context) {
@@ -1743,11 +3209,65 @@ impl ProfApp {
             return;
         }
         cx.zoom_state.index += 1;
+        cx.zoom_state.pan_time = None;
         cx.view_interval = cx.zoom_state.levels[cx.zoom_state.index];
-        cx.interval_state.start_buffer = cx.view_interval.start.to_string();
-        cx.interval_state.stop_buffer = cx.view_interval.stop.to_string();
-        cx.interval_state.start_error = None;
-        cx.interval_state.stop_error = None;
+        cx.interval_state.reset(cx.view_interval);
+    }
+
+    // Pans less than this far apart (in seconds) are one gesture, e.g.,
+    // scrolling or holding down an arrow key
+    const PAN_GESTURE_GAP: f64 = 1.0;
+
+    fn pan(cx: &mut Context, duration_ns: i64, time: f64) {
+        // The view stays inside the profile
+        let interval = cx
+            .view_interval
+            .translate_within(duration_ns, cx.total_interval);
+        if interval == cx.view_interval {
+            return;
+        }
+
+        let in_gesture =
+            (cx.zoom_state.pan_time).map_or(false, |t| time - t < Self::PAN_GESTURE_GAP);
+        if in_gesture {
+            cx.view_interval = interval;
+            cx.zoom_state.levels[cx.zoom_state.index] = interval;
+            cx.interval_state.reset(cx.view_interval);
+        } else {
+            Self::zoom(cx, interval);
+        }
+        cx.zoom_state.pan_time = Some(time);
+    }
+
+    fn end_pan_gesture(cx: &mut Context) {
+        cx.zoom_state.pan_time = None;
+    }
+
+    // After a live profile grows, keep a view that showed the end of the
+    // profile there: a view of the whole profile grows with it, anything
+    // else slides along. Updates in place, like panning.
+    fn follow_tail(cx: &mut Context, old_total: Interval) {
+        if cx.view_interval.stop != old_total.stop || cx.total_interval.stop == old_total.stop {
+            return;
+        }
+        let interval = if cx.view_interval.start == old_total.start {
+            cx.total_interval
+        } else {
+            let delta = cx.total_interval.stop.0 - old_total.stop.0;
+            cx.view_interval.translate(delta)
+        };
+
+        cx.view_interval = interval;
+        if let Some(level) = cx.zoom_state.levels.get_mut(cx.zoom_state.index) {
+            *level = interval;
+        }
+        cx.interval_state.reset(cx.view_interval);
+    }
+
+    // Pan by a fraction of the view interval (negative to pan left)
+    fn pan_by_fraction(cx: &mut Context, fraction: f32, time: f64) {
+        let duration = cx.view_interval.duration_ns() as f64 * fraction as f64;
+        Self::pan(cx, duration.round() as i64, time);
     }
 
     fn zoom_in(cx: &mut Context) {
@@ -1764,7 +3284,7 @@ impl ProfApp {
     }
 
     fn multiply_scale_factor(cx: &mut Context, factor: f32) {
-        cx.scale_factor = (cx.scale_factor * factor).min(4.0).max(0.25);
+        cx.scale_factor = (cx.scale_factor * factor).clamp(0.25, 4.0);
     }
 
     fn reset_scale_factor(cx: &mut Context) {
@@ -1777,18 +3297,24 @@ impl ProfApp {
             return;
         }
 
+        // Fraction of the view interval to move per key press
+        const PAN_FRACTION: f32 = 0.1;
+
         enum Actions {
             ZoomIn,
             ZoomOut,
             UndoZoom,
             RedoZoom,
             ResetZoom,
+            PanLeft,
+            PanRight,
             ExpandVertical,
             ShrinkVertical,
             ResetVertical,
             ToggleControls,
             NoAction,
         }
+        let time = ctx.input(|i| i.time);
         let action = ctx.input(|i| {
             if i.modifiers.ctrl {
                 if i.modifiers.alt {
@@ -1814,6 +3340,10 @@ impl ProfApp {
                 } else {
                     Actions::NoAction
                 }
+            } else if i.key_pressed(egui::Key::ArrowLeft) {
+                Actions::PanLeft
+            } else if i.key_pressed(egui::Key::ArrowRight) {
+                Actions::PanRight
             } else if i.key_pressed(egui::Key::H) {
                 Actions::ToggleControls
             } else {
@@ -1826,6 +3356,8 @@ impl ProfApp {
             Actions::UndoZoom => ProfApp::undo_zoom(cx),
             Actions::RedoZoom => ProfApp::redo_zoom(cx),
             Actions::ResetZoom => ProfApp::zoom(cx, cx.total_interval),
+            Actions::PanLeft => ProfApp::pan_by_fraction(cx, -PAN_FRACTION, time),
+            Actions::PanRight => ProfApp::pan_by_fraction(cx, PAN_FRACTION, time),
             Actions::ExpandVertical => ProfApp::multiply_scale_factor(cx, 2.0),
             Actions::ShrinkVertical => ProfApp::multiply_scale_factor(cx, 0.5),
             Actions::ResetVertical => ProfApp::reset_scale_factor(cx),
@@ -1853,10 +3385,34 @@ impl ProfApp {
 
         let response = ui.allocate_rect(rect, egui::Sense::drag());
 
+        // Handle pan detection: shift + drag, middle drag, or horizontal
+        // scroll. (A drag that started as a zoom stays a zoom.)
+        let shift = ui.input(|i| i.modifiers.shift);
+        let is_pan_drag = response.dragged_by(egui::PointerButton::Middle)
+            || (response.dragged_by(egui::PointerButton::Primary)
+                && shift
+                && cx.drag_origin.is_none());
+        let mut pan_delta = 0.0;
+        if is_pan_drag {
+            pan_delta += response.drag_delta().x;
+        }
+        if response.hover_pos().is_some() {
+            pan_delta += ui.input(|i| i.scroll_delta.x);
+        }
+        if pan_delta != 0.0 {
+            // Moving the content right means moving the view left
+            let time = ui.input(|i| i.time);
+            ProfApp::pan_by_fraction(cx, -pan_delta / rect.width(), time);
+        }
+        // Letting go ends a drag right away, the next one is a new gesture
+        if response.drag_released() {
+            ProfApp::end_pan_gesture(cx);
+        }
+
         // Handle drag detection
         let mut drag_interval = None;
 
-        let is_active_drag = response.dragged_by(egui::PointerButton::Primary);
+        let is_active_drag = response.dragged_by(egui::PointerButton::Primary) && !is_pan_drag;
         if is_active_drag && response.drag_started() {
             // On the beginning of a drag, save our position so we can
             // calculate the delta
@@ -1971,6 +3527,12 @@ impl ProfApp {
                 show_row("Undo Zoom", "Ctrl + Left Arrow");
                 show_row("Redo Zoom", "Ctrl + Right Arrow");
                 show_row("Reset Zoom", "Ctrl + 0");
+                show_row(
+                    "Pan",
+                    "Shift + Click and Drag, Middle Drag, Horizontal Scroll",
+                );
+                show_row("Pan Left", "Left Arrow");
+                show_row("Pan Right", "Right Arrow");
                 show_row("Expand Vertical Spacing", "Ctrl + Alt + Plus/Equals");
                 show_row("Shrink Vertical Spacing", "Ctrl + Alt + Minus");
                 show_row("Reset Vertical Spacing", "Ctrl + Alt + 0");
@@ -1993,6 +3555,7 @@ impl ProfApp {
         match field {
             Field::I64(value) => vec![(format!("{value}"), None)],
             Field::U64(value) => vec![(format!("{value}"), None)],
+            Field::F64(value) => vec![(format!("{value}"), None)],
             Field::String(value) => vec![(value.to_string(), None)],
             Field::Interval(value) => vec![(format!("{value}"), None)],
             Field::ItemLink(ItemLink { title, .. }) => {
@@ -2033,6 +3596,7 @@ impl ProfApp {
         match field {
             Field::I64(value) => label(ui, &format!("{value}")),
             Field::U64(value) => label(ui, &format!("{value}")),
+            Field::F64(value) => label(ui, &format!("{value}")),
             Field::String(value) => label(ui, value),
             Field::Interval(value) => label(ui, &format!("{value}")),
             Field::ItemLink(ItemLink {
@@ -2135,6 +3699,7 @@ impl eframe::App for ProfApp {
         let Self {
             pending_data_sources,
             windows,
+            load_errors,
             cx,
             #[cfg(not(target_arch = "wasm32"))]
             last_update,
@@ -2144,51 +3709,136 @@ impl eframe::App for ProfApp {
         if let Some(mut source) = pending_data_sources.pop_front() {
             // We made one request, so we know there is always zero or one
             // elements in this list.
-            if let Some(info) = source.get_infos().pop() {
-                let window = Window::new(source, info, windows.len() as u64);
-                if windows.is_empty() {
-                    cx.total_interval = window.config.interval;
-                } else {
-                    cx.total_interval = cx.total_interval.union(window.config.interval);
+            match source.get_infos().pop() {
+                Some(Ok(info)) => {
+                    let mut window = Window::new(source, info, windows.len() as u64);
+                    if windows.is_empty() {
+                        cx.total_interval = window.config.interval;
+                    } else {
+                        cx.total_interval = cx.total_interval.union(window.config.interval);
+                    }
+                    ProfApp::zoom(cx, cx.total_interval);
+                    if window.config.data_source.supports_info_updates() {
+                        window.config.data_source.fetch_info_update(0);
+                    }
+                    windows.push(window);
+                }
+                Some(Err(error)) => {
+                    log::error!("unable to load profile: {}", error);
+                    load_errors.push(error);
+                }
+                None => {
+                    pending_data_sources.push_front(source);
                 }
-                ProfApp::zoom(cx, cx.total_interval);
-                windows.push(window);
-            } else {
-                pending_data_sources.push_front(source);
             }
         }
 
         for window in windows.iter_mut() {
             for tile in window.config.data_source.get_summary_tiles() {
-                if let Some(entry) = window.find_summary(&tile.entry_id) {
-                    // If the entry doesn't exist, we already zoomed away and
-                    // are no longer interested in this tile.
-                    entry
-                        .tiles
-                        .entry(tile.tile_id)
-                        .and_modify(|t| *t = Some(tile.data));
+                let entry = window.find_summary(&tile.entry_id);
+                match tile.result {
+                    Ok(data) => {
+                        if let Some(entry) = entry {
+                            // If the entry doesn't exist, we already zoomed away and
+                            // are no longer interested in this tile.
+                            entry
+                                .tiles
+                                .entry(tile.tile_id)
+                                .and_modify(|t| *t = Some(data.data));
+                        }
+                    }
+                    Err(error) => {
+                        if let Some(entry) = entry {
+                            entry.mark_failed_tile(tile.tile_id);
+                        }
+                        window.config.report_error(error);
+                    }
                 }
             }
 
             for tile in window.config.data_source.get_slot_tiles() {
-                if let Some(entry) = window.find_slot(&tile.entry_id) {
-                    // If the entry doesn't exist, we already zoomed away and
-                    // are no longer interested in this tile.
-                    entry
-                        .tiles
-                        .entry(tile.tile_id)
-                        .and_modify(|t| *t = Some(tile.data));
+                let entry = window.find_slot(&tile.entry_id);
+                match tile.result {
+                    Ok(data) => {
+                        if let Some(entry) = entry {
+                            // If the entry doesn't exist, we already zoomed away and
+                            // are no longer interested in this tile.
+                            entry
+                                .tiles
+                                .entry(tile.tile_id)
+                                .and_modify(|t| *t = Some(data.data));
+                        }
+                    }
+                    Err(error) => {
+                        if let Some(entry) = entry {
+                            entry.mark_failed_tile(tile.tile_id);
+                        }
+                        window.config.report_error(error);
+                    }
                 }
             }
 
             for tile in window.config.data_source.get_slot_meta_tiles() {
-                if let Some(entry) = window.find_slot(&tile.entry_id) {
-                    // If the entry doesn't exist, we already zoomed away and
-                    // are no longer interested in this tile.
-                    entry
-                        .tile_metas
-                        .entry(tile.tile_id)
-                        .and_modify(|t| *t = Some(tile.data));
+                // Full tiles are only ever requested for the critical path
+                // and statistics, never for display
+                if tile.full {
+                    window.config.critical_path.receive(&tile);
+                    window.config.statistics.receive(&tile);
+                    if let Err(error) = tile.result {
+                        window.config.report_error(error);
+                    }
+                    continue;
+                }
+                let entry = window.find_slot(&tile.entry_id);
+                match tile.result {
+                    Ok(data) => {
+                        if let Some(entry) = entry {
+                            // If the entry doesn't exist, we already zoomed away and
+                            // are no longer interested in this tile.
+                            entry
+                                .tile_metas
+                                .entry(tile.tile_id)
+                                .and_modify(|t| *t = Some(data.data));
+                        }
+                    }
+                    Err(error) => {
+                        if let Some(entry) = entry {
+                            entry.mark_failed_meta_tile(tile.tile_id);
+                        }
+                        window.config.report_error(error);
+                    }
+                }
+            }
+
+            for response in window.config.data_source.get_search_results() {
+                match response {
+                    Ok(response) => window.config.search_state.receive(response),
+                    // The data source can't search after all, so the next
+                    // search will fall back to scanning tiles
+                    Err(_) if !window.config.data_source.supports_search() => {
+                        window.config.search_state.clear();
+                    }
+                    Err(error) => window.config.report_error(error),
+                }
+            }
+
+            for update in window.config.data_source.get_info_updates() {
+                match update {
+                    Ok(update) => {
+                        if let Some(update) = update {
+                            let old_total = cx.total_interval;
+                            window.config.info_revision = update.revision;
+                            window.update_info(update.info);
+                            cx.total_interval = cx.total_interval.union(window.config.interval);
+                            ProfApp::follow_tail(cx, old_total);
+                        }
+                        let revision = window.config.info_revision;
+                        window.config.data_source.fetch_info_update(revision);
+                    }
+                    // Not a live profile
+                    Err(_) if !window.config.data_source.supports_info_updates() => {}
+                    // Stop following the profile
+                    Err(error) => window.config.report_error(error),
                 }
             }
         }
@@ -2282,6 +3932,19 @@ impl eframe::App for ProfApp {
             });
         });
 
+        if windows.iter().any(|w| w.config.critical_path.active) {
+            egui::SidePanel::right("critical_path_panel").show(ctx, |ui| {
+                for window in windows.iter_mut() {
+                    if window.config.critical_path.active {
+                        egui::Frame::group(ui.style()).show(ui, |ui| {
+                            ui.set_width(ui.available_width());
+                            window.critical_path_results(ui, cx);
+                        });
+                    }
+                }
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // Use body font to figure out how tall to draw rectangles.
             let font_id = TextStyle::Body.resolve(ui.style());
@@ -2289,6 +3952,12 @@ impl eframe::App for ProfApp {
             // Just set this on every frame for now
             cx.row_height = row_height * cx.scale_factor;
 
+            for error in load_errors.iter() {
+                ui.label(
+                    RichText::new(format!("Unable to load profile: {}", error)).color(Color32::RED),
+                );
+            }
+
             let mut remaining = windows.len();
             // Only wrap in a frame if more than one profile
             if remaining > 1 {
@@ -2316,6 +3985,15 @@ impl eframe::App for ProfApp {
             .resizable(false)
             .show(ctx, Self::display_bindings);
 
+        for window in windows.iter_mut() {
+            let mut active = window.config.statistics.active;
+            egui::Window::new(format!("Profile {}: Statistics", window.index))
+                .open(&mut active)
+                .resizable(true)
+                .show(ctx, |ui| window.statistics_results(ui, cx));
+            window.config.statistics.active &= active;
+        }
+
         for window in windows.iter_mut() {
             let mut zoom_target = None;
             window
@@ -2360,6 +4038,12 @@ impl eframe::App for ProfApp {
                 .any(|w| w.config.data_source.outstanding_requests() > 0)
         {
             ctx.request_repaint_after(Duration::from_millis(50));
+        } else if windows
+            .iter()
+            .any(|w| w.config.data_source.supports_info_updates())
+        {
+            // Check on live profiles now and then
+            ctx.request_repaint_after(Duration::from_millis(250));
         }
     }
 }
@@ -2378,6 +4062,7 @@ trait UiExtra {
         rect: &Rect,
         add_contents: impl FnOnce(&mut egui::Ui),
     );
+    #[allow(dead_code)]
     fn show_tooltip_at(
         &mut self,
         id_source: impl core::hash::Hash,
@@ -1,14 +1,66 @@
-use std::fs::{create_dir, remove_dir_all, File};
-use std::io;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{create_dir, create_dir_all, remove_dir_all, remove_file, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use serde::Serialize;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::data::{DataSourceInfo, EntryID, EntryIDSlug, EntryIndex, EntryInfo, TileID, TileSet};
+use crate::data::{
+    DataSource, DataSourceError, DataSourceInfo, EntryID, EntryIDSlug, EntryIndex, EntryInfo, Item,
+    ItemMeta, ItemUID, SearchRequest, SearchResult, SlotMetaTile, SlotMetaTileData, SlotTile,
+    SlotTileData, SummaryTile, SummaryTileData, TileID, TileIDSlug, TileSet, UtilPoint,
+};
 use crate::deferred_data::{CountingDeferredDataSource, DeferredDataSource};
-use crate::http::schema::TileRequestRef;
+use crate::http::schema::{TileKind, TileRequestRef};
+use crate::search::{
+    indexed_search, scan_slot_meta_tiles, SearchIndexBuilder, SearchIndexInfo, SearchIndexShard,
+};
 use crate::timestamp::{Interval, Timestamp};
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    // One file per tile, in a directory tree that can be served statically
+    Directory,
+    // Everything in a single file (see ArchiveFile)
+    SingleFile,
+}
+
+// Tile counts are per level, and count slot and slot meta tiles separately.
+// When retiling, all levels are written together and reported as the last.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArchiveProgress {
+    pub level: u32,
+    pub levels: u32,
+    pub tiles_done: u64,
+    pub tiles_total: u64,
+    // Tiles found already written (and valid) when resuming
+    pub tiles_skipped: u64,
+}
+
+// Progress is reported through the log by default, so that library users
+// don't get output they didn't ask for
+fn log_progress(progress: ArchiveProgress) {
+    if progress.tiles_done == progress.tiles_skipped {
+        log::info!(
+            "Writing level {} with {} tiles ({} already written)",
+            progress.level,
+            progress.tiles_total,
+            progress.tiles_skipped
+        );
+    }
+}
+
+// Written last, so that a directory without one is known to be incomplete
+#[derive(Debug, Deserialize, Serialize)]
+struct ArchiveManifest {
+    levels: u32,
+    tiles: u64,
+}
+
 pub struct DataSourceArchiveWriter<T: DeferredDataSource> {
     data_source: CountingDeferredDataSource<T>,
     levels: u32,
@@ -16,12 +68,22 @@ pub struct DataSourceArchiveWriter<T: DeferredDataSource> {
     path: PathBuf,
     force: bool,
     zstd_compression: i32,
+    format: ArchiveFormat,
+    resume: bool,
+    retile: bool,
+    search_index: bool,
+    progress: Box<dyn FnMut(ArchiveProgress) + Send>,
+    tiles_written: u64,
+    // Collects full resolution slot meta tiles while they're written
+    index_builder: Option<SearchIndexBuilder>,
+    index_tiles: bool,
+    write_error: Arc<Mutex<Option<io::Error>>>,
 }
 
 fn create_unique_dir<P: AsRef<Path>>(path: P, force: bool) -> io::Result<PathBuf> {
     let mut path = path.as_ref().to_owned();
     if force {
-        println!("Removing previous contents of {:?}", &path);
+        log::info!("Removing previous contents of {:?}", &path);
         let _ = remove_dir_all(&path); // ignore failure, we'll catch it on create
         create_dir(&path)?;
     } else if create_dir(&path).is_err() {
@@ -45,27 +107,468 @@ fn create_unique_dir<P: AsRef<Path>>(path: P, force: bool) -> io::Result<PathBuf
     Ok(path)
 }
 
-fn write_data<T>(path: PathBuf, data: T, zstd_compression: i32) -> io::Result<()>
+fn create_unique_file<P: AsRef<Path>>(path: P, force: bool) -> io::Result<(PathBuf, File)> {
+    let path = path.as_ref().to_owned();
+    if force {
+        log::info!("Removing previous contents of {:?}", &path);
+        let f = File::create(&path)?;
+        return Ok((path, f));
+    }
+    let mut i = 0;
+    let retry_limit = 100;
+    loop {
+        let p = if i == 0 {
+            path.clone()
+        } else {
+            let mut f = path.file_name().unwrap().to_owned();
+            f.push(format!(".{}", i));
+            path.with_file_name(f)
+        };
+        let r = OpenOptions::new().write(true).create_new(true).open(&p);
+        match r {
+            Ok(f) => return Ok((p, f)),
+            // tried too many times, assume this is a permanent failure
+            Err(e) if i >= retry_limit => return Err(e),
+            Err(_) => {}
+        }
+        i += 1;
+    }
+}
+
+fn encode_data<T>(data: T, zstd_compression: i32) -> io::Result<Vec<u8>>
 where
     T: Serialize,
 {
-    let mut f = zstd::Encoder::new(File::create(path)?, zstd_compression)?;
+    let mut f = zstd::Encoder::new(Vec::new(), zstd_compression)?;
     ciborium::into_writer(&data, &mut f).expect("ciborium encoding failed");
-    f.finish()?;
-    Ok(())
+    f.finish()
+}
+
+fn decode_data<T>(f: impl Read) -> io::Result<T>
+where
+    T: DeserializeOwned,
+{
+    let f = zstd::Decoder::new(f)?;
+    ciborium::from_reader(f).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
-fn spawn_write<T>(path: PathBuf, data: T, zstd_compression: i32, scope: &rayon::Scope<'_>)
+fn read_data<T>(path: &Path) -> io::Result<T>
 where
+    T: DeserializeOwned,
+{
+    decode_data(File::open(path)?)
+}
+
+fn is_valid_tile(path: &Path, kind: TileKind) -> bool {
+    match kind {
+        TileKind::Summary => read_data::<SummaryTile>(path).is_ok(),
+        TileKind::Slot => read_data::<SlotTile>(path).is_ok(),
+        TileKind::SlotMeta => read_data::<SlotMetaTile>(path).is_ok(),
+    }
+}
+
+// Single-file archive layout:
+//
+//   header: magic (8 bytes), version, index offset, index length (u64 LE)
+//   blobs:  zstd-compressed CBOR, exactly as they would be stored on disk
+//           in the directory format (or sent over HTTP)
+//   index:  zstd-compressed CBOR ArchiveIndex
+//
+// The magic and version are written up front, but the index is written
+// last, so an unfinished file is detected by a zero index offset in the
+// header.
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"LGPRFARC";
+const ARCHIVE_VERSION: u64 = 1;
+const ARCHIVE_HEADER_SIZE: u64 = 32;
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+struct BlobRef {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ArchiveIndex {
+    info: Option<BlobRef>,
+    tiles: Vec<(TileKind, EntryID, TileID, BlobRef)>,
+    // Optional, so absent in archives written without a search index
+    #[serde(default)]
+    search_index: Option<BlobRef>,
+    #[serde(default)]
+    search_shards: BTreeMap<usize, BlobRef>,
+}
+
+enum BlobKey {
+    Info,
+    Tile(TileKind, EntryID, TileID),
+    SearchIndexInfo,
+    SearchIndexShard(usize),
+}
+
+// Directory (in directory archives) with the search index: info, plus one
+// file per shard named by its position
+const SEARCH_INDEX_DIR: &str = "search_index";
+
+struct ArchiveFileWriter {
+    f: BufWriter<File>,
+    offset: u64,
+    index: ArchiveIndex,
+}
+
+impl ArchiveFileWriter {
+    fn new(f: File) -> io::Result<Self> {
+        let mut f = BufWriter::new(f);
+        f.write_all(ARCHIVE_MAGIC)?;
+        f.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+        // Index offset and length, filled in by finish
+        f.write_all(&[0; 16])?;
+        Ok(Self {
+            f,
+            offset: ARCHIVE_HEADER_SIZE,
+            index: ArchiveIndex::default(),
+        })
+    }
+
+    fn append(&mut self, key: BlobKey, data: &[u8]) -> io::Result<()> {
+        self.f.write_all(data)?;
+        let blob = BlobRef {
+            offset: self.offset,
+            len: data.len() as u64,
+        };
+        self.offset += blob.len;
+        match key {
+            BlobKey::Info => self.index.info = Some(blob),
+            BlobKey::Tile(kind, entry_id, tile_id) => {
+                self.index.tiles.push((kind, entry_id, tile_id, blob))
+            }
+            BlobKey::SearchIndexInfo => self.index.search_index = Some(blob),
+            BlobKey::SearchIndexShard(i) => {
+                self.index.search_shards.insert(i, blob);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, zstd_compression: i32) -> io::Result<()> {
+        let index = encode_data(&self.index, zstd_compression)?;
+        self.f.write_all(&index)?;
+
+        let mut f = self.f.into_inner().map_err(|e| e.into_error())?;
+        f.seek(SeekFrom::Start(16))?;
+        f.write_all(&self.offset.to_le_bytes())?;
+        f.write_all(&(index.len() as u64).to_le_bytes())?;
+        f.sync_all()
+    }
+}
+
+enum ArchiveSink {
+    Directory(PathBuf),
+    File(Mutex<ArchiveFileWriter>),
+}
+
+impl ArchiveSink {
+    fn write<T>(&self, key: BlobKey, data: T, zstd_compression: i32) -> io::Result<()>
+    where
+        T: Serialize,
+    {
+        let data = encode_data(data, zstd_compression)?;
+        match self {
+            ArchiveSink::Directory(path) => {
+                let path = match key {
+                    BlobKey::Info => path.join("info"),
+                    BlobKey::Tile(kind, entry_id, tile_id) => {
+                        let req = TileRequestRef {
+                            entry_id: &entry_id,
+                            tile_id,
+                        };
+                        path.join(kind.name()).join(req.to_slug())
+                    }
+                    BlobKey::SearchIndexInfo => path.join(SEARCH_INDEX_DIR).join("info"),
+                    BlobKey::SearchIndexShard(i) => path.join(SEARCH_INDEX_DIR).join(i.to_string()),
+                };
+                File::create(path)?.write_all(&data)
+            }
+            ArchiveSink::File(f) => f.lock().unwrap().append(key, &data),
+        }
+    }
+}
+
+impl ArchiveSink {
+    fn missing_tiles(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_ids: &[TileID],
+    ) -> Vec<TileID> {
+        match self {
+            ArchiveSink::Directory(path) => tile_ids
+                .par_iter()
+                .filter(|tile_id| {
+                    let req = TileRequestRef {
+                        entry_id,
+                        tile_id: **tile_id,
+                    };
+                    !is_valid_tile(&path.join(kind.name()).join(req.to_slug()), kind)
+                })
+                .copied()
+                .collect(),
+            ArchiveSink::File(_) => tile_ids.to_vec(),
+        }
+    }
+
+    // Only tiles reported present by missing_tiles can be read back
+    fn read_tile<T>(&self, kind: TileKind, entry_id: &EntryID, tile_id: TileID) -> io::Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            ArchiveSink::Directory(path) => {
+                let req = TileRequestRef { entry_id, tile_id };
+                read_data(&path.join(kind.name()).join(req.to_slug()))
+            }
+            ArchiveSink::File(_) => unreachable!(),
+        }
+    }
+}
+
+fn spawn_write<T>(
+    sink: Arc<ArchiveSink>,
+    key: BlobKey,
+    data: T,
+    zstd_compression: i32,
+    write_error: Arc<Mutex<Option<io::Error>>>,
+    scope: &rayon::Scope<'_>,
+) where
     T: Serialize + Send + Sync + 'static,
 {
     scope.spawn(move |_| {
-        // FIXME (Elliott): is there a better way to handle I/O failure?
-        write_data(path, data, zstd_compression).unwrap();
+        // Only the first failure is kept, it is reported once the writer
+        // gets back to the main thread
+        if let Err(e) = sink.write(key, data, zstd_compression) {
+            write_error.lock().unwrap().get_or_insert(e);
+        }
     });
 }
 
-fn walk_entry_list(info: &EntryInfo) -> Vec<EntryID> {
+// Coarse levels are downsampled to about this many points (or items) per
+// tile, like dynamic sources do for tiles that aren't requested in full
+const RETILE_RESOLUTION: i64 = 1000;
+
+// Items shorter than this are merged when the tile isn't requested in full
+pub(crate) fn merge_threshold(tile_id: TileID, full: bool) -> Option<i64> {
+    if full {
+        None
+    } else {
+        Some(tile_id.0.duration_ns() / RETILE_RESOLUTION)
+    }
+}
+
+// Items merged by downsampling get a UID with this bit set, so that they
+// can't be confused with (or selected as) any of the items they replace
+const MERGED_ITEM_UID: u64 = 1 << 63;
+
+// Cuts one summary tile out of source tiles, which must be added in time
+// order
+struct SummaryRetiler {
+    tile_id: TileID,
+    bucket: i64,
+    utilization: Vec<UtilPoint>,
+    // Number of points averaged into the last one
+    count: usize,
+}
+
+impl SummaryRetiler {
+    fn new(tile_id: TileID, full: bool) -> Self {
+        let bucket = if full {
+            0
+        } else {
+            tile_id.0.duration_ns() / RETILE_RESOLUTION
+        };
+        Self {
+            tile_id,
+            bucket,
+            utilization: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, source: &SummaryTile) {
+        let tile = self.tile_id.0;
+        let source_id = source.tile_id.0;
+        if source_id.start > tile.stop || source_id.stop < tile.start {
+            return;
+        }
+        for point in &source.data.utilization {
+            if point.time < tile.start || point.time > tile.stop {
+                continue;
+            }
+            match self.utilization.last_mut() {
+                Some(last) if point.time <= last.time => {}
+                Some(last) if point.time.0 - last.time.0 < self.bucket => {
+                    self.count += 1;
+                    last.util += (point.util - last.util) / self.count as f32;
+                }
+                _ => {
+                    self.utilization.push(*point);
+                    self.count = 1;
+                }
+            }
+        }
+    }
+
+    fn finish(self, entry_id: &EntryID) -> SummaryTile {
+        SummaryTile {
+            entry_id: entry_id.clone(),
+            tile_id: self.tile_id,
+            data: SummaryTileData {
+                utilization: self.utilization,
+            },
+        }
+    }
+}
+
+// Cuts one slot (and slot meta) tile out of source tiles, which must be
+// added in time order. Items that were sliced across source tile boundaries
+// are stitched back together.
+struct SlotRetiler {
+    tile_id: TileID,
+    full: bool,
+    // Items shorter than this are merged, if not full
+    threshold: Option<i64>,
+    rows: Vec<RowRetiler>,
+}
+
+#[derive(Default)]
+pub(crate) struct RowRetiler {
+    items: Vec<Item>,
+    metas: Vec<ItemMeta>,
+    // The last item may still be stitched to the next source tile, so it
+    // isn't merged until we've seen the next item
+    pending: Option<(Item, ItemMeta)>,
+    // Number of small items merged into the last one
+    count: usize,
+}
+
+impl SlotRetiler {
+    fn new(tile_id: TileID, full: bool) -> Self {
+        Self {
+            tile_id,
+            full,
+            threshold: merge_threshold(tile_id, full),
+            rows: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, slot: &SlotTile, meta: &SlotMetaTile) {
+        let tile = self.tile_id.0;
+        if !tile.overlaps(slot.tile_id.0) {
+            return;
+        }
+        if self.rows.len() < slot.data.items.len() {
+            self.rows
+                .resize_with(slot.data.items.len(), RowRetiler::default);
+        }
+        for (row, (row_items, row_metas)) in self
+            .rows
+            .iter_mut()
+            .zip(slot.data.items.iter().zip(&meta.data.items))
+        {
+            for (item, item_meta) in row_items.iter().zip(row_metas) {
+                if !tile.overlaps(item.interval) {
+                    continue;
+                }
+                let interval = item.interval.intersection(tile);
+                match &mut row.pending {
+                    Some((last, _)) if last.item_uid == item.item_uid => {
+                        last.interval = last.interval.union(interval);
+                    }
+                    pending => {
+                        let mut item = item.clone();
+                        item.interval = interval;
+                        if let Some((last, last_meta)) = pending.replace((item, item_meta.clone()))
+                        {
+                            row.push(last, last_meta, self.threshold);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self, entry_id: &EntryID) -> (SlotTile, SlotMetaTile) {
+        let threshold = self.threshold;
+        let (items, metas) = self
+            .rows
+            .into_iter()
+            .map(|mut row| {
+                if let Some((last, last_meta)) = row.pending.take() {
+                    row.push(last, last_meta, threshold);
+                }
+                row.finish()
+            })
+            .unzip();
+        (
+            SlotTile {
+                entry_id: entry_id.clone(),
+                tile_id: self.tile_id,
+                data: SlotTileData { items },
+            },
+            SlotMetaTile {
+                entry_id: entry_id.clone(),
+                tile_id: self.tile_id,
+                data: SlotMetaTileData { items: metas },
+            },
+        )
+    }
+}
+
+impl RowRetiler {
+    // Runs of items too small to see (and too close together to tell apart)
+    // are replaced by a single item covering all of them
+    pub(crate) fn push(&mut self, item: Item, meta: ItemMeta, threshold: Option<i64>) {
+        let Some(threshold) = threshold else {
+            self.items.push(item);
+            self.metas.push(meta);
+            return;
+        };
+        let small = item.interval.duration_ns() < threshold;
+        match (self.items.last_mut(), self.metas.last_mut()) {
+            (Some(last), Some(last_meta))
+                if small
+                    && self.count > 0
+                    && item.interval.start.0 - last.interval.stop.0 < threshold =>
+            {
+                self.count += 1;
+                last.item_uid = ItemUID(last.item_uid.0 | MERGED_ITEM_UID);
+                last.interval = last.interval.union(item.interval);
+                last_meta.item_uid = last.item_uid;
+                last_meta.original_interval =
+                    last_meta.original_interval.union(meta.original_interval);
+                last_meta.title = format!("{} merged items", self.count);
+                last_meta.fields.clear();
+            }
+            _ => {
+                self.count = usize::from(small);
+                self.items.push(item);
+                self.metas.push(meta);
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> (Vec<Item>, Vec<ItemMeta>) {
+        (self.items, self.metas)
+    }
+}
+
+fn tile_kinds(entry_id: &EntryID) -> &'static [TileKind] {
+    match entry_id.last_index().unwrap() {
+        EntryIndex::Summary => &[TileKind::Summary],
+        EntryIndex::Slot(..) => &[TileKind::Slot, TileKind::SlotMeta],
+    }
+}
+
+pub(crate) fn walk_entry_list(info: &EntryInfo) -> Vec<EntryID> {
     let mut result = Vec::new();
     fn walk(info: &EntryInfo, entry_id: EntryID, result: &mut Vec<EntryID>) {
         match info {
@@ -107,130 +610,478 @@ impl<T: DeferredDataSource> DataSourceArchiveWriter<T> {
             path: path.as_ref().to_owned(),
             force,
             zstd_compression,
+            format: ArchiveFormat::Directory,
+            resume: false,
+            retile: false,
+            search_index: false,
+            progress: Box::new(log_progress),
+            tiles_written: 0,
+            index_builder: None,
+            index_tiles: false,
+            write_error: Arc::new(Mutex::new(None)),
         }
     }
 
-    fn check_info(&mut self) -> Option<DataSourceInfo> {
+    pub fn with_format(mut self, format: ArchiveFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    // Continue writing into an existing (directory) archive, fetching only
+    // the tiles that are missing or fail to decode
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    // Sources with a static tile set are normally archived with that same
+    // tile set. With retile, the tile set is generated from levels and
+    // branch_factor instead, and tiles are assembled from the source's
+    // finest level.
+    pub fn with_retile(mut self, retile: bool) -> Self {
+        self.retile = retile;
+        self
+    }
+
+    // Also write a search index (see search.rs) over item titles and
+    // searchable fields. The whole index is built in memory.
+    pub fn with_search_index(mut self, search_index: bool) -> Self {
+        self.search_index = search_index;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: impl FnMut(ArchiveProgress) + Send + 'static) -> Self {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    fn check_info(&mut self) -> Option<Result<DataSourceInfo, DataSourceError>> {
         // We requested this once, so we know we'll get zero or one result
         self.data_source.get_infos().pop()
     }
 
-    fn write_info(&mut self, info: DataSourceInfo, scope: &rayon::Scope<'_>) {
-        let path = self.path.join("info");
-        spawn_write(path, info, self.zstd_compression, scope);
+    fn check_write_error(&mut self) -> Result<(), DataSourceError> {
+        match self.write_error.lock().unwrap().take() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn write_info(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        info: DataSourceInfo,
+        scope: &rayon::Scope<'_>,
+    ) {
+        spawn_write(
+            sink.clone(),
+            BlobKey::Info,
+            info,
+            self.zstd_compression,
+            self.write_error.clone(),
+            scope,
+        );
     }
 
-    fn write_summary_tiles(&mut self, scope: &rayon::Scope<'_>) {
+    fn write_summary_tiles(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        scope: &rayon::Scope<'_>,
+    ) -> Result<(), DataSourceError> {
         for tile in self.data_source.get_summary_tiles() {
-            let mut path = self.path.join("summary_tile");
-            let req = TileRequestRef {
-                entry_id: &tile.entry_id,
-                tile_id: tile.tile_id,
-            };
-            path.push(req.to_slug());
-            spawn_write(path, tile, self.zstd_compression, scope);
+            let tile = tile.result?;
+            let key = BlobKey::Tile(TileKind::Summary, tile.entry_id.clone(), tile.tile_id);
+            self.tiles_written += 1;
+            spawn_write(
+                sink.clone(),
+                key,
+                tile,
+                self.zstd_compression,
+                self.write_error.clone(),
+                scope,
+            );
         }
+        Ok(())
     }
 
-    fn write_slot_tiles(&mut self, scope: &rayon::Scope<'_>) {
+    fn write_slot_tiles(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        scope: &rayon::Scope<'_>,
+    ) -> Result<(), DataSourceError> {
         for tile in self.data_source.get_slot_tiles() {
-            let mut path = self.path.join("slot_tile");
-            let req = TileRequestRef {
-                entry_id: &tile.entry_id,
-                tile_id: tile.tile_id,
-            };
-            path.push(req.to_slug());
-            spawn_write(path, tile, self.zstd_compression, scope);
+            let tile = tile.result?;
+            let key = BlobKey::Tile(TileKind::Slot, tile.entry_id.clone(), tile.tile_id);
+            self.tiles_written += 1;
+            spawn_write(
+                sink.clone(),
+                key,
+                tile,
+                self.zstd_compression,
+                self.write_error.clone(),
+                scope,
+            );
         }
+        Ok(())
     }
 
-    fn write_slot_meta_tiles(&mut self, scope: &rayon::Scope<'_>) {
+    fn write_slot_meta_tiles(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        scope: &rayon::Scope<'_>,
+    ) -> Result<(), DataSourceError> {
         for tile in self.data_source.get_slot_meta_tiles() {
-            let mut path = self.path.join("slot_meta_tile");
-            let req = TileRequestRef {
-                entry_id: &tile.entry_id,
-                tile_id: tile.tile_id,
-            };
-            path.push(req.to_slug());
-            spawn_write(path, tile, self.zstd_compression, scope);
+            let tile = tile.result?;
+            self.index_tile(&tile);
+            let key = BlobKey::Tile(TileKind::SlotMeta, tile.entry_id.clone(), tile.tile_id);
+            self.tiles_written += 1;
+            spawn_write(
+                sink.clone(),
+                key,
+                tile,
+                self.zstd_compression,
+                self.write_error.clone(),
+                scope,
+            );
+        }
+        Ok(())
+    }
+
+    fn index_tile(&mut self, tile: &SlotMetaTile) {
+        if let (true, Some(builder)) = (self.index_tiles, &mut self.index_builder) {
+            builder.add_tile(tile);
         }
     }
 
-    pub fn write(mut self) -> io::Result<()> {
-        self.path = create_unique_dir(&self.path, self.force)?;
-        println!("Created output directory {:?}", &self.path);
-        create_dir(self.path.join("summary_tile"))?;
-        create_dir(self.path.join("slot_tile"))?;
-        create_dir(self.path.join("slot_meta_tile"))?;
+    fn write_search_index(&mut self, sink: &Arc<ArchiveSink>) -> Result<(), DataSourceError> {
+        let Some(builder) = self.index_builder.take() else {
+            return Ok(());
+        };
+        let (index, shards) = builder.finish();
+        rayon::in_place_scope(|s| {
+            for (i, shard) in shards.into_iter().enumerate() {
+                let key = BlobKey::SearchIndexShard(i);
+                let (sink, zstd, error) = (
+                    sink.clone(),
+                    self.zstd_compression,
+                    self.write_error.clone(),
+                );
+                spawn_write(sink, key, shard, zstd, error, s);
+            }
+            let (sink, zstd, error) = (
+                sink.clone(),
+                self.zstd_compression,
+                self.write_error.clone(),
+            );
+            spawn_write(sink, BlobKey::SearchIndexInfo, index, zstd, error, s);
+        });
+        self.check_write_error()
+    }
+
+    fn write_tiles(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        scope: &rayon::Scope<'_>,
+    ) -> Result<(), DataSourceError> {
+        self.write_summary_tiles(sink, scope)?;
+        self.write_slot_tiles(sink, scope)?;
+        self.write_slot_meta_tiles(sink, scope)
+    }
 
-        self.data_source.fetch_info();
-        let mut info = None;
-        while info.is_none() {
-            info = self.check_info();
+    // Fetch the source tiles overlapping what is missing for this entry (at
+    // full resolution) in time order, a batch at a time, and cut them into the
+    // new tiles at every level. Each new tile is written as soon as no later
+    // source tile can touch it, so only the tiles being cut are held at once.
+    // Missing tiles are given per level, finest last.
+    fn retile_entry(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        entry_id: &EntryID,
+        missing: &[Vec<(TileKind, Vec<TileID>)>],
+        source_tiles: &[TileID],
+    ) -> Result<(), DataSourceError> {
+        let mut needed: Vec<_> = source_tiles
+            .iter()
+            .copied()
+            .filter(|source| {
+                missing
+                    .iter()
+                    .flatten()
+                    .flat_map(|(_, tile_ids)| tile_ids)
+                    .any(|tile_id| tile_id.0.overlaps(source.0))
+            })
+            .collect();
+        needed.sort_by_key(|source| source.0.start);
+
+        let missing_of = |level_missing: &[(TileKind, Vec<TileID>)], kind| {
+            level_missing
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .flat_map(|(_, tile_ids)| tile_ids.iter().copied())
+                .collect::<BTreeSet<_>>()
+        };
+
+        // Slot and slot meta tiles are cut together, but either may be
+        // present already when resuming
+        let mut summaries = Vec::new();
+        let mut slots = Vec::new();
+        for (level, level_missing) in missing.iter().enumerate() {
+            let full = level == missing.len() - 1;
+            for tile_id in missing_of(level_missing, TileKind::Summary) {
+                summaries.push(SummaryRetiler::new(tile_id, full));
+            }
+            let slot_missing = missing_of(level_missing, TileKind::Slot);
+            let meta_missing = missing_of(level_missing, TileKind::SlotMeta);
+            for tile_id in slot_missing.union(&meta_missing) {
+                let write = (
+                    slot_missing.contains(tile_id),
+                    meta_missing.contains(tile_id),
+                );
+                slots.push((write, SlotRetiler::new(*tile_id, full)));
+            }
         }
-        let mut info = info.unwrap();
 
-        let entry_ids = walk_entry_list(&info.entry_info);
-        for entry_id in &entry_ids {
-            let entry_dir = format!("{}", EntryIDSlug(entry_id));
-            match entry_id.last_index().unwrap() {
-                EntryIndex::Summary => {
-                    create_dir(self.path.join("summary_tile").join(&entry_dir))?;
-                }
-                EntryIndex::Slot(..) => {
-                    create_dir(self.path.join("slot_tile").join(&entry_dir))?;
-                    create_dir(self.path.join("slot_meta_tile").join(&entry_dir))?;
+        const RETILE_BATCH: usize = 100;
+
+        for (i, batch) in needed.chunks(RETILE_BATCH).enumerate() {
+            for source in batch {
+                match entry_id.last_index().unwrap() {
+                    EntryIndex::Summary => {
+                        self.data_source.fetch_summary_tile(entry_id, *source, true);
+                    }
+                    EntryIndex::Slot(..) => {
+                        self.data_source.fetch_slot_tile(entry_id, *source, true);
+                        self.data_source
+                            .fetch_slot_meta_tile(entry_id, *source, true);
+                    }
                 }
             }
-        }
 
-        // For now, this only works on dynamic data sources
-        assert!(info.tile_set.tiles.is_empty());
+            let mut summary_tiles = Vec::new();
+            let mut slot_tiles = Vec::new();
+            let mut slot_meta_tiles = Vec::new();
+            while self.data_source.outstanding_requests() > 0 {
+                for tile in self.data_source.get_summary_tiles() {
+                    summary_tiles.push(tile.result?);
+                }
+                for tile in self.data_source.get_slot_tiles() {
+                    slot_tiles.push(tile.result?);
+                }
+                for tile in self.data_source.get_slot_meta_tiles() {
+                    slot_meta_tiles.push(tile.result?);
+                }
+            }
+            summary_tiles.sort_by_key(|tile| tile.tile_id.0.start);
+            slot_tiles.sort_by_key(|tile| tile.tile_id.0.start);
+            slot_meta_tiles.sort_by_key(|tile| tile.tile_id.0.start);
 
-        let mut tile_set = Vec::new();
+            for tile in &summary_tiles {
+                for retiler in &mut summaries {
+                    retiler.add(tile);
+                }
+            }
+            for (tile, meta) in slot_tiles.iter().zip(&slot_meta_tiles) {
+                for (_, retiler) in &mut slots {
+                    retiler.add(tile, meta);
+                }
+            }
 
-        for level in 0..self.levels {
-            let num_tiles = self.branch_factor.pow(level) as i64;
-            let duration = info.interval.duration_ns();
-            let tile_ids: Vec<_> = (0..num_tiles)
-                .map(|i| {
-                    let start = Timestamp(duration * i / num_tiles);
-                    let stop = Timestamp(duration * (i + 1) / num_tiles);
-                    TileID(Interval::new(start, stop))
-                })
-                .collect();
-            tile_set.push(tile_ids);
+            // Tiles that end before the next batch starts are done
+            let done = |tile_id: TileID| match needed.get((i + 1) * RETILE_BATCH) {
+                Some(next) => tile_id.0.stop < next.0.start,
+                None => true,
+            };
+            let finished_summaries;
+            (finished_summaries, summaries) = summaries
+                .into_iter()
+                .partition(|retiler| done(retiler.tile_id));
+            let finished_slots;
+            (finished_slots, slots) = slots
+                .into_iter()
+                .partition(|(_, retiler)| done(retiler.tile_id));
+            self.write_retiled(sink, entry_id, finished_summaries, finished_slots)?;
         }
+        // Tiles no source tile overlaps are still written (empty)
+        self.write_retiled(sink, entry_id, summaries, slots)
+    }
 
-        info.tile_set = TileSet {
-            tiles: tile_set.clone(),
-        };
-
+    fn write_retiled(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        entry_id: &EntryID,
+        summaries: Vec<SummaryRetiler>,
+        slots: Vec<((bool, bool), SlotRetiler)>,
+    ) -> Result<(), DataSourceError> {
         rayon::in_place_scope(|s| {
-            self.write_info(info, s);
+            for retiler in summaries {
+                let tile = retiler.finish(entry_id);
+                let key = BlobKey::Tile(TileKind::Summary, entry_id.clone(), tile.tile_id);
+                let (sink, zstd, error) = (
+                    sink.clone(),
+                    self.zstd_compression,
+                    self.write_error.clone(),
+                );
+                spawn_write(sink, key, tile, zstd, error, s);
+                self.tiles_written += 1;
+            }
+            for ((write_slot, write_meta), retiler) in slots {
+                let full = retiler.full;
+                let (tile, meta) = retiler.finish(entry_id);
+                let tile_id = tile.tile_id;
+                if write_slot {
+                    let key = BlobKey::Tile(TileKind::Slot, entry_id.clone(), tile_id);
+                    let (sink, zstd, error) = (
+                        sink.clone(),
+                        self.zstd_compression,
+                        self.write_error.clone(),
+                    );
+                    spawn_write(sink, key, tile, zstd, error, s);
+                    self.tiles_written += 1;
+                }
+                if write_meta {
+                    self.index_tiles = full;
+                    self.index_tile(&meta);
+                    let key = BlobKey::Tile(TileKind::SlotMeta, entry_id.clone(), tile_id);
+                    let (sink, zstd, error) = (
+                        sink.clone(),
+                        self.zstd_compression,
+                        self.write_error.clone(),
+                    );
+                    spawn_write(sink, key, meta, zstd, error, s);
+                    self.tiles_written += 1;
+                }
+            }
         });
+        self.check_write_error()
+    }
 
-        for level in 0..self.levels {
-            let tile_ids = &tile_set[level as usize];
+    // Tiles of this entry that still need to be written at one level. When
+    // resuming, tiles that are already present are counted as skipped (and
+    // indexed, if this is the finest level).
+    fn find_missing_tiles(
+        &mut self,
+        sink: &ArchiveSink,
+        entry_id: &EntryID,
+        tile_ids: &[TileID],
+        full: bool,
+        progress: &mut ArchiveProgress,
+    ) -> Result<Vec<(TileKind, Vec<TileID>)>, DataSourceError> {
+        self.index_tiles = full;
+        let mut missing = Vec::new();
+        for kind in tile_kinds(entry_id) {
+            let kind_missing = if self.resume {
+                sink.missing_tiles(*kind, entry_id, tile_ids)
+            } else {
+                tile_ids.to_vec()
+            };
+            progress.tiles_skipped += (tile_ids.len() - kind_missing.len()) as u64;
+            if *kind == TileKind::SlotMeta && full && self.index_builder.is_some() {
+                // Tiles written previously still need to be indexed
+                for tile_id in tile_ids {
+                    if !kind_missing.contains(tile_id) {
+                        let tile = sink.read_tile(TileKind::SlotMeta, entry_id, *tile_id)?;
+                        self.index_tile(&tile);
+                    }
+                }
+            }
+            missing.push((*kind, kind_missing));
+        }
+        Ok(missing)
+    }
+
+    fn create_sink(&mut self, entry_ids: &[EntryID]) -> Result<ArchiveSink, DataSourceError> {
+        match self.format {
+            ArchiveFormat::Directory => {
+                if self.resume && self.path.is_dir() {
+                    log::info!("Resuming archive in {:?}", &self.path);
+                    // The archive is incomplete again until we finish
+                    match remove_file(self.path.join("manifest")) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                } else {
+                    self.path = create_unique_dir(&self.path, self.force)?;
+                    log::info!("Created output directory {:?}", &self.path);
+                }
+                if self.search_index {
+                    // Shards are renumbered on every write
+                    let index_dir = self.path.join(SEARCH_INDEX_DIR);
+                    match remove_dir_all(&index_dir) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                    create_dir(index_dir)?;
+                }
+                for entry_id in entry_ids {
+                    let entry_dir = format!("{}", EntryIDSlug(entry_id));
+                    match entry_id.last_index().unwrap() {
+                        EntryIndex::Summary => {
+                            create_dir_all(self.path.join("summary_tile").join(&entry_dir))?;
+                        }
+                        EntryIndex::Slot(..) => {
+                            create_dir_all(self.path.join("slot_tile").join(&entry_dir))?;
+                            create_dir_all(self.path.join("slot_meta_tile").join(&entry_dir))?;
+                        }
+                    }
+                }
+                Ok(ArchiveSink::Directory(self.path.clone()))
+            }
+            ArchiveFormat::SingleFile if self.resume => Err(DataSourceError::BadRequest(
+                "resume is only supported for directory archives".to_owned(),
+            )),
+            ArchiveFormat::SingleFile => {
+                let (path, f) = create_unique_file(&self.path, self.force)?;
+                self.path = path;
+                log::info!("Created output file {:?}", &self.path);
+                Ok(ArchiveSink::File(Mutex::new(ArchiveFileWriter::new(f)?)))
+            }
+        }
+    }
+
+    // Levels are written one after another, fetching each tile from the
+    // data source at that level
+    fn write_levels(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        entry_ids: &[EntryID],
+        tile_set: &[Vec<TileID>],
+    ) -> Result<u64, DataSourceError> {
+        let mut total_tiles = 0;
+        for (level, tile_ids) in tile_set.iter().enumerate() {
+            let level = level as u32;
             let full = level == self.levels - 1;
 
-            println!("Writing level {} with {} tiles", level, tile_ids.len());
+            let mut progress = ArchiveProgress {
+                level,
+                levels: self.levels,
+                tiles_done: 0,
+                tiles_total: entry_ids
+                    .iter()
+                    .map(|entry_id| (tile_kinds(entry_id).len() * tile_ids.len()) as u64)
+                    .sum(),
+                tiles_skipped: 0,
+            };
+            self.tiles_written = 0;
+            (self.progress)(progress);
 
             const MAX_IN_FLIGHT_REQUESTS: u64 = 100;
 
-            for entry_id in &entry_ids {
-                match entry_id.last_index().unwrap() {
-                    EntryIndex::Summary => {
-                        for tile_id in tile_ids {
-                            self.data_source
-                                .fetch_summary_tile(entry_id, *tile_id, full);
-                        }
-                    }
-                    EntryIndex::Slot(..) => {
-                        for tile_id in tile_ids {
-                            self.data_source.fetch_slot_tile(entry_id, *tile_id, full);
-                            self.data_source
-                                .fetch_slot_meta_tile(entry_id, *tile_id, full);
+            for entry_id in entry_ids {
+                let missing =
+                    self.find_missing_tiles(sink, entry_id, tile_ids, full, &mut progress)?;
+                for (kind, kind_missing) in missing {
+                    for tile_id in kind_missing {
+                        match kind {
+                            TileKind::Summary => {
+                                self.data_source.fetch_summary_tile(entry_id, tile_id, full);
+                            }
+                            TileKind::Slot => {
+                                self.data_source.fetch_slot_tile(entry_id, tile_id, full);
+                            }
+                            TileKind::SlotMeta => {
+                                self.data_source
+                                    .fetch_slot_meta_tile(entry_id, tile_id, full);
+                            }
                         }
                     }
                 }
@@ -238,22 +1089,1455 @@ impl<T: DeferredDataSource> DataSourceArchiveWriter<T> {
                 // Bound the number of in-flight requests so we don't use too much memory.
                 rayon::in_place_scope(|s| {
                     while self.data_source.outstanding_requests() > MAX_IN_FLIGHT_REQUESTS {
-                        self.write_summary_tiles(s);
-                        self.write_slot_tiles(s);
-                        self.write_slot_meta_tiles(s);
+                        self.write_tiles(sink, s)?;
                     }
-                });
-            }
-        }
+                    Ok::<_, DataSourceError>(())
+                })?;
+                self.check_write_error()?;
 
-        rayon::in_place_scope(|s| {
-            while self.data_source.outstanding_requests() > 0 {
-                self.write_summary_tiles(s);
-                self.write_slot_tiles(s);
-                self.write_slot_meta_tiles(s);
+                progress.tiles_done = progress.tiles_skipped + self.tiles_written;
+                (self.progress)(progress);
             }
-        });
 
-        Ok(())
+            // Finish the level, so that progress is accurate
+            rayon::in_place_scope(|s| {
+                while self.data_source.outstanding_requests() > 0 {
+                    self.write_tiles(sink, s)?;
+                }
+                Ok::<_, DataSourceError>(())
+            })?;
+            self.check_write_error()?;
+
+            progress.tiles_done = progress.tiles_skipped + self.tiles_written;
+            (self.progress)(progress);
+            total_tiles += progress.tiles_total;
+        }
+        Ok(total_tiles)
+    }
+
+    // Each entry is retiled at every level in one pass, so that its source
+    // tiles are only fetched once. Progress is reported for all levels
+    // together, as the last level.
+    fn write_retiled_tiles(
+        &mut self,
+        sink: &Arc<ArchiveSink>,
+        entry_ids: &[EntryID],
+        tile_set: &[Vec<TileID>],
+        source_tiles: &[TileID],
+    ) -> Result<u64, DataSourceError> {
+        let mut progress = ArchiveProgress {
+            level: self.levels - 1,
+            levels: self.levels,
+            tiles_done: 0,
+            tiles_total: entry_ids
+                .iter()
+                .map(|entry_id| {
+                    (tile_kinds(entry_id).len() * tile_set.iter().flatten().count()) as u64
+                })
+                .sum(),
+            tiles_skipped: 0,
+        };
+        self.tiles_written = 0;
+        (self.progress)(progress);
+
+        for entry_id in entry_ids {
+            let mut missing = Vec::new();
+            for (level, tile_ids) in tile_set.iter().enumerate() {
+                let full = level == tile_set.len() - 1;
+                missing.push(self.find_missing_tiles(
+                    sink,
+                    entry_id,
+                    tile_ids,
+                    full,
+                    &mut progress,
+                )?);
+            }
+            self.retile_entry(sink, entry_id, &missing, source_tiles)?;
+
+            progress.tiles_done = progress.tiles_skipped + self.tiles_written;
+            (self.progress)(progress);
+        }
+        Ok(progress.tiles_total)
+    }
+
+    pub fn write(mut self) -> Result<(), DataSourceError> {
+        self.data_source.fetch_info();
+        let mut info = None;
+        while info.is_none() {
+            info = self.check_info();
+        }
+        let mut info = info.unwrap()?;
+
+        let entry_ids = walk_entry_list(&info.entry_info);
+
+        let source_tiles = info.tile_set.tiles.last().cloned();
+        let copy_tiles = source_tiles.is_some() && !self.retile;
+        let retile_from = if copy_tiles { None } else { source_tiles };
+        if copy_tiles {
+            self.levels = info.tile_set.tiles.len() as u32;
+        }
+
+        let mut tile_set = Vec::new();
+
+        for level in 0..self.levels {
+            if copy_tiles {
+                tile_set.push(info.tile_set.tiles[level as usize].clone());
+                continue;
+            }
+
+            let num_tiles = self.branch_factor.pow(level) as i64;
+            let duration = info.interval.duration_ns();
+            let tile_ids: Vec<_> = (0..num_tiles)
+                .map(|i| {
+                    let start = Timestamp(duration * i / num_tiles);
+                    let stop = Timestamp(duration * (i + 1) / num_tiles);
+                    TileID(Interval::new(start, stop))
+                })
+                .collect();
+            tile_set.push(tile_ids);
+        }
+
+        info.tile_set = TileSet {
+            tiles: tile_set.clone(),
+        };
+
+        if self.resume && self.format == ArchiveFormat::Directory {
+            // Tiles can only be reused if they cover the same intervals
+            if let Ok(old_info) = read_data::<DataSourceInfo>(&self.path.join("info")) {
+                if old_info.tile_set.tiles != tile_set {
+                    return Err(DataSourceError::BadRequest(format!(
+                        "archive {:?} was written with a different tile set",
+                        self.path
+                    )));
+                }
+            }
+        }
+
+        let sink = Arc::new(self.create_sink(&entry_ids)?);
+        if self.search_index {
+            self.index_builder = Some(SearchIndexBuilder::new(&info.field_schema));
+        }
+
+        rayon::in_place_scope(|s| {
+            self.write_info(&sink, info, s);
+        });
+        self.check_write_error()?;
+
+        let total_tiles = match retile_from {
+            Some(source_tiles) => {
+                self.write_retiled_tiles(&sink, &entry_ids, &tile_set, &source_tiles)?
+            }
+            None => self.write_levels(&sink, &entry_ids, &tile_set)?,
+        };
+
+        self.write_search_index(&sink)?;
+
+        // All writes have completed, so we hold the only reference
+        match Arc::try_unwrap(sink) {
+            Ok(ArchiveSink::File(f)) => {
+                f.into_inner().unwrap().finish(self.zstd_compression)?;
+            }
+            Ok(ArchiveSink::Directory(path)) => {
+                let manifest = ArchiveManifest {
+                    levels: self.levels,
+                    tiles: total_tiles,
+                };
+                let data = encode_data(manifest, self.zstd_compression)?;
+                File::create(path.join("manifest"))?.write_all(&data)?;
+            }
+            Err(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+pub struct DataSourceArchiveReader {
+    path: PathBuf,
+    info: DataSourceInfo,
+    tile_ids: BTreeSet<TileID>,
+    search_index: Option<SearchIndexInfo>,
+}
+
+impl DataSourceArchiveReader {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DataSourceError> {
+        let path = path.as_ref().to_owned();
+        let info: DataSourceInfo = read_data(&path.join("info"))?;
+        let tile_ids = info.tile_set.tiles.iter().flatten().copied().collect();
+        let search_index = match read_data(&path.join(SEARCH_INDEX_DIR).join("info")) {
+            Ok(search_index) => Some(search_index),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            info,
+            tile_ids,
+            search_index,
+        })
+    }
+
+    // Whether the writer ran to completion
+    pub fn is_complete(path: impl AsRef<Path>) -> bool {
+        read_data::<ArchiveManifest>(&path.as_ref().join("manifest")).is_ok()
+    }
+
+    fn read_tile<T>(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+    ) -> Result<T, DataSourceError>
+    where
+        T: DeserializeOwned,
+    {
+        check_tile_id(&self.tile_ids, tile_id)?;
+
+        let req = TileRequestRef { entry_id, tile_id };
+        let path = self.path.join(kind.name()).join(req.to_slug());
+        read_data(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                DataSourceError::NotFound(format!("missing archive file {:?}", path))
+            }
+            _ => DataSourceError::from(e),
+        })
+    }
+}
+
+fn check_tile_id(tile_ids: &BTreeSet<TileID>, tile_id: TileID) -> Result<(), DataSourceError> {
+    // Archives are only written for a fixed set of tiles
+    if !tile_ids.contains(&tile_id) {
+        return Err(DataSourceError::BadRequest(format!(
+            "tile {} is not part of the archive tile set",
+            TileIDSlug(tile_id)
+        )));
+    }
+    Ok(())
+}
+
+impl DataSource for DataSourceArchiveReader {
+    fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+        Ok(self.info.clone())
+    }
+
+    fn fetch_summary_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SummaryTile, DataSourceError> {
+        self.read_tile(TileKind::Summary, entry_id, tile_id)
+    }
+
+    fn fetch_slot_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SlotTile, DataSourceError> {
+        self.read_tile(TileKind::Slot, entry_id, tile_id)
+    }
+
+    fn fetch_slot_meta_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SlotMetaTile, DataSourceError> {
+        self.read_tile(TileKind::SlotMeta, entry_id, tile_id)
+    }
+
+    fn search(&self, request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        if let Some(index) = &self.search_index {
+            let read_shard = |i: usize| {
+                let path = self.path.join(SEARCH_INDEX_DIR).join(i.to_string());
+                Ok(read_data(&path)?)
+            };
+            if let Some(result) = indexed_search(self, index, read_shard, request) {
+                return result;
+            }
+        }
+        scan_slot_meta_tiles(self, request)
+    }
+}
+
+// A memory-mapped single-file archive. Blobs are stored in their encoded
+// form so that they can be handed out (e.g., over HTTP) without decoding.
+pub struct ArchiveFile {
+    path: PathBuf,
+    mmap: Mmap,
+    info: DataSourceInfo,
+    tile_ids: BTreeSet<TileID>,
+    // Tiles are stored between the header and the index
+    data_end: u64,
+    tiles: BTreeMap<(TileKind, EntryID, TileID), BlobRef>,
+    search_index: Option<SearchIndexInfo>,
+    search_shards: BTreeMap<usize, BlobRef>,
+}
+
+// Whether the file starts like a single-file archive, as opposed to some
+// other kind of profile. True for unfinished archives too, so that opening
+// them reports that they're incomplete.
+pub fn is_archive_file(path: impl AsRef<Path>) -> bool {
+    let mut magic = [0; ARCHIVE_MAGIC.len()];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map_or(false, |()| &magic == ARCHIVE_MAGIC)
+}
+
+impl ArchiveFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DataSourceError> {
+        let path = path.as_ref().to_owned();
+        let f = File::open(&path)?;
+        // SAFETY: archives are written once and never modified in place. If
+        // the file is truncated underneath us anyway, reads may fault.
+        let mmap = unsafe { Mmap::map(&f)? };
+
+        let bad = |msg: &str| DataSourceError::Decode(format!("{:?}: {}", path, msg));
+        let header = mmap
+            .get(..ARCHIVE_HEADER_SIZE as usize)
+            .ok_or_else(|| bad("file too small"))?;
+        let word = |i: usize| u64::from_le_bytes(header[i * 8..(i + 1) * 8].try_into().unwrap());
+        if &header[..8] != ARCHIVE_MAGIC {
+            return Err(bad("not a profile archive"));
+        }
+        if word(1) != ARCHIVE_VERSION {
+            return Err(bad(&format!("unsupported archive version {}", word(1))));
+        }
+        if word(2) == 0 {
+            return Err(bad("archive is incomplete"));
+        }
+        let index_ref = BlobRef {
+            offset: word(2),
+            len: word(3),
+        };
+
+        let blob = |blob: BlobRef| {
+            usize::try_from(blob.offset)
+                .ok()
+                .zip(usize::try_from(blob.len).ok())
+                .and_then(|(start, len)| mmap.get(start..start.checked_add(len)?))
+                .ok_or_else(|| bad("blob out of bounds"))
+        };
+        let index: ArchiveIndex = decode_data(blob(index_ref)?)?;
+        let info_ref = index.info.ok_or_else(|| bad("missing info"))?;
+        let info: DataSourceInfo = decode_data(blob(info_ref)?)?;
+        // Tile bounds are checked when tiles are read, so that a damaged
+        // archive can still be opened (and verified)
+        let mut tiles = BTreeMap::new();
+        for (kind, entry_id, tile_id, tile_ref) in index.tiles {
+            tiles.insert((kind, entry_id, tile_id), tile_ref);
+        }
+        let search_index = match index.search_index {
+            Some(index_ref) => Some(decode_data(blob(index_ref)?)?),
+            None => None,
+        };
+        for shard_ref in index.search_shards.values() {
+            blob(*shard_ref)?;
+        }
+
+        let tile_ids = info.tile_set.tiles.iter().flatten().copied().collect();
+        Ok(Self {
+            path,
+            mmap,
+            info,
+            tile_ids,
+            data_end: index_ref.offset,
+            tiles,
+            search_index,
+            search_shards: index.search_shards,
+        })
+    }
+
+    pub fn info(&self) -> &DataSourceInfo {
+        &self.info
+    }
+
+    fn in_bounds(&self, blob: BlobRef) -> bool {
+        blob.offset >= ARCHIVE_HEADER_SIZE
+            && (blob.offset.checked_add(blob.len)).map_or(false, |end| end <= self.data_end)
+    }
+
+    // Whether the index has every tile in the tile set, each within the
+    // file (see DataSourceArchiveReader::is_complete)
+    pub fn is_complete(&self) -> bool {
+        walk_entry_list(&self.info.entry_info)
+            .iter()
+            .all(|entry_id| {
+                tile_kinds(entry_id).iter().all(|kind| {
+                    self.tile_ids.iter().all(|tile_id| {
+                        (self.tiles.get(&(*kind, entry_id.clone(), *tile_id)))
+                            .map_or(false, |blob| self.in_bounds(*blob))
+                    })
+                })
+            })
+    }
+
+    // Encoded (zstd-compressed CBOR) tile
+    pub fn tile_blob(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+    ) -> Result<&[u8], DataSourceError> {
+        check_tile_id(&self.tile_ids, tile_id)?;
+
+        let req = TileRequestRef { entry_id, tile_id };
+        let blob = self
+            .tiles
+            .get(&(kind, entry_id.clone(), tile_id))
+            .ok_or_else(|| {
+                DataSourceError::NotFound(format!(
+                    "missing {} {} in archive {:?}",
+                    kind.name(),
+                    req.to_slug(),
+                    self.path
+                ))
+            })?;
+        if !self.in_bounds(*blob) {
+            return Err(DataSourceError::Decode(format!(
+                "{} {} is out of bounds in archive {:?}",
+                kind.name(),
+                req.to_slug(),
+                self.path
+            )));
+        }
+        Ok(&self.mmap[blob.offset as usize..(blob.offset + blob.len) as usize])
+    }
+
+    fn read_tile<T>(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+    ) -> Result<T, DataSourceError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(decode_data(self.tile_blob(kind, entry_id, tile_id)?)?)
+    }
+
+    pub fn search_index(&self) -> Option<&SearchIndexInfo> {
+        self.search_index.as_ref()
+    }
+
+    fn read_search_shard(&self, i: usize) -> Result<SearchIndexShard, DataSourceError> {
+        // Bounds were checked on open
+        let blob = self.search_shards.get(&i).ok_or_else(|| {
+            DataSourceError::NotFound(format!(
+                "missing search index shard {} in archive {:?}",
+                i, self.path
+            ))
+        })?;
+        let data = &self.mmap[blob.offset as usize..(blob.offset + blob.len) as usize];
+        Ok(decode_data(data)?)
+    }
+}
+
+pub struct DataSourceArchiveFileReader {
+    archive: Arc<ArchiveFile>,
+}
+
+impl DataSourceArchiveFileReader {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DataSourceError> {
+        Ok(Self::from_archive(Arc::new(ArchiveFile::open(path)?)))
+    }
+
+    pub fn from_archive(archive: Arc<ArchiveFile>) -> Self {
+        Self { archive }
+    }
+}
+
+impl DataSource for DataSourceArchiveFileReader {
+    fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+        Ok(self.archive.info().clone())
+    }
+
+    fn fetch_summary_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SummaryTile, DataSourceError> {
+        self.archive.read_tile(TileKind::Summary, entry_id, tile_id)
+    }
+
+    fn fetch_slot_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SlotTile, DataSourceError> {
+        self.archive.read_tile(TileKind::Slot, entry_id, tile_id)
+    }
+
+    fn fetch_slot_meta_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SlotMetaTile, DataSourceError> {
+        self.archive
+            .read_tile(TileKind::SlotMeta, entry_id, tile_id)
+    }
+
+    fn search(&self, request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        if let Some(index) = self.archive.search_index() {
+            let read_shard = |i| self.archive.read_search_shard(i);
+            if let Some(result) = indexed_search(self, index, read_shard, request) {
+                return result;
+            }
+        }
+        scan_slot_meta_tiles(self, request)
+    }
+}
+
+// Archive verification: decode every tile implied by the tile set and
+// cross-check slot tiles against their meta tiles.
+
+#[derive(Debug, Clone)]
+pub struct TileStats {
+    pub kind: TileKind,
+    pub entry_id: EntryID,
+    pub tile_id: TileID,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LevelStats {
+    pub tiles: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+impl LevelStats {
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveProblem {
+    pub kind: TileKind,
+    pub entry_id: EntryID,
+    pub tile_id: TileID,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveReport {
+    // Whether the writer ran to completion (see DataSourceArchiveReader and
+    // ArchiveFile::is_complete)
+    pub complete: bool,
+    pub levels: Vec<LevelStats>,
+    pub largest_tiles: Vec<TileStats>,
+    pub problems: Vec<ArchiveProblem>,
+}
+
+impl ArchiveReport {
+    pub fn is_ok(&self) -> bool {
+        self.complete && self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ArchiveProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let req = TileRequestRef {
+            entry_id: &self.entry_id,
+            tile_id: self.tile_id,
+        };
+        write!(
+            f,
+            "{}/{}: {}",
+            self.kind.name(),
+            req.to_slug(),
+            self.message
+        )
+    }
+}
+
+impl fmt::Display for ArchiveReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.complete {
+            writeln!(f, "Archive is incomplete (missing manifest or tiles)")?;
+        }
+        for (level, stats) in self.levels.iter().enumerate() {
+            writeln!(
+                f,
+                "Level {}: {} tiles, {} bytes ({} uncompressed, ratio {:.2})",
+                level,
+                stats.tiles,
+                stats.compressed_size,
+                stats.uncompressed_size,
+                stats.compression_ratio()
+            )?;
+        }
+        writeln!(f, "Largest tiles:")?;
+        for tile in &self.largest_tiles {
+            let req = TileRequestRef {
+                entry_id: &tile.entry_id,
+                tile_id: tile.tile_id,
+            };
+            writeln!(
+                f,
+                "  {}/{}: {} bytes ({} uncompressed)",
+                tile.kind.name(),
+                req.to_slug(),
+                tile.compressed_size,
+                tile.uncompressed_size
+            )?;
+        }
+        writeln!(f, "{} problems found", self.problems.len())?;
+        for problem in &self.problems {
+            writeln!(f, "  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+enum ArchiveSource {
+    Directory(PathBuf),
+    File(Box<ArchiveFile>),
+}
+
+impl ArchiveSource {
+    fn read(&self, kind: TileKind, entry_id: &EntryID, tile_id: TileID) -> Result<Vec<u8>, String> {
+        match self {
+            ArchiveSource::Directory(path) => {
+                let req = TileRequestRef { entry_id, tile_id };
+                std::fs::read(path.join(kind.name()).join(req.to_slug())).map_err(|e| e.to_string())
+            }
+            ArchiveSource::File(archive) => archive
+                .tile_blob(kind, entry_id, tile_id)
+                .map(|blob| blob.to_vec())
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+struct TileCheck<'a> {
+    entry_id: &'a EntryID,
+    tile_id: TileID,
+    stats: Vec<TileStats>,
+    problems: Vec<ArchiveProblem>,
+}
+
+impl<'a> TileCheck<'a> {
+    fn problem(&mut self, kind: TileKind, message: String) {
+        self.problems.push(ArchiveProblem {
+            kind,
+            entry_id: self.entry_id.clone(),
+            tile_id: self.tile_id,
+            message,
+        });
+    }
+
+    fn load<T>(&mut self, source: &ArchiveSource, kind: TileKind) -> Option<T>
+    where
+        T: DeserializeOwned,
+    {
+        let decoded = source
+            .read(kind, self.entry_id, self.tile_id)
+            .and_then(|raw| {
+                let data = zstd::decode_all(&raw[..]).map_err(|e| e.to_string())?;
+                let tile = ciborium::from_reader(&data[..]).map_err(|e| e.to_string())?;
+                Ok((tile, raw.len(), data.len()))
+            });
+        match decoded {
+            Ok((tile, compressed_size, uncompressed_size)) => {
+                self.stats.push(TileStats {
+                    kind,
+                    entry_id: self.entry_id.clone(),
+                    tile_id: self.tile_id,
+                    compressed_size: compressed_size as u64,
+                    uncompressed_size: uncompressed_size as u64,
+                });
+                Some(tile)
+            }
+            Err(e) => {
+                self.problem(kind, e);
+                None
+            }
+        }
+    }
+
+    fn check_ids(&mut self, kind: TileKind, entry_id: &EntryID, tile_id: TileID) {
+        if entry_id != self.entry_id || tile_id != self.tile_id {
+            let req = TileRequestRef { entry_id, tile_id };
+            self.problem(kind, format!("tile contains data for {}", req.to_slug()));
+        }
+    }
+
+    fn check_slot(&mut self, slot: &SlotTile, meta: &SlotMetaTile) {
+        let (slot, meta) = (&slot.data.items, &meta.data.items);
+        if slot.len() != meta.len() {
+            let msg = format!("{} rows, but meta tile has {}", slot.len(), meta.len());
+            self.problem(TileKind::Slot, msg);
+            return;
+        }
+        for (row, (items, metas)) in slot.iter().zip(meta).enumerate() {
+            if items.len() != metas.len() {
+                let msg = format!(
+                    "row {} has {} items, but meta tile has {}",
+                    row,
+                    items.len(),
+                    metas.len()
+                );
+                self.problem(TileKind::Slot, msg);
+                continue;
+            }
+            let mismatch = items
+                .iter()
+                .zip(metas)
+                .position(|(item, meta)| item.item_uid != meta.item_uid);
+            if let Some(index) = mismatch {
+                let msg = format!(
+                    "row {} item {} has UID {}, but meta tile has {}",
+                    row, index, items[index].item_uid.0, metas[index].item_uid.0
+                );
+                self.problem(TileKind::Slot, msg);
+            }
+        }
+    }
+
+    fn check_intervals(&mut self, slot: &SlotTile) {
+        let tile = self.tile_id.0;
+        let outside = slot
+            .data
+            .items
+            .iter()
+            .flatten()
+            .find(|item| item.interval.start < tile.start || item.interval.stop > tile.stop);
+        if let Some(item) = outside {
+            let msg = format!(
+                "item {} interval {} is outside the tile",
+                item.item_uid.0, item.interval
+            );
+            self.problem(TileKind::Slot, msg);
+        }
+    }
+}
+
+fn verify_tile<'a>(
+    source: &ArchiveSource,
+    entry_id: &'a EntryID,
+    tile_id: TileID,
+) -> TileCheck<'a> {
+    let mut check = TileCheck {
+        entry_id,
+        tile_id,
+        stats: Vec::new(),
+        problems: Vec::new(),
+    };
+    match entry_id.last_index().unwrap() {
+        EntryIndex::Summary => {
+            if let Some(tile) = check.load::<SummaryTile>(source, TileKind::Summary) {
+                check.check_ids(TileKind::Summary, &tile.entry_id, tile.tile_id);
+            }
+        }
+        EntryIndex::Slot(..) => {
+            let slot = check.load::<SlotTile>(source, TileKind::Slot);
+            let meta = check.load::<SlotMetaTile>(source, TileKind::SlotMeta);
+            if let Some(slot) = &slot {
+                check.check_ids(TileKind::Slot, &slot.entry_id, slot.tile_id);
+                check.check_intervals(slot);
+            }
+            if let Some(meta) = &meta {
+                check.check_ids(TileKind::SlotMeta, &meta.entry_id, meta.tile_id);
+            }
+            if let (Some(slot), Some(meta)) = (&slot, &meta) {
+                check.check_slot(slot, meta);
+            }
+        }
+    }
+    check
+}
+
+// Works on both directory and single-file archives
+pub fn verify_archive(path: impl AsRef<Path>) -> Result<ArchiveReport, DataSourceError> {
+    const LARGEST_TILES: usize = 10;
+
+    let path = path.as_ref();
+    let (source, info, complete) = if path.is_dir() {
+        let info: DataSourceInfo = read_data(&path.join("info"))?;
+        let complete = DataSourceArchiveReader::is_complete(path);
+        (ArchiveSource::Directory(path.to_owned()), info, complete)
+    } else {
+        let archive = ArchiveFile::open(path)?;
+        let info = archive.info().clone();
+        let complete = archive.is_complete();
+        (ArchiveSource::File(Box::new(archive)), info, complete)
+    };
+
+    let entry_ids = walk_entry_list(&info.entry_info);
+    let mut report = ArchiveReport {
+        complete,
+        levels: Vec::new(),
+        largest_tiles: Vec::new(),
+        problems: Vec::new(),
+    };
+    let mut all_stats = Vec::new();
+    for tile_ids in &info.tile_set.tiles {
+        let checks: Vec<_> = entry_ids
+            .par_iter()
+            .flat_map(|entry_id| {
+                let source = &source;
+                tile_ids
+                    .par_iter()
+                    .map(move |tile_id| verify_tile(source, entry_id, *tile_id))
+            })
+            .map(|check| (check.stats, check.problems))
+            .collect();
+
+        let mut level = LevelStats::default();
+        for (stats, problems) in checks {
+            for tile in &stats {
+                level.tiles += 1;
+                level.compressed_size += tile.compressed_size;
+                level.uncompressed_size += tile.uncompressed_size;
+            }
+            all_stats.extend(stats);
+            report.problems.extend(problems);
+        }
+        report.levels.push(level);
+    }
+
+    all_stats.sort_by_key(|tile| std::cmp::Reverse(tile.compressed_size));
+    all_stats.truncate(LARGEST_TILES);
+    report.largest_tiles = all_stats;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::{
+        Color32, DataSourceMut, FieldSchema, Item, ItemMeta, ItemUID, SearchMode, SlotMetaTileData,
+        SlotTileData, SummaryTileData, UtilPoint,
+    };
+    use crate::deferred_data::DeferredDataSourceWrapper;
+    use crate::search::{candidates, IndexLookup};
+
+    struct TestDataSource {
+        info: DataSourceInfo,
+        items: Vec<Interval>,
+    }
+
+    impl TestDataSource {
+        fn new() -> Self {
+            let entry_info = EntryInfo::Panel {
+                short_name: "root".to_owned(),
+                long_name: "root".to_owned(),
+                summary: Some(Box::new(EntryInfo::Summary {
+                    color: Color32::BLUE,
+                })),
+                slots: vec![EntryInfo::Slot {
+                    short_name: "s0".to_owned(),
+                    long_name: "Slot 0".to_owned(),
+                    max_rows: 1,
+                }],
+            };
+            let items = (0..10)
+                .map(|i| Interval::new(Timestamp(i * 100 + 10), Timestamp(i * 100 + 90)))
+                .collect();
+            Self {
+                info: DataSourceInfo {
+                    entry_info,
+                    interval: Interval::new(Timestamp(0), Timestamp(1000)),
+                    tile_set: TileSet::default(),
+                    field_schema: FieldSchema::new(),
+                },
+                items,
+            }
+        }
+
+        fn overlapping(&self, tile_id: TileID) -> impl Iterator<Item = (usize, &Interval)> {
+            self.items
+                .iter()
+                .enumerate()
+                .filter(move |(_, i)| tile_id.0.overlaps(**i))
+        }
+    }
+
+    impl DataSource for TestDataSource {
+        fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+            Ok(self.info.clone())
+        }
+
+        fn fetch_summary_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SummaryTile, DataSourceError> {
+            Ok(SummaryTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SummaryTileData {
+                    utilization: vec![UtilPoint {
+                        time: tile_id.0.start,
+                        util: 0.5,
+                    }],
+                },
+            })
+        }
+
+        fn fetch_slot_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotTile, DataSourceError> {
+            let items = self
+                .overlapping(tile_id)
+                .map(|(i, interval)| Item {
+                    item_uid: ItemUID(i as u64),
+                    interval: interval.intersection(tile_id.0),
+                    color: Color32::RED,
+                })
+                .collect();
+            Ok(SlotTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotTileData { items: vec![items] },
+            })
+        }
+
+        fn fetch_slot_meta_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotMetaTile, DataSourceError> {
+            let items = self
+                .overlapping(tile_id)
+                .map(|(i, interval)| ItemMeta {
+                    item_uid: ItemUID(i as u64),
+                    original_interval: *interval,
+                    title: format!("Item {}", i),
+                    fields: Vec::new(),
+                })
+                .collect();
+            Ok(SlotMetaTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotMetaTileData { items: vec![items] },
+            })
+        }
+    }
+
+    // An archive (directory or file) in the temp directory, deleted on drop
+    // so that failing tests don't leave it behind
+    struct TempArchive {
+        path: PathBuf,
+    }
+
+    impl TempArchive {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "legion_prof_viewer_{}_{}",
+                name,
+                std::process::id()
+            ));
+            let archive = Self { path };
+            archive.remove();
+            archive
+        }
+
+        fn remove(&self) {
+            let _ = remove_dir_all(&self.path);
+            let _ = remove_file(&self.path);
+        }
+    }
+
+    impl Drop for TempArchive {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    type TestArchiveWriter<T> = DataSourceArchiveWriter<DeferredDataSourceWrapper<T>>;
+
+    fn write_archive<T: DataSourceMut>(
+        name: &str,
+        source: T,
+        levels: u32,
+        branch_factor: u64,
+        configure: impl FnOnce(TestArchiveWriter<T>) -> TestArchiveWriter<T>,
+    ) -> TempArchive {
+        let archive = TempArchive::new(name);
+        let source = DeferredDataSourceWrapper::new(source);
+        let writer =
+            DataSourceArchiveWriter::new(source, levels, branch_factor, &archive.path, true, 1);
+        configure(writer).write().unwrap();
+        archive
+    }
+
+    // Two levels of TestDataSource
+    fn write_test_archive(
+        name: &str,
+        configure: impl FnOnce(TestArchiveWriter<TestDataSource>) -> TestArchiveWriter<TestDataSource>,
+    ) -> TempArchive {
+        write_archive(name, TestDataSource::new(), 2, 2, configure)
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let archive = write_test_archive("roundtrip", |w| w);
+        let path = &archive.path;
+
+        let reader = DataSourceArchiveReader::new(path).unwrap();
+        let info = reader.fetch_info().unwrap();
+        assert_eq!(info.tile_set.tiles.len(), 2);
+        assert_eq!(info.tile_set.tiles[1].len(), 2);
+
+        let slot = EntryID::root().child(0);
+        for tile_id in &info.tile_set.tiles[1] {
+            let tile = reader.fetch_slot_tile(&slot, *tile_id, true).unwrap();
+            assert_eq!(tile.tile_id, *tile_id);
+            assert_eq!(tile.data.items[0].len(), 5);
+
+            let meta = reader.fetch_slot_meta_tile(&slot, *tile_id, true).unwrap();
+            assert_eq!(meta.data.items[0].len(), 5);
+
+            let summary = reader
+                .fetch_summary_tile(&EntryID::root().summary(), *tile_id, true)
+                .unwrap();
+            assert_eq!(summary.data.utilization.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_archive_resume() {
+        let archive = write_test_archive("resume", |w| w);
+        let path = &archive.path;
+        assert!(DataSourceArchiveReader::is_complete(path));
+
+        // Simulate a crash: one tile never written, another truncated
+        let info = DataSourceArchiveReader::new(path)
+            .unwrap()
+            .fetch_info()
+            .unwrap();
+        let slot = EntryID::root().child(0);
+        let tile_path = |kind: TileKind, tile_id| {
+            let req = TileRequestRef {
+                entry_id: &slot,
+                tile_id,
+            };
+            path.join(kind.name()).join(req.to_slug())
+        };
+        remove_file(tile_path(TileKind::Slot, info.tile_set.tiles[1][0])).unwrap();
+        File::create(tile_path(TileKind::SlotMeta, info.tile_set.tiles[1][1])).unwrap();
+        remove_file(path.join("manifest")).unwrap();
+        assert!(!DataSourceArchiveReader::is_complete(path));
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_log = progress.clone();
+        let source = DeferredDataSourceWrapper::new(TestDataSource::new());
+        DataSourceArchiveWriter::new(source, 2, 2, path, false, 1)
+            .with_resume(true)
+            .with_progress(move |p| progress_log.lock().unwrap().push(p))
+            .write()
+            .unwrap();
+        assert!(DataSourceArchiveReader::is_complete(path));
+
+        let progress = progress.lock().unwrap();
+        let last = progress.last().unwrap();
+        // 2 tiles at level 1, for each of summary, slot and slot meta
+        assert_eq!(last.level, 1);
+        assert_eq!(last.tiles_total, 6);
+        assert_eq!(last.tiles_done, 6);
+        assert_eq!(last.tiles_skipped, 4);
+
+        let reader = DataSourceArchiveReader::new(path).unwrap();
+        for tile_id in &info.tile_set.tiles[1] {
+            reader.fetch_slot_tile(&slot, *tile_id, true).unwrap();
+            reader.fetch_slot_meta_tile(&slot, *tile_id, true).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_archive_static_source() {
+        let archive = write_test_archive("static_source", |w| w);
+        let path = &archive.path;
+        let original = DataSourceArchiveReader::new(path).unwrap();
+        let original_info = original.fetch_info().unwrap();
+        let slot = EntryID::root().child(0);
+
+        // Copy the tile set as-is (levels and branch factor are ignored)
+        let source = DataSourceArchiveReader::new(path).unwrap();
+        let copy = write_archive("static_source_copy", source, 1, 4, |w| {
+            w.with_format(ArchiveFormat::SingleFile)
+        });
+        let copy = DataSourceArchiveFileReader::new(&copy.path).unwrap();
+        let info = copy.fetch_info().unwrap();
+        assert_eq!(info.tile_set.tiles, original_info.tile_set.tiles);
+        for tile_id in info.tile_set.tiles.iter().flatten() {
+            let a = original
+                .fetch_slot_meta_tile(&slot, *tile_id, false)
+                .unwrap();
+            let b = copy.fetch_slot_meta_tile(&slot, *tile_id, false).unwrap();
+            assert_eq!(a.data.items[0].len(), b.data.items[0].len());
+        }
+
+        // Re-tile from the finest level of the original
+        let source = DataSourceArchiveReader::new(path).unwrap();
+        let retiled = write_archive("static_source_retile", source, 3, 2, |w| {
+            w.with_retile(true)
+        });
+        let retiled = DataSourceArchiveReader::new(&retiled.path).unwrap();
+        let info = retiled.fetch_info().unwrap();
+        assert_eq!(info.tile_set.tiles.len(), 3);
+
+        // Items are large enough to stay visible at the coarsest level
+        let whole = retiled
+            .fetch_slot_tile(&slot, info.tile_set.tiles[0][0], false)
+            .unwrap();
+        assert_eq!(whole.data.items[0].len(), 10);
+
+        // Items are sliced to the new tile boundaries
+        let tile_id = info.tile_set.tiles[2][0];
+        let tile = retiled.fetch_slot_tile(&slot, tile_id, true).unwrap();
+        let meta = retiled.fetch_slot_meta_tile(&slot, tile_id, true).unwrap();
+        assert_eq!(tile.data.items[0].len(), 3);
+        assert_eq!(meta.data.items[0].len(), 3);
+        assert_eq!(tile.data.items[0][2].interval.stop, tile_id.0.stop);
+        assert_eq!(
+            meta.data.items[0][2].original_interval,
+            Interval::new(Timestamp(210), Timestamp(290))
+        );
+    }
+
+    #[test]
+    fn test_archive_retile_batches() {
+        // More source tiles than are fetched at once
+        let archive = write_archive("retile_batches", TestDataSource::new(), 8, 2, |w| w);
+        let source = DataSourceArchiveReader::new(&archive.path).unwrap();
+        let retiled = write_archive("retile_batches_retile", source, 2, 3, |w| {
+            w.with_retile(true)
+        });
+        let retiled = DataSourceArchiveReader::new(&retiled.path).unwrap();
+        let info = retiled.fetch_info().unwrap();
+        let slot = EntryID::root().child(0);
+        let mut uids = Vec::new();
+        for tile_id in &info.tile_set.tiles[1] {
+            let tile = retiled.fetch_slot_tile(&slot, *tile_id, true).unwrap();
+            uids.extend(tile.data.items[0].iter().map(|item| item.item_uid.0));
+            let summary = retiled
+                .fetch_summary_tile(&EntryID::root().summary(), *tile_id, true)
+                .unwrap();
+            assert!(!summary.data.utilization.is_empty());
+        }
+        uids.dedup();
+        assert_eq!(uids, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_retile_downsample() {
+        let source = TestDataSource::new();
+        let slot = EntryID::root().child(0);
+        let retile = |tile_id, full| {
+            // Split item 4 across two source tiles
+            let mut retiler = SlotRetiler::new(tile_id, full);
+            for source_id in [
+                TileID(Interval::new(Timestamp(0), Timestamp(450))),
+                TileID(Interval::new(Timestamp(450), Timestamp(1000))),
+            ] {
+                let tile = source.fetch_slot_tile(&slot, source_id, true).unwrap();
+                let meta = source.fetch_slot_meta_tile(&slot, source_id, true).unwrap();
+                retiler.add(&tile, &meta);
+            }
+            retiler.finish(&slot)
+        };
+
+        // Seen from far enough away, every item is too small to see
+        let tile_id = TileID(Interval::new(Timestamp(0), Timestamp(1_000_000)));
+        let (tile, meta) = retile(tile_id, false);
+        assert_eq!(tile.data.items[0].len(), 1);
+        assert_eq!(meta.data.items[0][0].title, "10 merged items");
+        assert_eq!(
+            meta.data.items[0][0].original_interval,
+            Interval::new(Timestamp(10), Timestamp(990))
+        );
+
+        // The merged item can't be mistaken for any of the original items
+        let uid = tile.data.items[0][0].item_uid;
+        assert_eq!(meta.data.items[0][0].item_uid, uid);
+        assert!((0..10).all(|i| uid != ItemUID(i)));
+
+        // Full tiles keep every item, stitched back together
+        let (tile, _) = retile(tile_id, true);
+        assert_eq!(tile.data.items[0].len(), 10);
+        assert_eq!(
+            tile.data.items[0][4].interval,
+            Interval::new(Timestamp(410), Timestamp(490))
+        );
+        assert_eq!(tile.data.items[0][4].item_uid, ItemUID(4));
+    }
+
+    #[test]
+    fn test_archive_search_index() {
+        let archive = write_test_archive("search_index", |w| w.with_search_index(true));
+        let path = &archive.path;
+        let file_archive = write_test_archive("search_index_file", |w| {
+            w.with_format(ArchiveFormat::SingleFile)
+                .with_search_index(true)
+        });
+
+        let reader = DataSourceArchiveReader::new(path).unwrap();
+        let file_reader = DataSourceArchiveFileReader::new(&file_archive.path).unwrap();
+        let index = reader.search_index.clone().unwrap();
+        assert!(file_reader.archive.search_index().is_some());
+
+        let request = |mode, query: &str| SearchRequest {
+            mode,
+            query: query.to_owned(),
+            field: None,
+            interval: Interval::new(Timestamp(0), Timestamp(1000)),
+            entry_ids: None,
+            max_results: 100,
+        };
+        let uids = |results: Vec<SearchResult>| -> Vec<_> {
+            results.into_iter().map(|r| r.item_uid.0).collect()
+        };
+        for (mode, query, expected) in [
+            (SearchMode::Substring, "Item 3", vec![3]),
+            (SearchMode::Substring, "tem", (0..10).collect()),
+            (SearchMode::Substring, "item 3", vec![]),
+            (SearchMode::CaseInsensitive, "ITEM 7", vec![7]),
+            (SearchMode::WholeWord, "Item 5", vec![5]),
+            (SearchMode::Substring, "Item 42", vec![]),
+            // Not answered by the index, so these scan
+            (SearchMode::Regex, "^Item [12]$", vec![1, 2]),
+            (SearchMode::Substring, "9", vec![9]),
+        ] {
+            let request = request(mode, query);
+            let scan = uids(scan_slot_meta_tiles(&reader, &request).unwrap());
+            assert_eq!(scan, expected, "{:?} {:?}", mode, query);
+            assert_eq!(uids(reader.search(&request).unwrap()), expected);
+            assert_eq!(uids(file_reader.search(&request).unwrap()), expected);
+        }
+
+        // Only candidates' shards are needed, and every token must match
+        let short_request = request(SearchMode::Substring, "m 3");
+        let lookup = index.lookup(&short_request).unwrap();
+        let shard_path = |i: usize| path.join(SEARCH_INDEX_DIR).join(i.to_string());
+        let read_shards = |lookup: &IndexLookup| {
+            (lookup.shards().into_iter())
+                .map(|i| Ok((i, read_data(&shard_path(i))?)))
+                .collect::<Result<BTreeMap<usize, SearchIndexShard>, DataSourceError>>()
+                .unwrap()
+        };
+        assert!(lookup.shards().len() < index.shards.len());
+        let shards = read_shards(&lookup);
+        let tiles = candidates(&short_request, &lookup, &shards).unwrap();
+        assert_eq!(tiles.len(), 1);
+        let ((entry_id, _), item_uids) = tiles.into_iter().next().unwrap();
+        assert_eq!(entry_id, EntryID::root().child(0));
+        assert_eq!(item_uids.into_iter().collect::<Vec<_>>(), vec![ItemUID(3)]);
+
+        // Candidates are limited to tiles overlapping the interval
+        let mut all_request = request(SearchMode::Substring, "tem");
+        let lookup = index.lookup(&all_request).unwrap();
+        let shards = read_shards(&lookup);
+        let all_tiles = candidates(&all_request, &lookup, &shards).unwrap();
+        all_request.interval = Interval::new(Timestamp(0), Timestamp(200));
+        let narrow_tiles = candidates(&all_request, &lookup, &shards).unwrap();
+        assert!(narrow_tiles.len() < all_tiles.len());
+        assert!(narrow_tiles
+            .keys()
+            .all(|(_, tile_id)| tile_id.0.overlaps(all_request.interval)));
+
+        // Long queries keep their rarest tokens, and a token no item
+        // contains is the rarest of all
+        let long_request = request(SearchMode::Substring, "Item 3 and then some more");
+        let lookup = index.lookup(&long_request).unwrap();
+        assert_eq!(lookup.tokens[0].1, None);
+        assert!(uids(reader.search(&long_request).unwrap()).is_empty());
+
+        // Without some of the shards, more tiles are scanned but the
+        // results are the same
+        let item_request = request(SearchMode::Substring, "Item 3");
+        let shards = index.lookup(&item_request).unwrap().shards();
+        remove_file(shard_path(*shards.first().unwrap())).unwrap();
+        assert_eq!(uids(reader.search(&item_request).unwrap()), vec![3]);
+        for i in shards.into_iter().skip(1) {
+            remove_file(shard_path(i)).unwrap();
+        }
+        assert_eq!(uids(reader.search(&item_request).unwrap()), vec![3]);
+
+        // A tile that fails to load costs only its own results
+        let info = reader.fetch_info().unwrap();
+        let tile_id = info.tile_set.tiles.last().unwrap()[0];
+        let tile_path = path.join(TileKind::SlotMeta.name()).join(
+            TileRequestRef {
+                entry_id: &entry_id,
+                tile_id,
+            }
+            .to_slug(),
+        );
+        remove_file(tile_path).unwrap();
+        let results = uids(
+            reader
+                .search(&request(SearchMode::Substring, "tem"))
+                .unwrap(),
+        );
+        assert!(!results.is_empty() && results.len() < 10, "{:?}", results);
+    }
+
+    #[test]
+    fn test_archive_verify() {
+        let archive = write_test_archive("verify", |w| w);
+        let path = &archive.path;
+
+        let report = verify_archive(path).unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.levels.len(), 2);
+        // Summary, slot and slot meta tile for each tile ID
+        assert_eq!(report.levels[0].tiles, 3);
+        assert_eq!(report.levels[1].tiles, 6);
+        assert!(!report.largest_tiles.is_empty());
+
+        // Swap one slot tile for another and delete a meta tile
+        let info = DataSourceArchiveReader::new(path)
+            .unwrap()
+            .fetch_info()
+            .unwrap();
+        let slot = EntryID::root().child(0);
+        let tile_path = |kind: TileKind, tile_id| {
+            let req = TileRequestRef {
+                entry_id: &slot,
+                tile_id,
+            };
+            path.join(kind.name()).join(req.to_slug())
+        };
+        let tiles = &info.tile_set.tiles[1];
+        std::fs::copy(
+            tile_path(TileKind::Slot, tiles[1]),
+            tile_path(TileKind::Slot, tiles[0]),
+        )
+        .unwrap();
+        remove_file(tile_path(TileKind::SlotMeta, tiles[1])).unwrap();
+
+        let report = verify_archive(path).unwrap();
+        assert!(!report.is_ok());
+        let messages: Vec<_> = report.problems.iter().map(|p| p.to_string()).collect();
+        assert_eq!(messages.len(), 4, "{:?}", messages);
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("tile contains data for")));
+        assert!(messages.iter().any(|m| m.contains("outside the tile")));
+        assert!(messages.iter().any(|m| m.contains("but meta tile has")));
+        assert!(report
+            .problems
+            .iter()
+            .any(|p| p.kind == TileKind::SlotMeta && p.tile_id == tiles[1]));
+    }
+
+    #[test]
+    fn test_archive_file_roundtrip() {
+        let archive = write_test_archive("file_roundtrip", |w| {
+            w.with_format(ArchiveFormat::SingleFile)
+        });
+        let path = &archive.path;
+
+        let reader = DataSourceArchiveFileReader::new(path).unwrap();
+        let info = reader.fetch_info().unwrap();
+        assert_eq!(info.tile_set.tiles.len(), 2);
+
+        let slot = EntryID::root().child(0);
+        for tile_id in info.tile_set.tiles.iter().flatten() {
+            let tile = reader.fetch_slot_tile(&slot, *tile_id, true).unwrap();
+            let meta = reader.fetch_slot_meta_tile(&slot, *tile_id, true).unwrap();
+            assert_eq!(tile.tile_id, *tile_id);
+            assert_eq!(tile.data.items[0].len(), meta.data.items[0].len());
+            reader
+                .fetch_summary_tile(&EntryID::root().summary(), *tile_id, true)
+                .unwrap();
+        }
+        let tile_id = info.tile_set.tiles[0][0];
+        assert!(matches!(
+            reader.fetch_slot_tile(&slot.child(0), tile_id, false),
+            Err(DataSourceError::NotFound(..))
+        ));
+
+        // A file that was never finished is recognized, but rejected
+        let mut writer = ArchiveFileWriter::new(File::create(path).unwrap()).unwrap();
+        writer.append(BlobKey::Info, &[1, 2, 3]).unwrap();
+        drop(writer);
+        assert!(is_archive_file(path));
+        assert!(matches!(
+            ArchiveFile::open(path),
+            Err(DataSourceError::Decode(msg)) if msg.contains("incomplete")
+        ));
+    }
+
+    #[test]
+    fn test_archive_file_verify() {
+        let archive =
+            write_test_archive("file_verify", |w| w.with_format(ArchiveFormat::SingleFile));
+        let path = &archive.path;
+
+        let report = verify_archive(path).unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.levels[1].tiles, 6);
+
+        // Rewrite the index without one meta tile, and with a slot tile
+        // that runs into the index
+        let data = std::fs::read(path).unwrap();
+        let word = |i: usize| u64::from_le_bytes(data[i * 8..(i + 1) * 8].try_into().unwrap());
+        let (index_offset, index_len) = (word(2), word(3));
+        let mut index: ArchiveIndex =
+            decode_data(&data[index_offset as usize..(index_offset + index_len) as usize]).unwrap();
+        let meta = (index.tiles.iter())
+            .position(|(kind, ..)| *kind == TileKind::SlotMeta)
+            .unwrap();
+        let (_, _, missing_tile, _) = index.tiles.remove(meta);
+        let (_, _, damaged_tile, blob) = (index.tiles.iter_mut())
+            .find(|(kind, ..)| *kind == TileKind::Slot)
+            .unwrap();
+        blob.len = index_offset - blob.offset + 1;
+        let damaged_tile = *damaged_tile;
+
+        let index = encode_data(&index, 1).unwrap();
+        let mut f = OpenOptions::new().write(true).open(path).unwrap();
+        f.set_len(index_offset).unwrap();
+        f.seek(SeekFrom::End(0)).unwrap();
+        f.write_all(&index).unwrap();
+        f.seek(SeekFrom::Start(24)).unwrap();
+        f.write_all(&(index.len() as u64).to_le_bytes()).unwrap();
+        drop(f);
+
+        let report = verify_archive(path).unwrap();
+        assert!(!report.complete);
+        assert_eq!(report.problems.len(), 2, "{}", report);
+        assert!(report
+            .problems
+            .iter()
+            .any(|p| p.kind == TileKind::SlotMeta && p.tile_id == missing_tile));
+        assert!(report.problems.iter().any(|p| p.kind == TileKind::Slot
+            && p.tile_id == damaged_tile
+            && p.message.contains("out of bounds")));
+    }
+
+    #[test]
+    fn test_archive_invalid_tile() {
+        let archive = write_archive("invalid_tile", TestDataSource::new(), 1, 2, |w| w);
+        let path = &archive.path;
+
+        let reader = DataSourceArchiveReader::new(path).unwrap();
+        let slot = EntryID::root().child(0);
+        let tile_id = TileID(Interval::new(Timestamp(0), Timestamp(10)));
+        assert!(matches!(
+            reader.fetch_slot_tile(&slot, tile_id, false),
+            Err(DataSourceError::BadRequest(..))
+        ));
+
+        let tile_id = reader.fetch_info().unwrap().tile_set.tiles[0][0];
+        assert!(matches!(
+            reader.fetch_slot_tile(&slot.child(0), tile_id, false),
+            Err(DataSourceError::NotFound(..))
+        ));
     }
 }
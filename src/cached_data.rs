@@ -0,0 +1,538 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+use crate::data::{
+    DataSourceError, DataSourceInfo, EntryID, Field, FieldID, InfoUpdate, Item, ItemMeta,
+    SearchRequest, SearchResponse, SlotMetaTile, SlotTile, SummaryTile, TileID, UtilPoint,
+};
+use crate::deferred_data::{CountingDeferredDataSource, DeferredDataSource, TileResponse};
+use crate::http::schema::TileKind;
+use crate::timestamp::Interval;
+
+type TileKey = (TileKind, EntryID, TileID, bool);
+
+#[derive(Debug, Clone)]
+enum CachedTile {
+    Summary(SummaryTile),
+    Slot(SlotTile),
+    SlotMeta(SlotMetaTile),
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TileCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub tiles: usize,
+    pub bytes: usize,
+    pub capacity: usize,
+}
+
+struct CacheEntry {
+    tile: CachedTile,
+    bytes: usize,
+    last_use: u64,
+}
+
+// Least-recently-used cache of tiles, bounded by the (estimated) number of
+// bytes held in memory.
+struct TileCache {
+    capacity: usize,
+    bytes: usize,
+    clock: u64,
+    entries: BTreeMap<TileKey, CacheEntry>,
+    lru: BTreeMap<u64, TileKey>,
+    hits: u64,
+    misses: u64,
+}
+
+fn field_size(field: &Field) -> usize {
+    match field {
+        Field::String(s) => s.len(),
+        Field::ItemLink(link) => link.title.len(),
+        Field::Vec(fields) => fields
+            .iter()
+            .map(|f| size_of::<Field>() + field_size(f))
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn item_meta_size(item: &ItemMeta) -> usize {
+    let fields: usize = item
+        .fields
+        .iter()
+        .map(|(_, f)| size_of::<(FieldID, Field)>() + field_size(f))
+        .sum();
+    size_of::<ItemMeta>() + item.title.len() + fields
+}
+
+impl CachedTile {
+    fn estimate_size(&self) -> usize {
+        match self {
+            CachedTile::Summary(tile) => {
+                size_of::<SummaryTile>() + tile.data.utilization.len() * size_of::<UtilPoint>()
+            }
+            CachedTile::Slot(tile) => {
+                let rows = &tile.data.items;
+                let items: usize = rows.iter().map(|row| row.len()).sum();
+                size_of::<SlotTile>()
+                    + rows.len() * size_of::<Vec<Item>>()
+                    + items * size_of::<Item>()
+            }
+            CachedTile::SlotMeta(tile) => {
+                let rows = &tile.data.items;
+                let items: usize = rows.iter().flatten().map(item_meta_size).sum();
+                size_of::<SlotMetaTile>() + rows.len() * size_of::<Vec<ItemMeta>>() + items
+            }
+        }
+    }
+}
+
+impl TileCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            bytes: 0,
+            clock: 0,
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &TileKey) -> Option<CachedTile> {
+        let now = self.tick();
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+
+        // Move to the back of the LRU order
+        self.lru.remove(&entry.last_use);
+        self.lru.insert(now, key.clone());
+        entry.last_use = now;
+
+        Some(entry.tile.clone())
+    }
+
+    fn insert(&mut self, key: TileKey, tile: CachedTile) {
+        let bytes = tile.estimate_size();
+        if bytes > self.capacity {
+            // Would evict everything else and still not fit
+            return;
+        }
+
+        self.remove(&key);
+        while self.bytes + bytes > self.capacity {
+            let (_, oldest) = self.lru.pop_first().unwrap();
+            let entry = self.entries.remove(&oldest).unwrap();
+            self.bytes -= entry.bytes;
+        }
+
+        let now = self.tick();
+        self.lru.insert(now, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                tile,
+                bytes,
+                last_use: now,
+            },
+        );
+        self.bytes += bytes;
+    }
+
+    fn remove(&mut self, key: &TileKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_use);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    fn remove_overlapping(&mut self, interval: Interval) {
+        let keys: Vec<_> = (self.entries.keys())
+            .filter(|(_, _, tile_id, _)| tile_id.0.overlaps(interval))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    fn stats(&self) -> TileCacheStats {
+        TileCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            tiles: self.entries.len(),
+            bytes: self.bytes,
+            capacity: self.capacity,
+        }
+    }
+}
+
+pub struct CachingDeferredDataSource<T: DeferredDataSource> {
+    data_source: T,
+    cache: TileCache,
+    // Cache hits waiting to be picked up by the next get_* call
+    summary_tiles: Vec<TileResponse<SummaryTile>>,
+    slot_tiles: Vec<TileResponse<SlotTile>>,
+    slot_meta_tiles: Vec<TileResponse<SlotMetaTile>>,
+}
+
+impl<T: DeferredDataSource> CachingDeferredDataSource<T> {
+    pub fn new(data_source: T, capacity: usize) -> Self {
+        Self {
+            data_source,
+            cache: TileCache::new(capacity),
+            summary_tiles: Vec::new(),
+            slot_tiles: Vec::new(),
+            slot_meta_tiles: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> TileCacheStats {
+        self.cache.stats()
+    }
+
+    // Drop cached tiles overlapping the interval, e.g., the part of a live
+    // profile that has grown since they were fetched
+    pub fn invalidate(&mut self, interval: Interval) {
+        self.cache.remove_overlapping(interval);
+    }
+
+    fn ready_hits(&self) -> u64 {
+        (self.summary_tiles.len() + self.slot_tiles.len() + self.slot_meta_tiles.len()) as u64
+    }
+
+    fn lookup(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Option<CachedTile> {
+        self.cache.get(&(kind, entry_id.clone(), tile_id, full))
+    }
+
+    // Responses say whether they were full, so they can be cached in
+    // whatever order they arrive
+    fn store<U>(&mut self, kind: TileKind, response: &TileResponse<U>, tile: CachedTile) {
+        let key = (
+            kind,
+            response.entry_id.clone(),
+            response.tile_id,
+            response.full,
+        );
+        self.cache.insert(key, tile);
+    }
+}
+
+fn drop_responses<U>(
+    responses: &mut Vec<TileResponse<U>>,
+    entry_id: &EntryID,
+    tile_id: TileID,
+    full: bool,
+) {
+    responses.retain(|response| {
+        !(response.entry_id == *entry_id && response.tile_id == tile_id && response.full == full)
+    });
+}
+
+impl<T: DeferredDataSource> CachingDeferredDataSource<CountingDeferredDataSource<T>> {
+    pub fn outstanding_requests(&self) -> u64 {
+        self.data_source.outstanding_requests() + self.ready_hits()
+    }
+}
+
+impl<T: DeferredDataSource> DeferredDataSource for CachingDeferredDataSource<T> {
+    fn fetch_info(&mut self) {
+        self.data_source.fetch_info()
+    }
+
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
+        self.data_source.get_infos()
+    }
+
+    fn fetch_summary_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
+        match self.lookup(TileKind::Summary, entry_id, tile_id, full) {
+            Some(CachedTile::Summary(tile)) => {
+                let response = TileResponse::new(entry_id, tile_id, full, Ok(tile));
+                self.summary_tiles.push(response);
+            }
+            Some(_) => unreachable!(),
+            None => self.data_source.fetch_summary_tile(entry_id, tile_id, full),
+        }
+    }
+
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
+        let mut result = std::mem::take(&mut self.summary_tiles);
+        for response in self.data_source.get_summary_tiles() {
+            if let Ok(tile) = &response.result {
+                let cached = CachedTile::Summary(tile.clone());
+                self.store(TileKind::Summary, &response, cached);
+            }
+            result.push(response);
+        }
+        result
+    }
+
+    fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
+        match self.lookup(TileKind::Slot, entry_id, tile_id, full) {
+            Some(CachedTile::Slot(tile)) => {
+                let response = TileResponse::new(entry_id, tile_id, full, Ok(tile));
+                self.slot_tiles.push(response);
+            }
+            Some(_) => unreachable!(),
+            None => self.data_source.fetch_slot_tile(entry_id, tile_id, full),
+        }
+    }
+
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
+        let mut result = std::mem::take(&mut self.slot_tiles);
+        for response in self.data_source.get_slot_tiles() {
+            if let Ok(tile) = &response.result {
+                let cached = CachedTile::Slot(tile.clone());
+                self.store(TileKind::Slot, &response, cached);
+            }
+            result.push(response);
+        }
+        result
+    }
+
+    fn fetch_slot_meta_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
+        match self.lookup(TileKind::SlotMeta, entry_id, tile_id, full) {
+            Some(CachedTile::SlotMeta(tile)) => {
+                let response = TileResponse::new(entry_id, tile_id, full, Ok(tile));
+                self.slot_meta_tiles.push(response);
+            }
+            Some(_) => unreachable!(),
+            None => self
+                .data_source
+                .fetch_slot_meta_tile(entry_id, tile_id, full),
+        }
+    }
+
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
+        let mut result = std::mem::take(&mut self.slot_meta_tiles);
+        for response in self.data_source.get_slot_meta_tiles() {
+            if let Ok(tile) = &response.result {
+                let cached = CachedTile::SlotMeta(tile.clone());
+                self.store(TileKind::SlotMeta, &response, cached);
+            }
+            result.push(response);
+        }
+        result
+    }
+
+    // Cache hits that haven't been picked up yet are dropped along with
+    // the requests to the underlying source
+    fn cancel_tile(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        let hits = self.ready_hits();
+        match kind {
+            TileKind::Summary => drop_responses(&mut self.summary_tiles, entry_id, tile_id, full),
+            TileKind::Slot => drop_responses(&mut self.slot_tiles, entry_id, tile_id, full),
+            TileKind::SlotMeta => {
+                drop_responses(&mut self.slot_meta_tiles, entry_id, tile_id, full)
+            }
+        }
+        let cancelled = hits - self.ready_hits();
+        cancelled + self.data_source.cancel_tile(kind, entry_id, tile_id, full)
+    }
+
+    // Search results aren't cached, since they're only requested when the
+    // search changes
+    fn supports_search(&self) -> bool {
+        self.data_source.supports_search()
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        self.data_source.fetch_search(request)
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        self.data_source.get_search_results()
+    }
+
+    fn supports_info_updates(&self) -> bool {
+        self.data_source.supports_info_updates()
+    }
+
+    fn fetch_info_update(&mut self, since: u64) {
+        self.data_source.fetch_info_update(since)
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        self.data_source.get_info_updates()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::{SummaryTileData, UtilPoint};
+    use crate::timestamp::Timestamp;
+
+    fn tile_id(i: i64) -> TileID {
+        TileID(Interval::new(Timestamp(i * 10), Timestamp((i + 1) * 10)))
+    }
+
+    fn summary_tile(i: i64, points: usize) -> SummaryTile {
+        SummaryTile {
+            entry_id: EntryID::root().summary(),
+            tile_id: tile_id(i),
+            data: SummaryTileData {
+                utilization: vec![UtilPoint::default(); points],
+            },
+        }
+    }
+
+    fn summary_key(i: i64) -> TileKey {
+        (
+            TileKind::Summary,
+            EntryID::root().summary(),
+            tile_id(i),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let tile_size = CachedTile::Summary(summary_tile(0, 10)).estimate_size();
+        let mut cache = TileCache::new(2 * tile_size);
+        cache.insert(summary_key(0), CachedTile::Summary(summary_tile(0, 10)));
+        cache.insert(summary_key(1), CachedTile::Summary(summary_tile(1, 10)));
+
+        // Touch tile 0 so that tile 1 becomes the oldest
+        assert!(cache.get(&summary_key(0)).is_some());
+        cache.insert(summary_key(2), CachedTile::Summary(summary_tile(2, 10)));
+
+        assert!(cache.get(&summary_key(0)).is_some());
+        assert!(cache.get(&summary_key(1)).is_none());
+        assert!(cache.get(&summary_key(2)).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.tiles, 2);
+        assert_eq!(stats.bytes, 2 * tile_size);
+    }
+
+    #[test]
+    fn test_cache_rejects_oversized_tile() {
+        let mut cache = TileCache::new(1);
+        cache.insert(summary_key(0), CachedTile::Summary(summary_tile(0, 10)));
+        assert_eq!(cache.stats().tiles, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[derive(Default)]
+    struct TestDataSource {
+        requests: u64,
+        summary_tiles: Vec<TileResponse<SummaryTile>>,
+    }
+
+    impl DeferredDataSource for TestDataSource {
+        fn fetch_info(&mut self) {}
+        fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
+            Vec::new()
+        }
+        fn fetch_summary_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
+            self.requests += 1;
+            let points = if full { 8 } else { 4 };
+            let tile = summary_tile(tile_id.0.start.0 / 10, points);
+            (self.summary_tiles).push(TileResponse::new(entry_id, tile_id, full, Ok(tile)));
+        }
+        // Responses come back in reverse order
+        fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
+            let mut tiles = std::mem::take(&mut self.summary_tiles);
+            tiles.reverse();
+            tiles
+        }
+        fn fetch_slot_tile(&mut self, _entry_id: &EntryID, _tile_id: TileID, _full: bool) {}
+        fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
+            Vec::new()
+        }
+        fn fetch_slot_meta_tile(&mut self, _entry_id: &EntryID, _tile_id: TileID, _full: bool) {}
+        fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_caching_data_source_reuses_tiles() {
+        let entry_id = EntryID::root().summary();
+        let mut source = CachingDeferredDataSource::new(TestDataSource::default(), 1 << 20);
+
+        source.fetch_summary_tile(&entry_id, tile_id(0), false);
+        assert_eq!(source.get_summary_tiles().len(), 1);
+
+        // Same tile again is served from the cache
+        source.fetch_summary_tile(&entry_id, tile_id(0), false);
+        assert_eq!(source.get_summary_tiles().len(), 1);
+        assert_eq!(source.data_source.requests, 1);
+
+        // A full request is a different tile
+        source.fetch_summary_tile(&entry_id, tile_id(0), true);
+        assert_eq!(source.get_summary_tiles().len(), 1);
+        assert_eq!(source.data_source.requests, 2);
+
+        let stats = source.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.tiles, 2);
+    }
+
+    #[test]
+    fn test_caching_data_source_out_of_order() {
+        let entry_id = EntryID::root().summary();
+        let mut source = CachingDeferredDataSource::new(TestDataSource::default(), 1 << 20);
+
+        source.fetch_summary_tile(&entry_id, tile_id(0), false);
+        source.fetch_summary_tile(&entry_id, tile_id(0), true);
+        assert_eq!(source.get_summary_tiles().len(), 2);
+
+        // Each is cached under the request it answers
+        for full in [false, true] {
+            source.fetch_summary_tile(&entry_id, tile_id(0), full);
+            let tiles = source.get_summary_tiles();
+            let tile = tiles[0].result.as_ref().unwrap();
+            let points = if full { 8 } else { 4 };
+            assert_eq!(tile.data.utilization.len(), points);
+        }
+        assert_eq!(source.data_source.requests, 2);
+    }
+
+    #[test]
+    fn test_caching_data_source_cancels_hits() {
+        let entry_id = EntryID::root().summary();
+        let mut source = CachingDeferredDataSource::new(TestDataSource::default(), 1 << 20);
+
+        source.fetch_summary_tile(&entry_id, tile_id(0), false);
+        source.get_summary_tiles();
+
+        // The hit is queued until the next get, and cancelled with the tile
+        // (but only for the request it answers)
+        source.fetch_summary_tile(&entry_id, tile_id(0), false);
+        source.fetch_summary_tile(&entry_id, tile_id(1), false);
+        let kind = TileKind::Summary;
+        assert_eq!(source.cancel_tile(kind, &entry_id, tile_id(0), true), 0);
+        assert_eq!(source.cancel_tile(kind, &entry_id, tile_id(0), false), 1);
+        let tiles = source.get_summary_tiles();
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].tile_id, tile_id(1));
+    }
+}
@@ -0,0 +1,980 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::archive_data::{merge_threshold, walk_entry_list, RowRetiler};
+use crate::data::{
+    Color32, DataSource, DataSourceError, DataSourceInfo, EntryID, EntryIndex, EntryInfo, Field,
+    FieldID, FieldSchema, Item, ItemMeta, ItemUID, SearchRequest, SearchResult, SlotMetaTile,
+    SlotMetaTileData, SlotTile, SlotTileData, SummaryTile, SummaryTileData, TileID, TileSet,
+    UtilPoint,
+};
+use crate::deferred_data::{CountingDeferredDataSource, DeferredDataSource};
+use crate::search::scan_slot_meta_tiles;
+use crate::timestamp::{Interval, Timestamp};
+
+// Importer for the Chrome Trace Event format (as produced by
+// chrome://tracing, Perfetto, and many other tools). Each pid becomes a
+// panel (with a utilization summary) and each tid becomes a slot, with
+// nested events stacked into rows by depth.
+//
+// Supported phases: complete events (X), begin/end pairs (B/E), and the
+// process_name/thread_name metadata events (M). Everything else is ignored.
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(untagged)]
+enum TraceID {
+    Number(i64),
+    String(String),
+}
+
+impl Default for TraceID {
+    fn default() -> Self {
+        TraceID::Number(0)
+    }
+}
+
+impl std::fmt::Display for TraceID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceID::Number(n) => write!(f, "{n}"),
+            TraceID::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TraceEvent {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    cat: String,
+    ph: String,
+    // Microseconds
+    #[serde(default)]
+    ts: f64,
+    #[serde(default)]
+    dur: Option<f64>,
+    #[serde(default)]
+    pid: TraceID,
+    #[serde(default)]
+    tid: TraceID,
+    #[serde(default)]
+    args: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TraceFile {
+    Object {
+        #[serde(rename = "traceEvents")]
+        trace_events: Vec<TraceEvent>,
+    },
+    Array(Vec<TraceEvent>),
+}
+
+#[derive(Debug, Clone)]
+struct Span {
+    start: Timestamp,
+    stop: Timestamp,
+    event: TraceEvent,
+}
+
+#[derive(Debug, Default)]
+struct Thread {
+    name: Option<String>,
+    spans: Vec<Span>,
+    // Open B events, innermost last
+    open: Vec<TraceEvent>,
+}
+
+#[derive(Debug, Default)]
+struct Process {
+    name: Option<String>,
+    threads: BTreeMap<TraceID, Thread>,
+}
+
+#[derive(Debug)]
+struct SlotData {
+    items: Vec<Vec<Item>>,
+    item_metas: Vec<Vec<ItemMeta>>,
+}
+
+pub struct ChromeTraceDataSource {
+    info: DataSourceInfo,
+    slots: BTreeMap<EntryID, SlotData>,
+    // Busy intervals of each thread in a process (merged, sorted)
+    summaries: BTreeMap<EntryID, Vec<Vec<Interval>>>,
+}
+
+fn timestamp(us: f64) -> Timestamp {
+    Timestamp((us * 1_000.0).round() as i64)
+}
+
+fn color(name: &str) -> Color32 {
+    // Stable color per event name (FNV-1a)
+    const COLORS: [Color32; 10] = [
+        Color32::from_rgb(0x4e, 0x79, 0xa7),
+        Color32::from_rgb(0xf2, 0x8e, 0x2b),
+        Color32::from_rgb(0xe1, 0x57, 0x59),
+        Color32::from_rgb(0x76, 0xb7, 0xb2),
+        Color32::from_rgb(0x59, 0xa1, 0x4f),
+        Color32::from_rgb(0xed, 0xc9, 0x48),
+        Color32::from_rgb(0xb0, 0x7a, 0xa1),
+        Color32::from_rgb(0xff, 0x9d, 0xa7),
+        Color32::from_rgb(0x9c, 0x75, 0x5f),
+        Color32::from_rgb(0xba, 0xb0, 0xac),
+    ];
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in name.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    COLORS[(hash % COLORS.len() as u64) as usize]
+}
+
+fn json_field(value: &Value) -> Field {
+    match value {
+        Value::Null => Field::Empty,
+        Value::Bool(b) => Field::String(b.to_string()),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Field::I64(i)
+            } else if let Some(u) = n.as_u64() {
+                Field::U64(u)
+            } else {
+                Field::F64(n.as_f64().unwrap())
+            }
+        }
+        Value::String(s) => Field::String(s.clone()),
+        Value::Array(values) => Field::Vec(values.iter().map(json_field).collect()),
+        Value::Object(_) => Field::String(value.to_string()),
+    }
+}
+
+// Assign each span a depth such that spans at the same depth never
+// overlap. Properly nested spans end up one row below their parent.
+fn assign_depths(spans: &mut [Span]) -> Vec<usize> {
+    spans.sort_by_key(|span| (span.start, std::cmp::Reverse(span.stop)));
+    let mut stack: Vec<Timestamp> = Vec::new();
+    let mut depths = Vec::with_capacity(spans.len());
+    for span in spans.iter() {
+        while stack.last().map_or(false, |stop| *stop <= span.start) {
+            stack.pop();
+        }
+        depths.push(stack.len());
+        stack.push(span.stop);
+    }
+    depths
+}
+
+fn busy_intervals(spans: &[Span], depths: &[usize]) -> Vec<Interval> {
+    let mut result: Vec<Interval> = Vec::new();
+    for (span, depth) in spans.iter().zip(depths) {
+        if *depth != 0 {
+            continue;
+        }
+        let interval = Interval::new(span.start, span.stop);
+        match result.last_mut() {
+            Some(last) if last.stop >= interval.start => {
+                last.stop = last.stop.max(interval.stop);
+            }
+            _ => result.push(interval),
+        }
+    }
+    result
+}
+
+impl ChromeTraceDataSource {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DataSourceError> {
+        let data = std::fs::read(path)?;
+        Self::from_slice(&data)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self, DataSourceError> {
+        let file: TraceFile =
+            serde_json::from_slice(data).map_err(|e| DataSourceError::Decode(e.to_string()))?;
+        let events = match file {
+            TraceFile::Object { trace_events } => trace_events,
+            TraceFile::Array(events) => events,
+        };
+        Self::from_events(events)
+    }
+
+    fn from_events(events: Vec<TraceEvent>) -> Result<Self, DataSourceError> {
+        let mut processes: BTreeMap<TraceID, Process> = BTreeMap::new();
+        let mut last_ts = Timestamp(0);
+        for event in events {
+            let ts = timestamp(event.ts);
+            last_ts = last_ts.max(ts);
+            match event.ph.as_str() {
+                "X" => {
+                    let stop = timestamp(event.ts + event.dur.unwrap_or(0.0));
+                    last_ts = last_ts.max(stop);
+                    let process = processes.entry(event.pid.clone()).or_default();
+                    let thread = process.threads.entry(event.tid.clone()).or_default();
+                    thread.spans.push(Span {
+                        start: ts,
+                        stop,
+                        event,
+                    });
+                }
+                "B" => {
+                    let process = processes.entry(event.pid.clone()).or_default();
+                    let thread = process.threads.entry(event.tid.clone()).or_default();
+                    thread.open.push(event);
+                }
+                "E" => {
+                    let process = processes.entry(event.pid.clone()).or_default();
+                    let thread = process.threads.entry(event.tid.clone()).or_default();
+                    // The name is optional on E events, in which case the
+                    // innermost B is the one that ends
+                    let index = thread
+                        .open
+                        .iter()
+                        .rposition(|begin| event.name.is_empty() || begin.name == event.name)
+                        .ok_or_else(|| {
+                            DataSourceError::Decode(format!(
+                                "E event '{}' at {}us in pid {} tid {} has no matching B event",
+                                event.name, event.ts, event.pid, event.tid
+                            ))
+                        })?;
+                    let mut begin = thread.open.remove(index);
+                    // Args may be supplied on either end
+                    begin.args.extend(event.args);
+                    thread.spans.push(Span {
+                        start: timestamp(begin.ts),
+                        stop: ts,
+                        event: begin,
+                    });
+                }
+                "M" => {
+                    let name = event.args.get("name").and_then(|x| x.as_str());
+                    let process = processes.entry(event.pid.clone()).or_default();
+                    match (event.name.as_str(), name) {
+                        ("process_name", Some(name)) => {
+                            process.name = Some(name.to_owned());
+                        }
+                        ("thread_name", Some(name)) => {
+                            let thread = process.threads.entry(event.tid.clone()).or_default();
+                            thread.name = Some(name.to_owned());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Close anything left open at the end of the trace
+        for process in processes.values_mut() {
+            for thread in process.threads.values_mut() {
+                while let Some(begin) = thread.open.pop() {
+                    thread.spans.push(Span {
+                        start: timestamp(begin.ts),
+                        stop: last_ts,
+                        event: begin,
+                    });
+                }
+            }
+        }
+
+        // Shift the trace to start at zero, since trace timestamps are
+        // often relative to some arbitrary point (e.g., boot)
+        let first_ts = processes
+            .values()
+            .flat_map(|p| p.threads.values())
+            .flat_map(|t| t.spans.iter())
+            .map(|span| span.start)
+            .min()
+            .unwrap_or(Timestamp(0));
+        let interval = Interval::new(Timestamp(0), Timestamp((last_ts.0 - first_ts.0).max(1)));
+
+        let mut field_schema = FieldSchema::new();
+        let category_field = field_schema.insert("Category".to_owned(), true);
+        let interval_field = field_schema.insert("Interval".to_owned(), false);
+        let mut arg_fields: BTreeMap<String, FieldID> = BTreeMap::new();
+
+        let mut next_uid = ItemUID(0);
+        let mut slots = BTreeMap::new();
+        let mut summaries = BTreeMap::new();
+        let mut process_infos = Vec::new();
+        for (pid_index, (pid, process)) in processes.into_iter().enumerate() {
+            let process_id = EntryID::root().child(pid_index as u64);
+            let mut thread_infos = Vec::new();
+            let mut thread_busy = Vec::new();
+            for (tid_index, (tid, mut thread)) in process.threads.into_iter().enumerate() {
+                let thread_id = process_id.child(tid_index as u64);
+                for span in &mut thread.spans {
+                    span.start = Timestamp(span.start.0 - first_ts.0);
+                    span.stop = Timestamp(span.stop.0 - first_ts.0).max(span.start);
+                }
+                let depths = assign_depths(&mut thread.spans);
+                thread_busy.push(busy_intervals(&thread.spans, &depths));
+
+                let max_rows = depths.iter().max().map_or(0, |d| d + 1);
+                let mut items = vec![Vec::new(); max_rows];
+                let mut item_metas = vec![Vec::new(); max_rows];
+                for (span, depth) in thread.spans.into_iter().zip(depths) {
+                    let item_uid = next_uid;
+                    next_uid.0 += 1;
+
+                    let interval = Interval::new(span.start, span.stop);
+                    let mut fields = vec![(interval_field, Field::Interval(interval))];
+                    if !span.event.cat.is_empty() {
+                        fields.push((category_field, Field::String(span.event.cat)));
+                    }
+                    for (key, value) in &span.event.args {
+                        let field_id = *arg_fields
+                            .entry(key.clone())
+                            .or_insert_with(|| field_schema.insert(key.clone(), true));
+                        fields.push((field_id, json_field(value)));
+                    }
+
+                    // Outermost spans go at the top (i.e., the last row)
+                    let row = max_rows - depth - 1;
+                    items[row].push(Item {
+                        item_uid,
+                        interval,
+                        color: color(&span.event.name),
+                    });
+                    item_metas[row].push(ItemMeta {
+                        item_uid,
+                        original_interval: interval,
+                        title: span.event.name,
+                        fields,
+                    });
+                }
+                slots.insert(thread_id, SlotData { items, item_metas });
+
+                thread_infos.push(EntryInfo::Slot {
+                    short_name: format!("t{tid}"),
+                    long_name: thread.name.unwrap_or_else(|| format!("Thread {tid}")),
+                    max_rows: max_rows as u64,
+                });
+            }
+            summaries.insert(process_id.summary(), thread_busy);
+
+            let long_name = match process.name {
+                Some(name) => format!("{name} (pid {pid})"),
+                None => format!("Process {pid}"),
+            };
+            process_infos.push(EntryInfo::Panel {
+                short_name: format!("p{pid}"),
+                long_name,
+                summary: Some(Box::new(EntryInfo::Summary {
+                    color: Color32::BLUE,
+                })),
+                slots: thread_infos,
+            });
+        }
+
+        let info = DataSourceInfo {
+            entry_info: EntryInfo::Panel {
+                short_name: "root".to_owned(),
+                long_name: "root".to_owned(),
+                summary: None,
+                slots: process_infos,
+            },
+            interval,
+            tile_set: TileSet::default(),
+            field_schema,
+        };
+
+        Ok(Self {
+            info,
+            slots,
+            summaries,
+        })
+    }
+
+    fn slot(&self, entry_id: &EntryID) -> Result<&SlotData, DataSourceError> {
+        self.slots
+            .get(entry_id)
+            .ok_or_else(|| DataSourceError::NotFound(format!("no slot {:?}", entry_id)))
+    }
+
+    // Items overlapping the tile, sliced to fit. Unless full, runs of
+    // sub-pixel items are merged the same way archive retiling does.
+    fn slot_tiles(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<(SlotTile, SlotMetaTile), DataSourceError> {
+        let slot = self.slot(entry_id)?;
+        let tile = tile_id.0;
+        let threshold = merge_threshold(tile_id, full);
+        let (items, metas) = slot
+            .items
+            .iter()
+            .zip(&slot.item_metas)
+            .map(|(row, row_meta)| {
+                // Items in a row never overlap, so they're sorted by stop as
+                // well as by start
+                let first = row.partition_point(|item| item.interval.stop < tile.start);
+                let mut retiler = RowRetiler::default();
+                for (item, item_meta) in row[first..].iter().zip(&row_meta[first..]) {
+                    if item.interval.start >= tile.stop {
+                        break;
+                    }
+                    let mut item = item.clone();
+                    item.interval = item.interval.intersection(tile);
+                    retiler.push(item, item_meta.clone(), threshold);
+                }
+                retiler.finish()
+            })
+            .unzip();
+        Ok((
+            SlotTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotTileData { items },
+            },
+            SlotMetaTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotMetaTileData { items: metas },
+            },
+        ))
+    }
+}
+
+impl DataSource for ChromeTraceDataSource {
+    fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+        Ok(self.info.clone())
+    }
+
+    fn fetch_summary_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SummaryTile, DataSourceError> {
+        const BINS: i64 = 256;
+
+        let threads = self
+            .summaries
+            .get(entry_id)
+            .ok_or_else(|| DataSourceError::NotFound(format!("no summary {:?}", entry_id)))?;
+
+        // Average the fraction of each bin that threads spend busy
+        let tile = tile_id.0;
+        let bin_ns = (tile.duration_ns() / BINS).max(1);
+        let bins = (tile.duration_ns() + bin_ns - 1) / bin_ns;
+        let mut busy = vec![0i64; bins as usize];
+        for intervals in threads {
+            let first = intervals.partition_point(|i| i.stop <= tile.start);
+            for interval in &intervals[first..] {
+                if interval.start >= tile.stop {
+                    break;
+                }
+                let interval = interval.intersection(tile);
+                let first_bin = (interval.start.0 - tile.start.0) / bin_ns;
+                let last_bin = ((interval.stop.0 - tile.start.0 - 1) / bin_ns).min(bins - 1);
+                for bin in first_bin..=last_bin {
+                    let bin_start = Timestamp(tile.start.0 + bin * bin_ns);
+                    let bin_stop = Timestamp(bin_start.0 + bin_ns);
+                    let overlap = interval.intersection(Interval::new(bin_start, bin_stop));
+                    busy[bin as usize] += overlap.duration_ns().max(0);
+                }
+            }
+        }
+
+        let count = threads.len().max(1) as f32;
+        let utilization = busy
+            .into_iter()
+            .enumerate()
+            .map(|(bin, busy)| UtilPoint {
+                time: Timestamp(tile.start.0 + bin as i64 * bin_ns + bin_ns / 2),
+                util: busy as f32 / (bin_ns as f32 * count),
+            })
+            .collect();
+
+        Ok(SummaryTile {
+            entry_id: entry_id.clone(),
+            tile_id,
+            data: SummaryTileData { utilization },
+        })
+    }
+
+    fn fetch_slot_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotTile, DataSourceError> {
+        let (tile, _) = self.slot_tiles(entry_id, tile_id, full)?;
+        Ok(tile)
+    }
+
+    fn fetch_slot_meta_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotMetaTile, DataSourceError> {
+        let (_, meta_tile) = self.slot_tiles(entry_id, tile_id, full)?;
+        Ok(meta_tile)
+    }
+
+    fn search(&self, request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        scan_slot_meta_tiles(self, request)
+    }
+}
+
+// Exporter: walks any data source at full resolution and writes it out in
+// the Chrome Trace Event format. Panels become processes, slots become
+// threads (with rows as nested slices), item fields become args and
+// summaries become counter tracks.
+
+pub struct ChromeTraceWriter<T: DeferredDataSource> {
+    data_source: CountingDeferredDataSource<T>,
+    path: PathBuf,
+}
+
+fn us(ts: Timestamp) -> f64 {
+    ts.0 as f64 / 1_000.0
+}
+
+fn field_json(field: &Field) -> Value {
+    match field {
+        Field::I64(x) => json!(x),
+        Field::U64(x) => json!(x),
+        Field::F64(x) => json!(x),
+        Field::String(x) => json!(x),
+        Field::Interval(x) => json!(x.to_string()),
+        Field::ItemLink(x) => json!({"item_uid": x.item_uid.0, "title": x.title}),
+        Field::Vec(x) => Value::Array(x.iter().map(field_json).collect()),
+        Field::Empty => Value::Null,
+    }
+}
+
+// Assign items (sorted by start, outermost first) to threads so that the
+// items in each thread nest. Rows of a slot need not nest, so an item that
+// partially overlaps an enclosing one in every thread so far starts a new
+// thread.
+fn assign_threads(items: &[(usize, ItemMeta)]) -> Vec<usize> {
+    // Stops of the open items in each thread, innermost last
+    let mut threads: Vec<Vec<Timestamp>> = Vec::new();
+    let mut result = Vec::with_capacity(items.len());
+    for (_, item) in items {
+        let interval = item.original_interval;
+        let mut index = None;
+        for (i, stack) in threads.iter_mut().enumerate() {
+            while stack.last().map_or(false, |stop| *stop <= interval.start) {
+                stack.pop();
+            }
+            if index.is_none() && stack.last().map_or(true, |stop| *stop >= interval.stop) {
+                index = Some(i);
+            }
+        }
+        let index = index.unwrap_or_else(|| {
+            threads.push(Vec::new());
+            threads.len() - 1
+        });
+        threads[index].push(interval.stop);
+        result.push(index);
+    }
+    result
+}
+
+struct EventWriter<W: Write> {
+    out: W,
+    first: bool,
+}
+
+impl<W: Write> EventWriter<W> {
+    fn new(mut out: W) -> io::Result<Self> {
+        write!(out, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        Ok(Self { out, first: true })
+    }
+
+    fn event(&mut self, event: Value) -> io::Result<()> {
+        if !self.first {
+            write!(self.out, ",")?;
+        }
+        self.first = false;
+        writeln!(self.out)?;
+        serde_json::to_writer(&mut self.out, &event)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        writeln!(self.out, "\n]}}")?;
+        self.out.flush()
+    }
+}
+
+impl<T: DeferredDataSource> ChromeTraceWriter<T> {
+    pub fn new(data_source: T, path: impl AsRef<Path>) -> Self {
+        Self {
+            data_source: CountingDeferredDataSource::new(data_source),
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    pub fn write(self) -> Result<(), DataSourceError> {
+        let f = BufWriter::new(File::create(&self.path)?);
+        self.write_to(f)
+    }
+
+    pub fn write_to(mut self, out: impl Write) -> Result<(), DataSourceError> {
+        self.data_source.fetch_info();
+        let mut info = None;
+        while info.is_none() {
+            // We requested this once, so we know we'll get zero or one result
+            info = self.data_source.get_infos().pop();
+        }
+        let info = info.unwrap()?;
+
+        // Fetch at the finest level available so that we see every item
+        let tile_ids = info
+            .tile_set
+            .tiles
+            .last()
+            .cloned()
+            .unwrap_or_else(|| vec![TileID(info.interval)]);
+
+        let mut out = EventWriter::new(out)?;
+        let mut pids: BTreeMap<EntryID, u64> = BTreeMap::new();
+        // Next free tid in each process
+        let mut next_tids: BTreeMap<u64, u64> = BTreeMap::new();
+        for entry_id in walk_entry_list(&info.entry_info) {
+            let parent = entry_id.parent().unwrap();
+            let pid = match pids.get(&parent) {
+                Some(pid) => *pid,
+                None => {
+                    let pid = pids.len() as u64;
+                    let Some(EntryInfo::Panel { long_name, .. }) = info.entry_info.get(&parent)
+                    else {
+                        unreachable!();
+                    };
+                    out.event(json!({
+                        "name": "process_name", "ph": "M", "pid": pid,
+                        "args": {"name": long_name},
+                    }))?;
+                    out.event(json!({
+                        "name": "process_sort_index", "ph": "M", "pid": pid,
+                        "args": {"sort_index": pid},
+                    }))?;
+                    pids.insert(parent, pid);
+                    pid
+                }
+            };
+
+            for tile_id in &tile_ids {
+                match entry_id.last_index().unwrap() {
+                    EntryIndex::Summary => {
+                        self.data_source
+                            .fetch_summary_tile(&entry_id, *tile_id, true);
+                    }
+                    EntryIndex::Slot(..) => {
+                        self.data_source
+                            .fetch_slot_meta_tile(&entry_id, *tile_id, true);
+                    }
+                }
+            }
+
+            // Items that straddle tiles show up more than once. Tiles may
+            // arrive in any order, since both are keyed.
+            let mut points = BTreeMap::new();
+            let mut items = BTreeMap::new();
+            while self.data_source.outstanding_requests() > 0 {
+                for tile in self.data_source.get_summary_tiles() {
+                    for point in tile.result?.data.utilization {
+                        points.insert(point.time, point.util);
+                    }
+                }
+                for tile in self.data_source.get_slot_meta_tiles() {
+                    let rows = tile.result?.data.items;
+                    let count = rows.len();
+                    for (row, row_items) in rows.into_iter().enumerate() {
+                        for item in row_items {
+                            // Reverse rows because we're in screen space
+                            items.insert(item.item_uid, (count - row - 1, item));
+                        }
+                    }
+                }
+            }
+
+            match info.entry_info.get(&entry_id) {
+                Some(EntryInfo::Summary { .. }) => {
+                    for (time, util) in points {
+                        out.event(json!({
+                            "name": "Utilization", "ph": "C", "pid": pid, "ts": us(time),
+                            "args": {"utilization": util},
+                        }))?;
+                    }
+                }
+                Some(EntryInfo::Slot { long_name, .. }) => {
+                    // Outer slices must come first so that the importer
+                    // (or any other reader) sees them nest
+                    let mut items: Vec<_> = items.into_values().collect();
+                    items.sort_by_key(|(row, item)| {
+                        let interval = item.original_interval;
+                        (interval.start, std::cmp::Reverse(interval.stop), *row)
+                    });
+                    let threads = assign_threads(&items);
+
+                    let next_tid = next_tids.entry(pid).or_default();
+                    let first_tid = *next_tid;
+                    *next_tid += threads.iter().max().map_or(1, |t| t + 1) as u64;
+
+                    let mut named = BTreeSet::new();
+                    for ((_, item), thread) in items.into_iter().zip(threads) {
+                        let tid = first_tid + thread as u64;
+                        if named.insert(tid) {
+                            let name = if thread == 0 {
+                                long_name.clone()
+                            } else {
+                                format!("{} ({})", long_name, thread + 1)
+                            };
+                            out.event(json!({
+                                "name": "thread_name", "ph": "M", "pid": pid, "tid": tid,
+                                "args": {"name": name},
+                            }))?;
+                            out.event(json!({
+                                "name": "thread_sort_index", "ph": "M", "pid": pid, "tid": tid,
+                                "args": {"sort_index": tid},
+                            }))?;
+                        }
+
+                        let args: serde_json::Map<_, _> = item
+                            .fields
+                            .iter()
+                            .filter_map(|(field_id, field)| {
+                                let name = info.field_schema.get_name(*field_id)?;
+                                Some((name.to_owned(), field_json(field)))
+                            })
+                            .collect();
+                        let interval = item.original_interval;
+                        out.event(json!({
+                            "name": item.title, "ph": "X", "pid": pid, "tid": tid,
+                            "ts": us(interval.start), "dur": us(interval.stop) - us(interval.start),
+                            "args": args,
+                        }))?;
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        out.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deferred_data::DeferredDataSourceWrapper;
+
+    const TRACE: &str = r#"{
+        "traceEvents": [
+            {"name": "process_name", "ph": "M", "pid": 1, "args": {"name": "worker"}},
+            {"name": "thread_name", "ph": "M", "pid": 1, "tid": 7, "args": {"name": "main"}},
+            {"name": "outer", "cat": "app", "ph": "X", "ts": 1000, "dur": 10, "pid": 1, "tid": 7},
+            {"name": "inner", "ph": "B", "ts": 1002, "pid": 1, "tid": 7, "args": {"n": 3}},
+            {"name": "leaf", "ph": "B", "ts": 1003, "pid": 1, "tid": 7},
+            {"name": "inner", "ph": "E", "ts": 1005, "pid": 1, "tid": 7, "args": {"ratio": 0.5}},
+            {"name": "leaf", "ph": "E", "ts": 1004, "pid": 1, "tid": 7},
+            {"name": "other", "ph": "X", "ts": 1000, "dur": 5, "pid": 2, "tid": 1,
+             "args": {"label": "x"}},
+            {"name": "ignored", "ph": "i", "ts": 1003, "pid": 2, "tid": 1}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse() {
+        let source = ChromeTraceDataSource::from_slice(TRACE.as_bytes()).unwrap();
+        let info = source.fetch_info().unwrap();
+        assert_eq!(
+            info.interval,
+            Interval::new(Timestamp(0), Timestamp(10_000))
+        );
+
+        let EntryInfo::Panel { slots, .. } = &info.entry_info else {
+            panic!("expected root panel");
+        };
+        assert_eq!(slots.len(), 2);
+        let EntryInfo::Panel {
+            long_name, slots, ..
+        } = &slots[0]
+        else {
+            panic!("expected process panel");
+        };
+        assert_eq!(long_name, "worker (pid 1)");
+        let EntryInfo::Slot {
+            long_name,
+            max_rows,
+            ..
+        } = &slots[0]
+        else {
+            panic!("expected thread slot");
+        };
+        assert_eq!(long_name, "main");
+        assert_eq!(*max_rows, 3);
+
+        let n_field = info.field_schema.get_id("n").unwrap();
+        let ratio_field = info.field_schema.get_id("ratio").unwrap();
+        let entry_id = EntryID::root().child(0).child(0);
+        let tile = TileID(info.interval);
+        let meta = source.fetch_slot_meta_tile(&entry_id, tile, false).unwrap();
+        // Outermost spans are in the last row
+        assert_eq!(meta.data.items[2][0].title, "outer");
+        assert_eq!(meta.data.items[0][0].title, "leaf");
+        // Each E closes the B with the same name, even out of order
+        let inner = &meta.data.items[1][0];
+        assert_eq!(inner.title, "inner");
+        assert_eq!(
+            inner.original_interval,
+            Interval::new(Timestamp(2_000), Timestamp(5_000))
+        );
+        assert_eq!(
+            meta.data.items[0][0].original_interval,
+            Interval::new(Timestamp(3_000), Timestamp(4_000))
+        );
+        let field = |id| inner.fields.iter().find(|(f, _)| *f == id);
+        assert!(matches!(field(n_field), Some((_, Field::I64(3)))));
+        assert!(matches!(field(ratio_field), Some((_, Field::F64(x))) if *x == 0.5));
+    }
+
+    #[test]
+    fn test_summary() {
+        let source = ChromeTraceDataSource::from_slice(TRACE.as_bytes()).unwrap();
+        let info = source.fetch_info().unwrap();
+        // pid 2 is busy for the first half of the trace
+        let entry_id = EntryID::root().child(1).summary();
+        let tile = source
+            .fetch_summary_tile(&entry_id, TileID(info.interval), false)
+            .unwrap();
+        let utilization = &tile.data.utilization;
+        assert_eq!(utilization.first().unwrap().util, 1.0);
+        assert_eq!(utilization.last().unwrap().util, 0.0);
+    }
+
+    #[test]
+    fn test_slot_tile() {
+        // 100 back-to-back 1us events, then one long event
+        let mut events: Vec<_> = (0..100)
+            .map(|i| json!({"name": "tiny", "ph": "X", "ts": i, "dur": 1, "pid": 1, "tid": 1}))
+            .collect();
+        events.push(json!({"name": "long", "ph": "X", "ts": 100, "dur": 900, "pid": 1, "tid": 1}));
+        let source =
+            ChromeTraceDataSource::from_slice(&serde_json::to_vec(&events).unwrap()).unwrap();
+        let entry_id = EntryID::root().child(0).child(0);
+        let tile = TileID(Interval::new(Timestamp(0), Timestamp(10_000_000)));
+
+        let full = source.fetch_slot_tile(&entry_id, tile, true).unwrap();
+        assert_eq!(full.data.items[0].len(), 101);
+
+        // The tiny events are each below a thousandth of the tile
+        let meta = source.fetch_slot_meta_tile(&entry_id, tile, false).unwrap();
+        let titles: Vec<_> = meta.data.items[0].iter().map(|m| &m.title[..]).collect();
+        assert_eq!(titles, ["100 merged items", "long"]);
+
+        let slice = TileID(Interval::new(Timestamp(50_500), Timestamp(150_000)));
+        let partial = source.fetch_slot_tile(&entry_id, slice, true).unwrap();
+        let items = &partial.data.items[0];
+        assert_eq!(items.len(), 51);
+        assert_eq!(items[0].interval.start, Timestamp(50_500));
+        assert_eq!(items[50].interval.stop, Timestamp(150_000));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            ChromeTraceDataSource::from_slice(b"{\"traceEvents\": 5}"),
+            Err(DataSourceError::Decode(_))
+        ));
+
+        let unmatched = r#"[
+            {"name": "a", "ph": "B", "ts": 0, "pid": 1, "tid": 1},
+            {"name": "b", "ph": "E", "ts": 1, "pid": 1, "tid": 1}
+        ]"#;
+        assert!(matches!(
+            ChromeTraceDataSource::from_slice(unmatched.as_bytes()),
+            Err(DataSourceError::Decode(_))
+        ));
+    }
+
+    fn slot_names(info: &DataSourceInfo, pid_index: u64) -> Vec<String> {
+        let Some(EntryInfo::Panel { slots, .. }) =
+            info.entry_info.get(&EntryID::root().child(pid_index))
+        else {
+            panic!("expected process panel");
+        };
+        slots
+            .iter()
+            .map(|slot| match slot {
+                EntryInfo::Slot { long_name, .. } => long_name.clone(),
+                _ => panic!("expected thread slot"),
+            })
+            .collect()
+    }
+
+    fn write(source: ChromeTraceDataSource) -> Vec<u8> {
+        let mut output = Vec::new();
+        ChromeTraceWriter::new(DeferredDataSourceWrapper::new(source), "unused")
+            .write_to(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = ChromeTraceDataSource::from_slice(TRACE.as_bytes()).unwrap();
+        let before = source.fetch_info().unwrap();
+        let output = write(source);
+
+        let events: Value = serde_json::from_slice(&output).unwrap();
+        let events = events["traceEvents"].as_array().unwrap();
+        let count = |ph: &str| events.iter().filter(|e| e["ph"] == ph).count();
+        assert_eq!(count("X"), 4);
+        assert!(count("C") > 0);
+
+        // Rows nest, so each slot comes back as a single thread
+        let source = ChromeTraceDataSource::from_slice(&output).unwrap();
+        let info = source.fetch_info().unwrap();
+        for pid_index in 0..2 {
+            assert_eq!(slot_names(&info, pid_index), slot_names(&before, pid_index));
+        }
+        let entry_id = EntryID::root().child(0).child(0);
+        assert_eq!(
+            info.entry_info.get(&entry_id).map(|x| match x {
+                EntryInfo::Slot { max_rows, .. } => *max_rows,
+                _ => 0,
+            }),
+            Some(3)
+        );
+        let tile = TileID(info.interval);
+        let meta = source.fetch_slot_meta_tile(&entry_id, tile, false).unwrap();
+        assert_eq!(meta.data.items[2][0].title, "outer");
+        assert_eq!(meta.data.items[1][0].title, "inner");
+        assert_eq!(meta.data.items[0][0].title, "leaf");
+        let n_field = info.field_schema.get_id("n").unwrap();
+        assert!(meta.data.items[1][0]
+            .fields
+            .iter()
+            .any(|(f, x)| *f == n_field && matches!(x, Field::I64(3))));
+
+        // Slices that partially overlap can't nest, so they need a second
+        // thread
+        let overlapping = r#"[
+            {"name": "thread_name", "ph": "M", "pid": 1, "tid": 1, "args": {"name": "main"}},
+            {"name": "a", "ph": "X", "ts": 0, "dur": 10, "pid": 1, "tid": 1},
+            {"name": "b", "ph": "X", "ts": 5, "dur": 10, "pid": 1, "tid": 1}
+        ]"#;
+        let source = ChromeTraceDataSource::from_slice(overlapping.as_bytes()).unwrap();
+        let output = write(source);
+        let info = ChromeTraceDataSource::from_slice(&output)
+            .unwrap()
+            .fetch_info()
+            .unwrap();
+        assert_eq!(slot_names(&info, 0), vec!["main", "main (2)"]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::data::{EntryID, ItemUID};
+use crate::timestamp::Interval;
+
+// Critical path analysis: starting from the item that finishes last, walk
+// backwards through whatever must have finished before each item could
+// start. An item's predecessors are the items it links to (via
+// Field::ItemLink) and the item before it in the same row of the same
+// slot. At each step we follow the predecessor that finished latest, since
+// that is the one the item was (most likely) waiting on.
+
+#[derive(Debug, Clone)]
+pub struct PathItem {
+    pub item_uid: ItemUID,
+    pub entry_id: EntryID,
+    // Row index in screen space (as in ItemLocator)
+    pub row: usize,
+    pub interval: Interval,
+    pub title: String,
+    pub links: Vec<ItemUID>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PathStep {
+    pub item_uid: ItemUID,
+    pub entry_id: EntryID,
+    pub row: usize,
+    pub interval: Interval,
+    pub title: String,
+    // Idle time between the end of the previous step and the start of
+    // this one (zero for the first step)
+    pub gap_ns: i64,
+    // Whether this step was reached from the next one by following an
+    // ItemLink (as opposed to a same-row predecessor)
+    pub via_link: bool,
+}
+
+pub fn critical_path(items: &BTreeMap<ItemUID, PathItem>, bounds: Interval) -> Vec<PathStep> {
+    // Items in each row, sorted by stop time
+    let mut rows: BTreeMap<(&EntryID, usize), Vec<&PathItem>> = BTreeMap::new();
+    for item in items.values() {
+        rows.entry((&item.entry_id, item.row))
+            .or_default()
+            .push(item);
+    }
+    for row in rows.values_mut() {
+        row.sort_by_key(|item| (item.interval.stop, item.interval.start));
+    }
+
+    let Some(last) = items
+        .values()
+        .filter(|item| item.interval.overlaps(bounds) && item.interval.stop <= bounds.stop)
+        .max_by_key(|item| (item.interval.stop, item.interval.start))
+    else {
+        return Vec::new();
+    };
+
+    let mut steps = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut current = last;
+    let mut via_link = false;
+    loop {
+        visited.insert(current.item_uid);
+        let start = current.interval.start;
+
+        // Predecessors must start strictly before the current item, which
+        // guarantees that the walk terminates
+        let linked = current
+            .links
+            .iter()
+            .filter_map(|uid| items.get(uid))
+            .filter(|item| item.interval.start < start)
+            .map(|item| (item, true));
+        let same_row = rows
+            .get(&(&current.entry_id, current.row))
+            .and_then(|row| {
+                let index = row.partition_point(|item| item.interval.stop <= start);
+                row[..index].last().copied()
+            })
+            .filter(|item| item.interval.start < start)
+            .map(|item| (item, false));
+
+        // Prefer links when there is a tie, because a link is an explicit
+        // dependence rather than a scheduling artifact
+        let pred = linked
+            .chain(same_row)
+            .filter(|(item, _)| !visited.contains(&item.item_uid))
+            .max_by_key(|(item, is_link)| (item.interval.stop.min(start), *is_link));
+
+        let gap_ns = pred.map_or(0, |(item, _)| start.0 - item.interval.stop.min(start).0);
+        steps.push(PathStep {
+            item_uid: current.item_uid,
+            entry_id: current.entry_id.clone(),
+            row: current.row,
+            interval: current.interval,
+            title: current.title.clone(),
+            gap_ns,
+            via_link,
+        });
+
+        let Some((pred, is_link)) = pred else {
+            break;
+        };
+        current = pred;
+        via_link = is_link;
+    }
+
+    // Report in chronological order
+    steps.reverse();
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timestamp::Timestamp;
+
+    fn item(uid: u64, slot: u64, row: usize, start: i64, stop: i64, links: &[u64]) -> PathItem {
+        PathItem {
+            item_uid: ItemUID(uid),
+            entry_id: EntryID::root().child(slot),
+            row,
+            interval: Interval::new(Timestamp(start), Timestamp(stop)),
+            title: format!("item {uid}"),
+            links: links.iter().map(|x| ItemUID(*x)).collect(),
+        }
+    }
+
+    fn path(items: Vec<PathItem>, stop: i64) -> Vec<(u64, i64)> {
+        let items = items.into_iter().map(|x| (x.item_uid, x)).collect();
+        let bounds = Interval::new(Timestamp(0), Timestamp(stop));
+        critical_path(&items, bounds)
+            .into_iter()
+            .map(|step| (step.item_uid.0, step.gap_ns))
+            .collect()
+    }
+
+    #[test]
+    fn test_same_row() {
+        let items = vec![
+            item(1, 0, 0, 0, 10, &[]),
+            item(2, 0, 0, 15, 20, &[]),
+            item(3, 0, 0, 20, 30, &[]),
+            // Different row, does not finish last
+            item(4, 0, 1, 0, 25, &[]),
+        ];
+        assert_eq!(path(items, 100), [(1, 0), (2, 5), (3, 0)]);
+    }
+
+    #[test]
+    fn test_links() {
+        let items = vec![
+            item(1, 0, 0, 0, 10, &[]),
+            item(2, 1, 0, 0, 18, &[]),
+            // Waits on 2 (which finishes later than the same-row item 1)
+            item(3, 0, 0, 20, 30, &[2]),
+            // Outside the bounds
+            item(4, 2, 0, 90, 120, &[]),
+        ];
+        let items: BTreeMap<_, _> = items.into_iter().map(|x| (x.item_uid, x)).collect();
+        let bounds = Interval::new(Timestamp(0), Timestamp(100));
+        let steps = critical_path(&items, bounds);
+        let uids: Vec<_> = steps.iter().map(|s| s.item_uid.0).collect();
+        assert_eq!(uids, [2, 3]);
+        assert_eq!(steps[1].gap_ns, 2);
+        assert!(steps[0].via_link);
+        assert!(!steps[1].via_link);
+    }
+
+    #[test]
+    fn test_cycle() {
+        // Links that point forward in time or form cycles are ignored
+        let items = vec![item(1, 0, 0, 0, 10, &[2]), item(2, 1, 0, 5, 20, &[1])];
+        assert_eq!(path(items, 100), [(1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(path(Vec::new(), 100).is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::fmt;
+use std::time::Duration;
 
 pub use egui::{Color32, Rgba};
 use serde::{Deserialize, Serialize};
@@ -27,6 +28,17 @@ pub struct DataSourceInfo {
     pub field_schema: FieldSchema,
 }
 
+// A revision of a live profile's info (see DataSource::fetch_info_update)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InfoUpdate {
+    pub revision: u64,
+    pub info: DataSourceInfo,
+}
+
+// How long to wait for a live profile to change before giving up (and
+// asking again)
+pub const INFO_UPDATE_TIMEOUT: Duration = Duration::from_secs(20);
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum EntryInfo {
     Panel {
@@ -122,6 +134,10 @@ impl FieldSchema {
     pub fn searchable(&self) -> &BTreeSet<FieldID> {
         &self.searchable
     }
+
+    pub fn field_ids(&self) -> impl Iterator<Item = FieldID> + '_ {
+        self.field_names.keys().copied()
+    }
 }
 
 impl Default for FieldSchema {
@@ -134,6 +150,7 @@ impl Default for FieldSchema {
 pub enum Field {
     I64(i64),
     U64(u64),
+    F64(f64),
     String(String),
     Interval(Interval),
     ItemLink(ItemLink),
@@ -170,6 +187,18 @@ pub struct TileSet {
     pub tiles: Vec<Vec<TileID>>,
 }
 
+impl TileSet {
+    // The finest level of a static tile set, because coarser levels may
+    // leave out small items. For dynamic profiles, the interval is one tile.
+    pub fn full_tiles(&self, interval: Interval) -> Vec<TileID> {
+        self.tiles
+            .iter()
+            .min_by_key(|level| level.first().map_or(i64::MAX, |t| t.0.duration_ns()))
+            .cloned()
+            .unwrap_or_else(|| vec![TileID(interval)])
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SummaryTileData {
     pub utilization: Vec<UtilPoint>,
@@ -207,32 +236,91 @@ pub struct SlotMetaTile {
 }
 
 pub trait DataSource {
-    fn fetch_info(&self) -> DataSourceInfo;
-    fn fetch_summary_tile(&self, entry_id: &EntryID, tile_id: TileID, full: bool) -> SummaryTile;
-    fn fetch_slot_tile(&self, entry_id: &EntryID, tile_id: TileID, full: bool) -> SlotTile;
-    fn fetch_slot_meta_tile(&self, entry_id: &EntryID, tile_id: TileID, full: bool)
-        -> SlotMetaTile;
+    fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError>;
+    fn fetch_summary_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SummaryTile, DataSourceError>;
+    fn fetch_slot_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotTile, DataSourceError>;
+    fn fetch_slot_meta_tile(
+        &self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotMetaTile, DataSourceError>;
+
+    // Optional: search for items without sending their tiles to the client
+    // (see search::scan_slot_meta_tiles for a simple implementation).
+    // Sources that don't support search return NotFound.
+    fn search(&self, _request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        Err(DataSourceError::NotFound(
+            "search is not supported".to_owned(),
+        ))
+    }
+
+    // Optional: for live profiles, which grow while the application runs.
+    // Waits up to timeout for a revision newer than since (revisions start
+    // at 1) and returns it, or None if there is none. The interval may
+    // extend, entries may be added and slots may gain rows, but nothing
+    // else (e.g., the field schema or anything before the old end of the
+    // interval) may change. Sources that never change return NotFound.
+    // See live_data::InfoPublisher for a simple implementation.
+    fn fetch_info_update(
+        &self,
+        _since: u64,
+        _timeout: Duration,
+    ) -> Result<Option<InfoUpdate>, DataSourceError> {
+        Err(DataSourceError::NotFound("profile is not live".to_owned()))
+    }
 }
 
 pub trait DataSourceMut {
-    fn fetch_info(&mut self) -> DataSourceInfo;
+    fn fetch_info(&mut self) -> Result<DataSourceInfo, DataSourceError>;
     fn fetch_summary_tile(
         &mut self,
         entry_id: &EntryID,
         tile_id: TileID,
         full: bool,
-    ) -> SummaryTile;
-    fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) -> SlotTile;
+    ) -> Result<SummaryTile, DataSourceError>;
+    fn fetch_slot_tile(
+        &mut self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotTile, DataSourceError>;
     fn fetch_slot_meta_tile(
         &mut self,
         entry_id: &EntryID,
         tile_id: TileID,
         full: bool,
-    ) -> SlotMetaTile;
+    ) -> Result<SlotMetaTile, DataSourceError>;
+
+    // See DataSource::search
+    fn search(&mut self, _request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        Err(DataSourceError::NotFound(
+            "search is not supported".to_owned(),
+        ))
+    }
+
+    // See DataSource::fetch_info_update
+    fn fetch_info_update(
+        &mut self,
+        _since: u64,
+        _timeout: Duration,
+    ) -> Result<Option<InfoUpdate>, DataSourceError> {
+        Err(DataSourceError::NotFound("profile is not live".to_owned()))
+    }
 }
 
 impl<T: DataSource> DataSourceMut for T {
-    fn fetch_info(&mut self) -> DataSourceInfo {
+    fn fetch_info(&mut self) -> Result<DataSourceInfo, DataSourceError> {
         DataSource::fetch_info(self)
     }
     fn fetch_summary_tile(
@@ -240,10 +328,15 @@ impl<T: DataSource> DataSourceMut for T {
         entry_id: &EntryID,
         tile_id: TileID,
         full: bool,
-    ) -> SummaryTile {
+    ) -> Result<SummaryTile, DataSourceError> {
         DataSource::fetch_summary_tile(self, entry_id, tile_id, full)
     }
-    fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) -> SlotTile {
+    fn fetch_slot_tile(
+        &mut self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<SlotTile, DataSourceError> {
         DataSource::fetch_slot_tile(self, entry_id, tile_id, full)
     }
     fn fetch_slot_meta_tile(
@@ -251,9 +344,19 @@ impl<T: DataSource> DataSourceMut for T {
         entry_id: &EntryID,
         tile_id: TileID,
         full: bool,
-    ) -> SlotMetaTile {
+    ) -> Result<SlotMetaTile, DataSourceError> {
         DataSource::fetch_slot_meta_tile(self, entry_id, tile_id, full)
     }
+    fn search(&mut self, request: &SearchRequest) -> Result<Vec<SearchResult>, DataSourceError> {
+        DataSource::search(self, request)
+    }
+    fn fetch_info_update(
+        &mut self,
+        since: u64,
+        timeout: Duration,
+    ) -> Result<Option<InfoUpdate>, DataSourceError> {
+        DataSource::fetch_info_update(self, since, timeout)
+    }
 }
 
 impl EntryID {
@@ -307,6 +410,11 @@ impl EntryID {
         )
     }
 
+    pub fn parent(&self) -> Option<Self> {
+        let (_, prefix) = self.0.split_last()?;
+        Some(Self(prefix.to_vec()))
+    }
+
     pub fn has_prefix(&self, prefix: &EntryID) -> bool {
         if prefix.0.len() > self.0.len() {
             return false;
@@ -336,7 +444,8 @@ impl EntryInfo {
                 (EntryIndex::Slot(j), EntryInfo::Panel { slots, .. }) => {
                     result = slots.get(j as usize)?;
                 }
-                _ => panic!("EntryID and EntryInfo do not match"),
+                // EntryID and EntryInfo do not match
+                _ => return None,
             }
         }
         Some(result)
@@ -351,27 +460,98 @@ impl EntryInfo {
     }
 
     pub fn kinds(&self) -> Vec<String> {
-        if let EntryInfo::Panel { slots: nodes, .. } = self {
-            let mut result = Vec::new();
-            let mut set = BTreeSet::new();
-            for node in nodes {
-                if let EntryInfo::Panel { slots: kinds, .. } = node {
-                    for kind in kinds {
-                        if let EntryInfo::Panel { short_name, .. } = kind {
-                            if set.insert(short_name) {
-                                result.push(short_name.clone());
-                            }
-                        } else {
-                            unreachable!();
-                        }
-                    }
-                } else {
-                    unreachable!();
+        // Kinds are the entries two levels below the root (root -> node -> kind)
+        self.levels()
+            .into_iter()
+            .nth(1)
+            .map(|level| level.short_names)
+            .unwrap_or_default()
+    }
+
+    // Describe the entries at each depth of the tree. The first element
+    // describes the children of the root, and so on. Summaries are not
+    // included.
+    pub fn levels(&self) -> Vec<EntryLevel> {
+        fn walk<'a>(
+            info: &'a EntryInfo,
+            depth: usize,
+            levels: &mut Vec<EntryLevel>,
+            seen: &mut Vec<BTreeSet<&'a str>>,
+        ) {
+            let EntryInfo::Panel { slots, .. } = info else {
+                return;
+            };
+            if slots.is_empty() {
+                return;
+            }
+            if levels.len() <= depth {
+                levels.push(EntryLevel::default());
+                seen.push(BTreeSet::new());
+            }
+            let level = &mut levels[depth];
+            level.max_slots = level.max_slots.max(slots.len() as u64);
+            level.entries += slots.len() as u64;
+            for slot in slots {
+                let (EntryInfo::Panel { short_name, .. } | EntryInfo::Slot { short_name, .. }) =
+                    slot
+                else {
+                    continue;
+                };
+                if seen[depth].insert(short_name) {
+                    level.short_names.push(short_name.clone());
                 }
             }
-            return result;
+            for slot in slots {
+                walk(slot, depth + 1, levels, seen);
+            }
+        }
+
+        let mut levels = Vec::new();
+        walk(self, 0, &mut levels, &mut Vec::new());
+        levels
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryLevel {
+    // Largest number of entries under any single parent
+    pub max_slots: u64,
+    // Total number of entries at this depth
+    pub entries: u64,
+    // Distinct short names, in order of first appearance
+    pub short_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DataSourceError {
+    Io(String),
+    Decode(String),
+    NotFound(String),
+    BadRequest(String),
+    Remote(String),
+}
+
+impl fmt::Display for DataSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataSourceError::Io(e) => write!(f, "I/O error: {}", e),
+            DataSourceError::Decode(e) => write!(f, "decode error: {}", e),
+            DataSourceError::NotFound(e) => write!(f, "not found: {}", e),
+            DataSourceError::BadRequest(e) => write!(f, "bad request: {}", e),
+            DataSourceError::Remote(e) => write!(f, "remote error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DataSourceError {}
+
+impl From<std::io::Error> for DataSourceError {
+    fn from(e: std::io::Error) -> DataSourceError {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => DataSourceError::NotFound(e.to_string()),
+            std::io::ErrorKind::InvalidData => DataSourceError::Decode(e.to_string()),
+            _ => DataSourceError::Io(e.to_string()),
         }
-        unreachable!()
     }
 }
 
@@ -443,3 +623,120 @@ impl fmt::Display for TileIDSlug {
         write!(f, "{}", self.0 .0.stop.0)
     }
 }
+
+// Searches are answered by DataSource::search (see search.rs for the matching
+// logic shared with the viewer)
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum SearchMode {
+    Substring,
+    CaseInsensitive,
+    WholeWord,
+    Regex,
+    Query,
+}
+
+impl SearchMode {
+    pub const ALL: [SearchMode; 5] = [
+        SearchMode::Substring,
+        SearchMode::CaseInsensitive,
+        SearchMode::WholeWord,
+        SearchMode::Regex,
+        SearchMode::Query,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchMode::Substring => "Substring",
+            SearchMode::CaseInsensitive => "Case-insensitive",
+            SearchMode::WholeWord => "Whole word",
+            SearchMode::Regex => "Regex",
+            SearchMode::Query => "Query",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SearchRequest {
+    pub mode: SearchMode,
+    pub query: String,
+    // Field to search, or None for item titles. Ignored by SearchMode::Query,
+    // since queries name their own fields.
+    pub field: Option<FieldID>,
+    // Only items overlapping the interval match
+    pub interval: Interval,
+    // Slots to search, or None for every slot in the profile
+    pub entry_ids: Option<Vec<EntryID>>,
+    pub max_results: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub item_uid: ItemUID,
+    pub entry_id: EntryID,
+    // Row index in screen space (as in ItemLocator)
+    pub row: usize,
+    pub original_interval: Interval,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResponse {
+    // The request being answered, since searches may complete out of order
+    pub request: SearchRequest,
+    pub results: Vec<SearchResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(name: &str) -> EntryInfo {
+        EntryInfo::Slot {
+            short_name: name.to_owned(),
+            long_name: name.to_owned(),
+            max_rows: 1,
+        }
+    }
+
+    fn panel(name: &str, slots: Vec<EntryInfo>) -> EntryInfo {
+        EntryInfo::Panel {
+            short_name: name.to_owned(),
+            long_name: name.to_owned(),
+            summary: None,
+            slots,
+        }
+    }
+
+    #[test]
+    fn test_levels_two() {
+        let info = panel("root", vec![slot("a"), slot("b"), slot("c")]);
+        let levels = info.levels();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].max_slots, 3);
+        assert_eq!(levels[0].entries, 3);
+        assert!(info.kinds().is_empty());
+    }
+
+    #[test]
+    fn test_levels_five() {
+        // cluster -> host -> socket -> core -> thread
+        let core = |i| panel(&format!("core{i}"), vec![slot("t0"), slot("t1")]);
+        let socket = |i| panel(&format!("socket{i}"), (0..4).map(core).collect());
+        let host = |i| panel(&format!("host{i}"), (0..2).map(socket).collect());
+        let info = panel("cluster", (0..3).map(host).collect());
+
+        let levels = info.levels();
+        let max_slots: Vec<_> = levels.iter().map(|l| l.max_slots).collect();
+        let entries: Vec<_> = levels.iter().map(|l| l.entries).collect();
+        assert_eq!(max_slots, [3, 2, 4, 2]);
+        assert_eq!(entries, [3, 6, 24, 48]);
+        assert_eq!(levels[1].short_names, ["socket0", "socket1"]);
+        assert_eq!(levels[3].short_names, ["t0", "t1"]);
+        assert_eq!(info.kinds(), ["socket0", "socket1"]);
+
+        let entry_id = EntryID::root().child(2).child(1).child(3);
+        assert_eq!(entry_id.parent(), Some(EntryID::root().child(2).child(1)));
+        assert_eq!(EntryID::root().parent(), None);
+    }
+}
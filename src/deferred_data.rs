@@ -1,24 +1,235 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use crate::data::{
-    DataSourceInfo, DataSourceMut, EntryID, SlotMetaTile, SlotTile, SummaryTile, TileID,
+    DataSourceError, DataSourceInfo, DataSourceMut, EntryID, InfoUpdate, SearchRequest,
+    SearchResponse, SlotMetaTile, SlotTile, SummaryTile, TileID,
 };
+use crate::http::schema::TileKind;
+
+// Whether a result means the data source doesn't support the request at
+// all (e.g., search), so that callers should fall back or stop asking.
+// Anything else, including a bad request, may only be about this request.
+pub fn is_unsupported<T>(result: &Result<T, DataSourceError>) -> bool {
+    matches!(result, Err(DataSourceError::NotFound(_)))
+}
+
+// The response to a tile request. Responses may arrive in any order, so each
+// one says which request it answers (including whether it asked for a full
+// tile), even when the request failed.
+#[derive(Debug, Clone)]
+pub struct TileResponse<T> {
+    pub entry_id: EntryID,
+    pub tile_id: TileID,
+    pub full: bool,
+    pub result: Result<T, DataSourceError>,
+}
+
+impl<T> TileResponse<T> {
+    pub fn new(
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+        result: Result<T, DataSourceError>,
+    ) -> Self {
+        Self {
+            entry_id: entry_id.clone(),
+            tile_id,
+            full,
+            result,
+        }
+    }
+}
 
 pub trait DeferredDataSource {
     fn fetch_info(&mut self);
-    fn get_infos(&mut self) -> Vec<DataSourceInfo>;
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>>;
     fn fetch_summary_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool);
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile>;
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>>;
     fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool);
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile>;
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>>;
     fn fetch_slot_meta_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool);
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile>;
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>>;
+
+    // Cancel outstanding requests for the given tile, of one kind and
+    // fullness only, so that requests made by others for the same tile
+    // still complete. A cancelled request never produces a result. Returns
+    // the number of requests cancelled; sources that can't cancel return 0
+    // and deliver results as usual.
+    fn cancel_tile(
+        &mut self,
+        _kind: TileKind,
+        _entry_id: &EntryID,
+        _tile_id: TileID,
+        _full: bool,
+    ) -> u64 {
+        0
+    }
+
+    // Search on the data source's side (see DataSource::search). Only call
+    // fetch_search while supports_search is true; otherwise (or once it
+    // becomes false) the caller should scan slot meta tiles instead. Each
+    // fetch_search produces exactly one result, even if search turns out
+    // to be unsupported.
+    fn supports_search(&self) -> bool {
+        false
+    }
+
+    fn fetch_search(&mut self, _request: &SearchRequest) {}
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        Vec::new()
+    }
+
+    // Live profiles (see DataSource::fetch_info_update). Only call
+    // fetch_info_update while supports_info_updates is true; it becomes
+    // false once the profile turns out not to be live. Each call produces
+    // exactly one result: the next revision after since, or None if there
+    // wasn't one in time (so ask again).
+    fn supports_info_updates(&self) -> bool {
+        false
+    }
+
+    fn fetch_info_update(&mut self, _since: u64) {}
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        Vec::new()
+    }
+}
+
+// Bookkeeping for tile requests that complete asynchronously, so that they
+// can be cancelled. Results must be delivered via RequestToken::finish,
+// which guarantees that a cancelled request never delivers anything.
+#[derive(Clone, Default)]
+pub struct RequestTracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    next_id: u64,
+    active: BTreeMap<u64, ActiveRequest>,
+}
+
+struct ActiveRequest {
+    kind: TileKind,
+    entry_id: EntryID,
+    tile_id: TileID,
+    full: bool,
+    on_cancel: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+#[derive(Clone)]
+pub struct RequestToken {
+    id: u64,
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl RequestTracker {
+    pub fn start(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> RequestToken {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.active.insert(
+            id,
+            ActiveRequest {
+                kind,
+                entry_id: entry_id.clone(),
+                tile_id,
+                full,
+                on_cancel: Vec::new(),
+            },
+        );
+        RequestToken {
+            id,
+            state: self.state.clone(),
+        }
+    }
+
+    fn cancel_where(&self, pred: impl Fn(&ActiveRequest) -> bool) -> u64 {
+        let cancelled: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            let ids: Vec<_> = state
+                .active
+                .iter()
+                .filter(|(_, req)| pred(req))
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter()
+                .map(|id| state.active.remove(id).unwrap())
+                .collect()
+        };
+        // Run the hooks without holding the lock, since they may do
+        // arbitrary work
+        let count = cancelled.len() as u64;
+        for req in cancelled {
+            for f in req.on_cancel {
+                f();
+            }
+        }
+        count
+    }
+
+    pub fn cancel_tile(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        self.cancel_where(|req| {
+            req.kind == kind
+                && req.entry_id == *entry_id
+                && req.tile_id == tile_id
+                && req.full == full
+        })
+    }
+}
+
+impl RequestToken {
+    pub fn is_cancelled(&self) -> bool {
+        !self.state.lock().unwrap().active.contains_key(&self.id)
+    }
+
+    // Run f when the request is cancelled (immediately if it already is)
+    pub fn on_cancel(&self, f: impl FnOnce() + Send + 'static) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(req) = state.active.get_mut(&self.id) {
+            req.on_cancel.push(Box::new(f));
+            return;
+        }
+        drop(state);
+        f();
+    }
+
+    // Complete the request, calling deliver unless it has been cancelled.
+    // Holds the lock throughout so that cancellation can't race with
+    // delivery.
+    pub fn finish(&self, deliver: impl FnOnce()) {
+        let mut state = self.state.lock().unwrap();
+        if state.active.remove(&self.id).is_some() {
+            deliver();
+        }
+    }
 }
 
 pub struct DeferredDataSourceWrapper<T: DataSourceMut> {
     data_source: T,
-    infos: Vec<DataSourceInfo>,
-    summary_tiles: Vec<SummaryTile>,
-    slot_tiles: Vec<SlotTile>,
-    slot_meta_tiles: Vec<SlotMetaTile>,
+    infos: Vec<Result<DataSourceInfo, DataSourceError>>,
+    summary_tiles: Vec<TileResponse<SummaryTile>>,
+    slot_tiles: Vec<TileResponse<SlotTile>>,
+    slot_meta_tiles: Vec<TileResponse<SlotMetaTile>>,
+    search_supported: bool,
+    search_results: Vec<Result<SearchResponse, DataSourceError>>,
+    info_updates_supported: bool,
+    info_updates: Vec<Result<Option<InfoUpdate>, DataSourceError>>,
 }
 
 impl<T: DataSourceMut> DeferredDataSourceWrapper<T> {
@@ -29,6 +240,10 @@ impl<T: DataSourceMut> DeferredDataSourceWrapper<T> {
             summary_tiles: Vec::new(),
             slot_tiles: Vec::new(),
             slot_meta_tiles: Vec::new(),
+            search_supported: true,
+            search_results: Vec::new(),
+            info_updates_supported: true,
+            info_updates: Vec::new(),
         }
     }
 }
@@ -38,38 +253,75 @@ impl<T: DataSourceMut> DeferredDataSource for DeferredDataSourceWrapper<T> {
         self.infos.push(self.data_source.fetch_info());
     }
 
-    fn get_infos(&mut self) -> Vec<DataSourceInfo> {
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
         std::mem::take(&mut self.infos)
     }
 
     fn fetch_summary_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        self.summary_tiles
-            .push(self.data_source.fetch_summary_tile(entry_id, tile_id, full));
+        let result = self.data_source.fetch_summary_tile(entry_id, tile_id, full);
+        (self.summary_tiles).push(TileResponse::new(entry_id, tile_id, full, result));
     }
 
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile> {
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
         std::mem::take(&mut self.summary_tiles)
     }
 
     fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        self.slot_tiles
-            .push(self.data_source.fetch_slot_tile(entry_id, tile_id, full));
+        let result = self.data_source.fetch_slot_tile(entry_id, tile_id, full);
+        (self.slot_tiles).push(TileResponse::new(entry_id, tile_id, full, result));
     }
 
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile> {
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
         std::mem::take(&mut self.slot_tiles)
     }
 
     fn fetch_slot_meta_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        self.slot_meta_tiles.push(
-            self.data_source
-                .fetch_slot_meta_tile(entry_id, tile_id, full),
-        );
+        let result = self
+            .data_source
+            .fetch_slot_meta_tile(entry_id, tile_id, full);
+        (self.slot_meta_tiles).push(TileResponse::new(entry_id, tile_id, full, result));
     }
 
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile> {
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
         std::mem::take(&mut self.slot_meta_tiles)
     }
+
+    fn supports_search(&self) -> bool {
+        self.search_supported
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        let result = self.data_source.search(request);
+        if is_unsupported(&result) {
+            self.search_supported = false;
+        }
+        self.search_results
+            .push(result.map(|results| SearchResponse {
+                request: request.clone(),
+                results,
+            }));
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        std::mem::take(&mut self.search_results)
+    }
+
+    fn supports_info_updates(&self) -> bool {
+        self.info_updates_supported
+    }
+
+    // Never waits, since this runs on the caller's thread
+    fn fetch_info_update(&mut self, since: u64) {
+        let result = self.data_source.fetch_info_update(since, Duration::ZERO);
+        if is_unsupported(&result) {
+            self.info_updates_supported = false;
+        }
+        self.info_updates.push(result);
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        std::mem::take(&mut self.info_updates)
+    }
 }
 
 pub struct CountingDeferredDataSource<T: DeferredDataSource> {
@@ -94,10 +346,13 @@ impl<T: DeferredDataSource> CountingDeferredDataSource<T> {
     }
 
     fn finish_request<E>(&mut self, result: Vec<E>) -> Vec<E> {
-        let count = result.len() as u64;
+        self.cancel_requests(result.len() as u64);
+        result
+    }
+
+    fn cancel_requests(&mut self, count: u64) {
         assert!(self.outstanding_requests >= count);
         self.outstanding_requests -= count;
-        result
     }
 }
 
@@ -107,7 +362,7 @@ impl<T: DeferredDataSource> DeferredDataSource for CountingDeferredDataSource<T>
         self.data_source.fetch_info()
     }
 
-    fn get_infos(&mut self) -> Vec<DataSourceInfo> {
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
         let result = self.data_source.get_infos();
         self.finish_request(result)
     }
@@ -117,7 +372,7 @@ impl<T: DeferredDataSource> DeferredDataSource for CountingDeferredDataSource<T>
         self.data_source.fetch_summary_tile(entry_id, tile_id, full)
     }
 
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile> {
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
         let result = self.data_source.get_summary_tiles();
         self.finish_request(result)
     }
@@ -127,7 +382,7 @@ impl<T: DeferredDataSource> DeferredDataSource for CountingDeferredDataSource<T>
         self.data_source.fetch_slot_tile(entry_id, tile_id, full)
     }
 
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile> {
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
         let result = self.data_source.get_slot_tiles();
         self.finish_request(result)
     }
@@ -138,10 +393,49 @@ impl<T: DeferredDataSource> DeferredDataSource for CountingDeferredDataSource<T>
             .fetch_slot_meta_tile(entry_id, tile_id, full)
     }
 
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile> {
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
         let result = self.data_source.get_slot_meta_tiles();
         self.finish_request(result)
     }
+
+    fn cancel_tile(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        let count = self.data_source.cancel_tile(kind, entry_id, tile_id, full);
+        self.cancel_requests(count);
+        count
+    }
+
+    fn supports_search(&self) -> bool {
+        self.data_source.supports_search()
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        self.start_request();
+        self.data_source.fetch_search(request)
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        let result = self.data_source.get_search_results();
+        self.finish_request(result)
+    }
+
+    // Not counted: a live profile always has one of these outstanding
+    fn supports_info_updates(&self) -> bool {
+        self.data_source.supports_info_updates()
+    }
+
+    fn fetch_info_update(&mut self, since: u64) {
+        self.data_source.fetch_info_update(since)
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        self.data_source.get_info_updates()
+    }
 }
 
 impl DeferredDataSource for Box<dyn DeferredDataSource> {
@@ -149,7 +443,7 @@ impl DeferredDataSource for Box<dyn DeferredDataSource> {
         self.as_mut().fetch_info()
     }
 
-    fn get_infos(&mut self) -> Vec<DataSourceInfo> {
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
         self.as_mut().get_infos()
     }
 
@@ -157,7 +451,7 @@ impl DeferredDataSource for Box<dyn DeferredDataSource> {
         self.as_mut().fetch_summary_tile(entry_id, tile_id, full)
     }
 
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile> {
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
         self.as_mut().get_summary_tiles()
     }
 
@@ -165,7 +459,7 @@ impl DeferredDataSource for Box<dyn DeferredDataSource> {
         self.as_mut().fetch_slot_tile(entry_id, tile_id, full)
     }
 
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile> {
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
         self.as_mut().get_slot_tiles()
     }
 
@@ -173,7 +467,80 @@ impl DeferredDataSource for Box<dyn DeferredDataSource> {
         self.as_mut().fetch_slot_meta_tile(entry_id, tile_id, full)
     }
 
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile> {
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
         self.as_mut().get_slot_meta_tiles()
     }
+
+    fn cancel_tile(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        self.as_mut().cancel_tile(kind, entry_id, tile_id, full)
+    }
+
+    fn supports_search(&self) -> bool {
+        self.as_ref().supports_search()
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        self.as_mut().fetch_search(request)
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        self.as_mut().get_search_results()
+    }
+
+    fn supports_info_updates(&self) -> bool {
+        self.as_ref().supports_info_updates()
+    }
+
+    fn fetch_info_update(&mut self, since: u64) {
+        self.as_mut().fetch_info_update(since)
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        self.as_mut().get_info_updates()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timestamp::{Interval, Timestamp};
+
+    #[test]
+    fn test_request_tracker() {
+        let tracker = RequestTracker::default();
+        let entry_id = EntryID::root().child(0);
+        let tile = |i: i64| TileID(Interval::new(Timestamp(i * 10), Timestamp((i + 1) * 10)));
+
+        let kind = TileKind::Slot;
+        let a = tracker.start(kind, &entry_id, tile(0), false);
+        let b = tracker.start(kind, &entry_id, tile(0), false);
+        let c = tracker.start(kind, &entry_id, tile(1), false);
+        // Same tile, but a different kind or a full request
+        let d = tracker.start(TileKind::SlotMeta, &entry_id, tile(0), false);
+        let e = tracker.start(kind, &entry_id, tile(0), true);
+
+        let mut delivered = 0;
+        a.finish(|| delivered += 1);
+        // Already finished, so only b is cancelled
+        assert_eq!(tracker.cancel_tile(kind, &entry_id, tile(0), false), 1);
+        assert!(b.is_cancelled());
+        b.finish(|| delivered += 1);
+        assert_eq!(delivered, 1);
+        assert!(!d.is_cancelled());
+        assert!(!e.is_cancelled());
+
+        let aborted = Arc::new(Mutex::new(false));
+        let flag = aborted.clone();
+        c.on_cancel(move || *flag.lock().unwrap() = true);
+        assert_eq!(tracker.cancel_tile(kind, &entry_id, tile(1), false), 1);
+        assert!(*aborted.lock().unwrap());
+        assert_eq!(tracker.cancel_tile(kind, &entry_id, tile(1), false), 0);
+    }
 }
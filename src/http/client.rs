@@ -1,118 +1,934 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-use bytes::Buf;
+use bytes::Bytes;
 
 use log::info;
 
-#[cfg(not(target_arch = "wasm32"))]
-use reqwest::blocking::{Client, ClientBuilder};
-#[cfg(target_arch = "wasm32")]
 use reqwest::{Client, ClientBuilder};
 
 use serde::Deserialize;
 
 use url::Url;
 
-use crate::data::{DataSourceInfo, EntryID, SlotMetaTile, SlotTile, SummaryTile, TileID};
-use crate::deferred_data::DeferredDataSource;
-use crate::http::fetch::{fetch, DataSourceResponse};
-use crate::http::schema::TileRequestRef;
+use crate::data::{
+    DataSourceError, DataSourceInfo, EntryID, InfoUpdate, ItemUID, SearchRequest, SearchResponse,
+    SearchResult, SlotMetaTile, SlotTile, SummaryTile, TileID,
+};
+use crate::deferred_data::{
+    is_unsupported, DeferredDataSource, RequestToken, RequestTracker, TileResponse,
+};
+use crate::http::fetch::{fetch, fetch_stream, DataSourceResponse, RequestPolicy, StreamEvent};
+use crate::http::schema::{BatchTileRequest, FrameDecoder, TileKind, TileRequestRef};
+use crate::search::{
+    candidates, collect_tiles, scan_tiles, IndexLookup, SearchCollector, SearchIndexInfo,
+    SearchIndexShard,
+};
+
+// Upper bound on the number of tiles requested in a single POST /tiles
+const MAX_BATCH_SIZE: usize = 256;
+
+// Bytes of (compressed) responses kept for revalidation
+const RESPONSE_CACHE_CAPACITY: usize = 64 << 20;
+
+// Browsers don't allow setting this, and advertise what they can decompress
+// themselves
+#[cfg(not(target_arch = "wasm32"))]
+const ACCEPT_ENCODING: &str = "zstd, gzip";
+
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+// Bodies may or may not be compressed, regardless of Content-Encoding: the
+// browser transparently decompresses whatever it negotiated, and static
+// archives are stored zstd-compressed. So go by the content instead. (The
+// CBOR for our types never starts with either magic number.)
+fn decode<T>(body: &[u8]) -> Result<T, DataSourceError>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let f: Box<dyn Read> = if body.starts_with(ZSTD_MAGIC) {
+        let f = zstd::Decoder::new(body).map_err(|e| DataSourceError::Decode(e.to_string()))?;
+        Box::new(f)
+    } else if body.starts_with(GZIP_MAGIC) {
+        Box::new(flate2::read::GzDecoder::new(body))
+    } else {
+        Box::new(body)
+    };
+    ciborium::from_reader(f).map_err(|e| DataSourceError::Decode(e.to_string()))
+}
+
+#[derive(Clone)]
+struct TileContainers {
+    summary_tiles: Arc<Mutex<Vec<TileResponse<SummaryTile>>>>,
+    slot_tiles: Arc<Mutex<Vec<TileResponse<SlotTile>>>>,
+    slot_meta_tiles: Arc<Mutex<Vec<TileResponse<SlotMetaTile>>>>,
+}
+
+impl TileContainers {
+    // Decode outside of token.finish, which holds the tracker lock
+    fn push(
+        &self,
+        req: &BatchTileRequest,
+        body: Result<&[u8], DataSourceError>,
+        token: &RequestToken,
+    ) {
+        let (entry_id, tile_id, full) = (&req.entry_id, req.tile_id, req.full);
+        match req.kind {
+            TileKind::Summary => {
+                let response = TileResponse::new(entry_id, tile_id, full, body.and_then(decode));
+                token.finish(|| self.summary_tiles.lock().unwrap().push(response));
+            }
+            TileKind::Slot => {
+                let response = TileResponse::new(entry_id, tile_id, full, body.and_then(decode));
+                token.finish(|| self.slot_tiles.lock().unwrap().push(response));
+            }
+            TileKind::SlotMeta => {
+                let response = TileResponse::new(entry_id, tile_id, full, body.and_then(decode));
+                token.finish(|| self.slot_meta_tiles.lock().unwrap().push(response));
+            }
+        }
+    }
+}
+
+// Compressed responses and their ETags, keyed by URL, so that a tile
+// requested again can be revalidated with If-None-Match rather than
+// downloaded again. Evicts the oldest responses first.
+struct ResponseCache {
+    capacity: usize,
+    bytes: usize,
+    entries: BTreeMap<String, (String, Bytes)>,
+    order: VecDeque<String>,
+}
+
+impl ResponseCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            bytes: 0,
+            entries: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<(String, Bytes)> {
+        self.entries.get(key).cloned()
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn insert(&mut self, key: String, etag: String, body: Bytes) {
+        if body.len() > self.capacity {
+            return;
+        }
+        self.bytes += body.len();
+        match self.entries.insert(key.clone(), (etag, body)) {
+            Some((_, old)) => self.bytes -= old.len(),
+            None => self.order.push_back(key),
+        }
+        while self.bytes > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            let (_, body) = self.entries.remove(&oldest).unwrap();
+            self.bytes -= body.len();
+        }
+    }
+}
+
+type Results<T> = Arc<Mutex<Vec<Result<T, DataSourceError>>>>;
+type KeyedResults<K, T> = Arc<Mutex<Vec<(K, Result<T, DataSourceError>)>>>;
+
+// Whether a static archive's search index is available, for servers
+// without POST /search
+enum SearchIndexState {
+    Unknown,
+    Fetching,
+    Missing,
+    Present(Arc<SearchIndexInfo>),
+}
+
+// A search answered by the client itself from a static archive: first
+// fetch the index shards for the query's tokens, then the tiles holding
+// candidate items. Requests the index can't answer (e.g., regular
+// expressions) skip straight to scanning every tile.
+struct IndexSearch {
+    request: SearchRequest,
+    stage: IndexSearchStage,
+}
+
+type TileKey = (EntryID, TileID);
+
+enum IndexSearchStage {
+    Shards {
+        lookup: IndexLookup,
+        count: usize,
+        // By position
+        shards: KeyedResults<usize, SearchIndexShard>,
+    },
+    Tiles {
+        // None to check every item in the tile
+        candidates: BTreeMap<TileKey, Option<BTreeSet<ItemUID>>>,
+        tiles: KeyedResults<TileKey, SlotMetaTile>,
+    },
+}
+
+// The opaque part of an ETag, i.e., without quotes or weakness indicator
+fn etag_value(etag: &str) -> &str {
+    etag.trim_start_matches("W/").trim_matches('"')
+}
 
 pub struct HTTPClientDataSource {
     pub baseurl: Url,
-    pub client: Client,
-    infos: Arc<Mutex<Vec<DataSourceInfo>>>,
-    summary_tiles: Arc<Mutex<Vec<SummaryTile>>>,
-    slot_tiles: Arc<Mutex<Vec<SlotTile>>>,
-    slot_meta_tiles: Arc<Mutex<Vec<SlotMetaTile>>>,
+    // Errors creating the client are reported on every request
+    client: Result<Client, DataSourceError>,
+    policy: RequestPolicy,
+    infos: Arc<Mutex<Vec<Result<DataSourceInfo, DataSourceError>>>>,
+    tiles: TileContainers,
+    requests: RequestTracker,
+    // Tile requests issued since the last get_*, sent together as a batch
+    pending: Vec<PendingTile>,
+    // Cleared when the server does not support POST /tiles (e.g., when
+    // hosting a static archive), after which tiles are fetched one by one
+    batch_supported: Arc<AtomicBool>,
+    // Requests from a rejected batch, to be reissued individually
+    retry: Arc<Mutex<Vec<PendingTile>>>,
+    cache: Arc<Mutex<ResponseCache>>,
+    // Identity of the profile (from the ETag of /info). Included in tile
+    // URLs, so that tiles cached (by us or the browser) for one profile
+    // are never used for another served from the same address.
+    version: Arc<Mutex<Option<String>>>,
+    // Cleared when neither the server (with POST /search) nor a static
+    // archive (with a search index) can search, after which the viewer
+    // searches tiles itself
+    search_supported: Arc<AtomicBool>,
+    search_route_supported: Arc<AtomicBool>,
+    search_results: Results<SearchResponse>,
+    // Requests rejected by POST /search, to be answered from the index
+    search_queue: Arc<Mutex<Vec<SearchRequest>>>,
+    search_index: Arc<Mutex<SearchIndexState>>,
+    index_searches: Vec<IndexSearch>,
+    // Needed to search on the client side
+    info: Arc<Mutex<Option<DataSourceInfo>>>,
+    // Cleared when the server does not support GET /info/updates (i.e.,
+    // the profile isn't live)
+    info_updates_supported: Arc<AtomicBool>,
+    info_updates: Results<Option<InfoUpdate>>,
+}
+
+type PendingTile = (BatchTileRequest, RequestToken);
+
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+fn build_client(policy: &RequestPolicy) -> Result<Client, DataSourceError> {
+    let builder = ClientBuilder::new();
+    // In the browser, timeouts are implemented by fetch
+    #[cfg(not(target_arch = "wasm32"))]
+    let builder = builder
+        .connect_timeout(policy.connect_timeout)
+        .timeout(policy.read_timeout);
+    builder
+        .build()
+        .map_err(|e| DataSourceError::Io(format!("unable to create HTTP client: {}", e)))
 }
 
 impl HTTPClientDataSource {
     pub fn new(baseurl: Url) -> Self {
+        let policy = RequestPolicy::default();
         Self {
             baseurl,
-            client: ClientBuilder::new().build().unwrap(),
+            client: build_client(&policy),
+            policy,
             infos: Arc::new(Mutex::new(Vec::new())),
-            summary_tiles: Arc::new(Mutex::new(Vec::new())),
-            slot_tiles: Arc::new(Mutex::new(Vec::new())),
-            slot_meta_tiles: Arc::new(Mutex::new(Vec::new())),
+            tiles: TileContainers {
+                summary_tiles: Arc::new(Mutex::new(Vec::new())),
+                slot_tiles: Arc::new(Mutex::new(Vec::new())),
+                slot_meta_tiles: Arc::new(Mutex::new(Vec::new())),
+            },
+            requests: RequestTracker::default(),
+            pending: Vec::new(),
+            batch_supported: Arc::new(AtomicBool::new(true)),
+            retry: Arc::new(Mutex::new(Vec::new())),
+            cache: Arc::new(Mutex::new(ResponseCache::new(RESPONSE_CACHE_CAPACITY))),
+            version: Arc::new(Mutex::new(None)),
+            search_supported: Arc::new(AtomicBool::new(true)),
+            search_route_supported: Arc::new(AtomicBool::new(true)),
+            search_results: Arc::new(Mutex::new(Vec::new())),
+            search_queue: Arc::new(Mutex::new(Vec::new())),
+            search_index: Arc::new(Mutex::new(SearchIndexState::Unknown)),
+            index_searches: Vec::new(),
+            info: Arc::new(Mutex::new(None)),
+            info_updates_supported: Arc::new(AtomicBool::new(true)),
+            info_updates: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    fn request<T>(&mut self, url: Url, container: Arc<Mutex<Vec<T>>>)
-    where
-        T: 'static + Sync + Send + for<'a> Deserialize<'a>,
-    {
+    pub fn with_policy(mut self, policy: RequestPolicy) -> Self {
+        self.client = build_client(&policy);
+        self.policy = policy;
+        self
+    }
+
+    fn client(&self) -> Result<&Client, DataSourceError> {
+        self.client.as_ref().map_err(Clone::clone)
+    }
+
+    // GET the URL, with a conditional request if we have a cached copy.
+    // on_done receives the body (fresh or cached) and its ETag.
+    fn get(
+        &self,
+        url: Result<Url, url::ParseError>,
+        tokens: Vec<RequestToken>,
+        on_done: impl 'static + Send + FnOnce(Result<(Bytes, Option<String>), DataSourceError>),
+    ) {
+        let target = url
+            .map_err(|e| DataSourceError::BadRequest(format!("invalid url: {}", e)))
+            .and_then(|url| Ok((self.client()?, url)));
+        let (client, url) = match target {
+            Ok(target) => target,
+            Err(error) => {
+                on_done(Err(error));
+                return;
+            }
+        };
+
         info!("fetch: {}", url);
-        let request = self
-            .client
+        let key = url.to_string();
+        let cached = self.cache.lock().unwrap().get(&key);
+        let mut request = client
             .get(url)
             .header("Accept", "*/*")
             .header("Content-Type", "application/octet-stream;");
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            request = request.header("Accept-Encoding", ACCEPT_ENCODING);
+        }
+        if let Some((etag, _)) = &cached {
+            request = request.header("If-None-Match", etag);
+        }
+        let cache = self.cache.clone();
         fetch(
             request,
-            move |response: Result<DataSourceResponse, String>| {
-                let f = response.unwrap().body.reader();
-                let f = zstd::Decoder::new(f).expect("zstd decompression failed");
-                let result = ciborium::from_reader(f).expect("cbor decoding failed");
-                container.lock().unwrap().push(result);
+            self.policy.clone(),
+            tokens,
+            move |response: Result<DataSourceResponse, DataSourceError>| {
+                let result = response.and_then(|response| {
+                    if response.not_modified {
+                        let (etag, body) = cached.ok_or_else(|| {
+                            DataSourceError::Decode("not modified, but nothing cached".to_owned())
+                        })?;
+                        return Ok((body, Some(etag)));
+                    }
+                    if let Some(etag) = &response.etag {
+                        let body = response.body.clone();
+                        cache.lock().unwrap().insert(key, etag.clone(), body);
+                    }
+                    Ok((response.body, response.etag))
+                });
+                on_done(result);
+            },
+        );
+    }
+
+    fn tile_url(
+        &self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> Result<Url, url::ParseError> {
+        let req = TileRequestRef { entry_id, tile_id };
+        let mut url = self
+            .baseurl
+            .join(&format!("{}/", kind.name()))?
+            .join(&req.to_slug())?;
+        url.query_pairs_mut().append_pair("full", &full.to_string());
+        if let Some(version) = &*self.version.lock().unwrap() {
+            url.query_pairs_mut().append_pair("v", version);
+        }
+        Ok(url)
+    }
+
+    fn request_tile(&mut self, (req, token): PendingTile) {
+        let url = self.tile_url(req.kind, &req.entry_id, req.tile_id, req.full);
+        let tiles = self.tiles.clone();
+        self.get(url, vec![token.clone()], move |result| {
+            let body = result.map(|(body, _)| body);
+            tiles.push(&req, body.as_deref().map_err(Clone::clone), &token);
+        });
+    }
+
+    fn request_batch(&mut self, mut batch: Vec<PendingTile>) {
+        let target = (self.baseurl.join("tiles"))
+            .map_err(|e| DataSourceError::BadRequest(format!("invalid url: {}", e)))
+            .and_then(|url| Ok((self.client()?, url)));
+        let (client, url) = match target {
+            Ok(target) => target,
+            Err(error) => {
+                for (req, token) in &batch {
+                    self.tiles.push(req, Err(error.clone()), token);
+                }
+                return;
+            }
+        };
+
+        let reqs: Vec<_> = batch.iter().map(|(req, _)| req).collect();
+        let mut body = Vec::new();
+        ciborium::into_writer(&reqs, &mut body).expect("ciborium encoding failed");
+        let tokens = batch.iter().map(|(_, token)| token.clone()).collect();
+        let keys: Vec<_> = (batch.iter())
+            .map(|(req, _)| {
+                let url = self.tile_url(req.kind, &req.entry_id, req.tile_id, req.full);
+                url.ok().map(String::from)
+            })
+            .collect();
+        let cache = self.cache.clone();
+
+        info!("fetch: {} ({} tiles)", url, batch.len());
+        let request = client
+            .post(url)
+            .header("Accept", "*/*")
+            .header("Content-Type", "application/octet-stream;")
+            .body(body);
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.header("Accept-Encoding", ACCEPT_ENCODING);
+        let tiles = self.tiles.clone();
+        let batch_supported = self.batch_supported.clone();
+        let retry = self.retry.clone();
+        let mut decoder = FrameDecoder::default();
+        let mut etag = None;
+        let mut missing: BTreeSet<_> = (0..batch.len()).collect();
+        // POST /tiles only reads, so it is safe to retry. Each tile is
+        // delivered as soon as its frame arrives.
+        fetch_stream(
+            request,
+            self.policy.clone(),
+            tokens,
+            move |event: StreamEvent| match event {
+                StreamEvent::Start(Ok(response)) => etag = response.etag,
+                StreamEvent::Start(Err(DataSourceError::NotFound(..))) => {
+                    // Most likely a server without the batch route
+                    batch_supported.store(false, Ordering::Relaxed);
+                    retry.lock().unwrap().extend(std::mem::take(&mut batch));
+                }
+                StreamEvent::Start(Err(e)) => {
+                    for (req, token) in &batch {
+                        tiles.push(req, Err(e.clone()), token);
+                    }
+                }
+                StreamEvent::Chunk(chunk) => {
+                    for (index, result) in decoder.push(&chunk) {
+                        let index = index as usize;
+                        if !missing.remove(&index) {
+                            continue;
+                        }
+                        // The batch's ETag applies to every tile in it
+                        if let (Ok(body), Some(etag), Some(key)) = (&result, &etag, &keys[index]) {
+                            (cache.lock().unwrap()).insert(key.clone(), etag.clone(), body.clone());
+                        }
+                        let (req, token) = &batch[index];
+                        tiles.push(req, result.as_deref().map_err(Clone::clone), token);
+                    }
+                }
+                StreamEvent::End(result) => {
+                    // Every request must produce exactly one result
+                    let error = match result.and_then(|()| decoder.finish()) {
+                        Ok(()) => DataSourceError::Decode("tile missing from batch".to_owned()),
+                        Err(e) => e,
+                    };
+                    for index in std::mem::take(&mut missing) {
+                        let (req, token) = &batch[index];
+                        tiles.push(req, Err(error.clone()), token);
+                    }
+                }
             },
         );
     }
+
+    // Send every tile request issued since the last flush. This runs when
+    // results are requested, so all the requests from a frame are
+    // coalesced.
+    fn flush(&mut self) {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.append(&mut self.retry.lock().unwrap());
+        pending.retain(|(_, token)| !token.is_cancelled());
+        if pending.is_empty() {
+            return;
+        }
+
+        // Tiles we have cached are revalidated individually, since a 304
+        // is cheap for the server and there's no conditional batch request
+        let (cached, mut pending): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(req, _)| {
+            let url = self.tile_url(req.kind, &req.entry_id, req.tile_id, req.full);
+            url.map_or(false, |url| {
+                self.cache.lock().unwrap().contains(url.as_str())
+            })
+        });
+        for tile in cached {
+            self.request_tile(tile);
+        }
+
+        if pending.len() == 1 || !self.batch_supported.load(Ordering::Relaxed) {
+            for tile in pending {
+                self.request_tile(tile);
+            }
+            return;
+        }
+
+        while !pending.is_empty() {
+            let rest = pending.split_off(pending.len().min(MAX_BATCH_SIZE));
+            let batch = std::mem::replace(&mut pending, rest);
+            self.request_batch(batch);
+        }
+    }
+
+    fn search_index_url(&self, name: &str) -> Result<Url, url::ParseError> {
+        let mut url = self.baseurl.join("search_index/")?.join(name)?;
+        if let Some(version) = &*self.version.lock().unwrap() {
+            url.query_pairs_mut().append_pair("v", version);
+        }
+        Ok(url)
+    }
+
+    fn fetch_search_index(&mut self) {
+        let url = self.search_index_url("info");
+        let search_index = self.search_index.clone();
+        let search_supported = self.search_supported.clone();
+        self.get(url, Vec::new(), move |result| {
+            let result = result.and_then(|(body, _)| decode(&body));
+            let mut search_index = search_index.lock().unwrap();
+            *search_index = match result {
+                Ok(index) => SearchIndexState::Present(Arc::new(index)),
+                Err(_) => {
+                    // Not an archive, or one written without an index
+                    search_supported.store(false, Ordering::Relaxed);
+                    SearchIndexState::Missing
+                }
+            };
+        });
+    }
+
+    // Move queued requests along once we know whether there's an index
+    fn start_index_searches(&mut self) {
+        let queue = std::mem::take(&mut *self.search_queue.lock().unwrap());
+        if queue.is_empty() {
+            return;
+        }
+        let mut search_index = self.search_index.lock().unwrap();
+        let index = match &*search_index {
+            SearchIndexState::Present(index) => Some(index.clone()),
+            SearchIndexState::Missing => None,
+            SearchIndexState::Unknown | SearchIndexState::Fetching => {
+                self.search_queue.lock().unwrap().extend(queue);
+                if let SearchIndexState::Unknown = *search_index {
+                    *search_index = SearchIndexState::Fetching;
+                    drop(search_index);
+                    self.fetch_search_index();
+                }
+                return;
+            }
+        };
+        drop(search_index);
+        let Some(index) = index else {
+            let mut results = self.search_results.lock().unwrap();
+            for _ in queue {
+                let error = DataSourceError::NotFound("search is not supported".to_owned());
+                results.push(Err(error));
+            }
+            return;
+        };
+        for request in queue {
+            let stage = match index.lookup(&request) {
+                Some(lookup) => {
+                    let shards = Arc::new(Mutex::new(Vec::new()));
+                    let shard_indices = lookup.shards();
+                    for i in &shard_indices {
+                        let url = self.search_index_url(&i.to_string());
+                        let shards = shards.clone();
+                        let i = *i;
+                        self.get(url, Vec::new(), move |result| {
+                            let result = result.and_then(|(body, _)| decode(&body));
+                            shards.lock().unwrap().push((i, result));
+                        });
+                    }
+                    IndexSearchStage::Shards {
+                        lookup,
+                        count: shard_indices.len(),
+                        shards,
+                    }
+                }
+                None => self.scan_all_tiles(&request),
+            };
+            self.index_searches.push(IndexSearch { request, stage });
+        }
+    }
+
+    fn scan_all_tiles(&mut self, request: &SearchRequest) -> IndexSearchStage {
+        let info = self.info.lock().unwrap();
+        let candidates = (info.iter())
+            .flat_map(|info| scan_tiles(info, request))
+            .map(|tile| (tile, None))
+            .collect();
+        drop(info);
+        self.fetch_candidate_tiles(candidates)
+    }
+
+    fn fetch_candidate_tiles(
+        &mut self,
+        candidates: BTreeMap<TileKey, Option<BTreeSet<ItemUID>>>,
+    ) -> IndexSearchStage {
+        let tiles = Arc::new(Mutex::new(Vec::new()));
+        for (entry_id, tile_id) in candidates.keys() {
+            let url = self.tile_url(TileKind::SlotMeta, entry_id, *tile_id, true);
+            let tiles = tiles.clone();
+            let key = (entry_id.clone(), *tile_id);
+            self.get(url, Vec::new(), move |result| {
+                let result = result.and_then(|(body, _)| decode(&body));
+                tiles.lock().unwrap().push((key, result));
+            });
+        }
+        IndexSearchStage::Tiles { candidates, tiles }
+    }
+
+    // Returns the search if it's still waiting on responses
+    fn poll_index_search(&mut self, search: IndexSearch) -> Option<IndexSearch> {
+        let IndexSearch { request, stage } = search;
+        match stage {
+            IndexSearchStage::Shards {
+                lookup,
+                count,
+                shards,
+            } => {
+                if shards.lock().unwrap().len() < count {
+                    let stage = IndexSearchStage::Shards {
+                        lookup,
+                        count,
+                        shards,
+                    };
+                    return Some(IndexSearch { request, stage });
+                }
+                // A shard that fails to load just means scanning more
+                let shards = std::mem::take(&mut *shards.lock().unwrap());
+                let shards = (shards.into_iter())
+                    .filter_map(|(i, shard)| Some((i, shard.ok()?)))
+                    .collect();
+                let stage = match candidates(&request, &lookup, &shards) {
+                    Some(candidates) => {
+                        let candidates = (candidates.into_iter())
+                            .map(|(tile, item_uids)| (tile, Some(item_uids)))
+                            .collect();
+                        self.fetch_candidate_tiles(candidates)
+                    }
+                    None => self.scan_all_tiles(&request),
+                };
+                // Check again, in case there were no candidates
+                self.poll_index_search(IndexSearch { request, stage })
+            }
+            IndexSearchStage::Tiles { candidates, tiles } => {
+                if tiles.lock().unwrap().len() < candidates.len() {
+                    let stage = IndexSearchStage::Tiles { candidates, tiles };
+                    return Some(IndexSearch { request, stage });
+                }
+                let tiles = std::mem::take(&mut *tiles.lock().unwrap());
+                let result = self.collect_index_search(&request, candidates, tiles);
+                let response = result.map(|results| SearchResponse { request, results });
+                self.search_results.lock().unwrap().push(response);
+                None
+            }
+        }
+    }
+
+    fn collect_index_search(
+        &self,
+        request: &SearchRequest,
+        candidates: BTreeMap<TileKey, Option<BTreeSet<ItemUID>>>,
+        tiles: Vec<(TileKey, Result<SlotMetaTile, DataSourceError>)>,
+    ) -> Result<Vec<SearchResult>, DataSourceError> {
+        let info = self.info.lock().unwrap();
+        let info = info
+            .as_ref()
+            .ok_or_else(|| DataSourceError::BadRequest("info has not been fetched".to_owned()))?;
+        let mut collector = SearchCollector::new(request, &info.field_schema)?;
+        // Tiles arrive in any order, but results should be in scan order
+        let mut tiles: BTreeMap<_, _> = tiles.into_iter().collect();
+        collect_tiles(&mut collector, candidates.into_iter().collect(), |key| {
+            tiles.remove(key).unwrap_or_else(|| {
+                Err(DataSourceError::NotFound(format!("missing tile {:?}", key)))
+            })
+        })?;
+        Ok(collector.finish())
+    }
+
+    fn enqueue(&mut self, kind: TileKind, entry_id: &EntryID, tile_id: TileID, full: bool) {
+        let req = BatchTileRequest {
+            kind,
+            entry_id: entry_id.clone(),
+            tile_id,
+            full,
+        };
+        let token = self.requests.start(kind, entry_id, tile_id, full);
+        self.pending.push((req, token));
+    }
 }
 
 impl DeferredDataSource for HTTPClientDataSource {
     fn fetch_info(&mut self) {
-        let url = self.baseurl.join("info").expect("invalid baseurl");
-        self.request::<DataSourceInfo>(url, self.infos.clone());
+        let url = self.baseurl.join("info");
+        let infos = self.infos.clone();
+        let info = self.info.clone();
+        let version = self.version.clone();
+        self.get(url, Vec::new(), move |result| {
+            let result = result.and_then(|(body, etag)| {
+                *version.lock().unwrap() = etag.map(|etag| etag_value(&etag).to_owned());
+                decode::<DataSourceInfo>(&body)
+            });
+            if let Ok(result) = &result {
+                *info.lock().unwrap() = Some(result.clone());
+            }
+            infos.lock().unwrap().push(result);
+        });
     }
 
-    fn get_infos(&mut self) -> Vec<DataSourceInfo> {
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
+        self.flush();
         std::mem::take(&mut self.infos.lock().unwrap())
     }
 
     fn fetch_summary_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        let req = TileRequestRef { entry_id, tile_id };
-        let mut url = self
-            .baseurl
-            .join("summary_tile/")
-            .and_then(|u| u.join(&req.to_slug()))
-            .expect("invalid baseurl");
-        url.set_query(Some(&format!("full={}", full)));
-        self.request::<SummaryTile>(url, self.summary_tiles.clone());
+        self.enqueue(TileKind::Summary, entry_id, tile_id, full);
     }
 
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile> {
-        std::mem::take(&mut self.summary_tiles.lock().unwrap())
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
+        self.flush();
+        std::mem::take(&mut self.tiles.summary_tiles.lock().unwrap())
     }
 
     fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        let req = TileRequestRef { entry_id, tile_id };
-        let mut url = self
-            .baseurl
-            .join("slot_tile/")
-            .and_then(|u| u.join(&req.to_slug()))
-            .expect("invalid baseurl");
-        url.set_query(Some(&format!("full={}", full)));
-        self.request::<SlotTile>(url, self.slot_tiles.clone());
+        self.enqueue(TileKind::Slot, entry_id, tile_id, full);
     }
 
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile> {
-        std::mem::take(&mut self.slot_tiles.lock().unwrap())
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
+        self.flush();
+        std::mem::take(&mut self.tiles.slot_tiles.lock().unwrap())
     }
 
     fn fetch_slot_meta_tile(&mut self, entry_id: &EntryID, tile_id: TileID, full: bool) {
-        let req = TileRequestRef { entry_id, tile_id };
-        let mut url = self
-            .baseurl
-            .join("slot_meta_tile/")
-            .and_then(|u| u.join(&req.to_slug()))
-            .expect("invalid baseurl");
-        url.set_query(Some(&format!("full={}", full)));
-        self.request::<SlotMetaTile>(url, self.slot_meta_tiles.clone());
+        self.enqueue(TileKind::SlotMeta, entry_id, tile_id, full);
+    }
+
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
+        self.flush();
+        std::mem::take(&mut self.tiles.slot_meta_tiles.lock().unwrap())
+    }
+
+    // Requests that haven't been sent are dropped, and in-flight requests
+    // are aborted once nothing is waiting on them
+    fn cancel_tile(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        self.requests.cancel_tile(kind, entry_id, tile_id, full)
+    }
+
+    fn supports_search(&self) -> bool {
+        self.search_supported.load(Ordering::Relaxed)
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        if !self.search_route_supported.load(Ordering::Relaxed) {
+            self.search_queue.lock().unwrap().push(request.clone());
+            return;
+        }
+
+        let search_request = request.clone();
+        let results = self.search_results.clone();
+        let target = (self.baseurl.join("search"))
+            .map_err(|e| DataSourceError::BadRequest(format!("invalid url: {}", e)))
+            .and_then(|url| Ok((self.client()?, url)));
+        let (client, url) = match target {
+            Ok(target) => target,
+            Err(error) => {
+                results.lock().unwrap().push(Err(error));
+                return;
+            }
+        };
+
+        let mut body = Vec::new();
+        ciborium::into_writer(request, &mut body).expect("ciborium encoding failed");
+
+        info!("fetch: {}", url);
+        let request = client
+            .post(url)
+            .header("Accept", "*/*")
+            .header("Content-Type", "application/octet-stream;")
+            .body(body);
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.header("Accept-Encoding", ACCEPT_ENCODING);
+        let search_route_supported = self.search_route_supported.clone();
+        let search_queue = self.search_queue.clone();
+        // Not retried: a search may scan the whole profile, and one that
+        // timed out would most likely time out again after redoing the scan
+        let policy = RequestPolicy {
+            max_retries: 0,
+            ..self.policy.clone()
+        };
+        fetch(
+            request,
+            policy,
+            Vec::new(),
+            move |response: Result<DataSourceResponse, DataSourceError>| {
+                let result = response.and_then(|response| decode(&response.body));
+                if is_unsupported(&result) {
+                    // Most likely a static archive, try its search index
+                    search_route_supported.store(false, Ordering::Relaxed);
+                    search_queue.lock().unwrap().push(search_request);
+                    return;
+                }
+                results.lock().unwrap().push(result);
+            },
+        );
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        self.start_index_searches();
+        for search in std::mem::take(&mut self.index_searches) {
+            if let Some(search) = self.poll_index_search(search) {
+                self.index_searches.push(search);
+            }
+        }
+        std::mem::take(&mut self.search_results.lock().unwrap())
+    }
+
+    fn supports_info_updates(&self) -> bool {
+        self.info_updates_supported.load(Ordering::Relaxed)
+    }
+
+    // A long poll: the server holds the request until the profile changes
+    // (or INFO_UPDATE_TIMEOUT passes, in which case there's no content)
+    fn fetch_info_update(&mut self, since: u64) {
+        let url = self.baseurl.join("info/updates").map(|mut url| {
+            url.query_pairs_mut()
+                .append_pair("since", &since.to_string());
+            url
+        });
+        let info = self.info.clone();
+        let info_updates_supported = self.info_updates_supported.clone();
+        let info_updates = self.info_updates.clone();
+        self.get(url, Vec::new(), move |result| {
+            let result = result.and_then(|(body, _)| {
+                if body.is_empty() {
+                    return Ok(None);
+                }
+                let update: InfoUpdate = decode(&body)?;
+                *info.lock().unwrap() = Some(update.info.clone());
+                Ok(Some(update))
+            });
+            if is_unsupported(&result) {
+                info_updates_supported.store(false, Ordering::Relaxed);
+            }
+            info_updates.lock().unwrap().push(result);
+        });
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        std::mem::take(&mut self.info_updates.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache() {
+        let mut cache = ResponseCache::new(10);
+        cache.insert(
+            "a".to_owned(),
+            "\"1\"".to_owned(),
+            Bytes::from_static(b"aaaa"),
+        );
+        cache.insert(
+            "b".to_owned(),
+            "\"2\"".to_owned(),
+            Bytes::from_static(b"bbbb"),
+        );
+        assert_eq!(cache.get("a").unwrap().0, "\"1\"");
+
+        // Replacing an entry doesn't count it twice
+        cache.insert(
+            "a".to_owned(),
+            "\"3\"".to_owned(),
+            Bytes::from_static(b"aa"),
+        );
+        assert_eq!(cache.bytes, 6);
+        assert_eq!(
+            cache.get("a").unwrap(),
+            ("\"3\"".to_owned(), Bytes::from_static(b"aa"))
+        );
+
+        // Evicts the oldest entries first, and ignores ones that can't fit
+        cache.insert(
+            "c".to_owned(),
+            "\"4\"".to_owned(),
+            Bytes::from_static(b"cccccc"),
+        );
+        assert!(!cache.contains("a"));
+        assert!(cache.contains("b") && cache.contains("c"));
+        cache.insert("d".to_owned(), "\"5\"".to_owned(), Bytes::from(vec![0; 11]));
+        assert!(!cache.contains("d"));
+        assert_eq!(cache.bytes, 10);
+
+        assert_eq!(etag_value("W/\"abc-zstd\""), "abc-zstd");
     }
 
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile> {
-        std::mem::take(&mut self.slot_meta_tiles.lock().unwrap())
+    // A cancelled request is aborted, closing its connection, even while
+    // the server has yet to answer
+    #[test]
+    fn test_cancel_aborts_request() {
+        use std::net::TcpListener;
+        use std::time::Duration;
+
+        use crate::timestamp::{Interval, Timestamp};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let baseurl = Url::parse(&format!("http://{}/", addr)).unwrap();
+        let mut client = HTTPClientDataSource::new(baseurl);
+
+        let entry_id = EntryID::root().child(0);
+        let tile_id = TileID(Interval::new(Timestamp(0), Timestamp(10)));
+        client.fetch_slot_tile(&entry_id, tile_id, false);
+        assert!(client.get_slot_tiles().is_empty());
+
+        // Never answer, so the request stays in flight until it's aborted
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(30)))
+            .unwrap();
+        let mut buffer = [0; 4096];
+        assert!(stream.read(&mut buffer).unwrap() > 0);
+
+        // Only the request it was made for is cancelled
+        assert_eq!(
+            client.cancel_tile(TileKind::Slot, &entry_id, tile_id, true),
+            0
+        );
+        assert_eq!(
+            client.cancel_tile(TileKind::Slot, &entry_id, tile_id, false),
+            1
+        );
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => break,
+                Err(e) => panic!("connection still open: {}", e),
+            }
+        }
+        assert!(client.get_slot_tiles().is_empty());
     }
 }
@@ -1,21 +1,247 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
 use bytes::Bytes;
 
-#[cfg(not(target_arch = "wasm32"))]
-use reqwest::blocking::RequestBuilder;
-#[cfg(target_arch = "wasm32")]
-use reqwest::RequestBuilder;
+use futures_util::future::{AbortHandle, AbortRegistration};
+
+use log::warn;
+
+use reqwest::header::{HeaderMap, ETAG};
+use reqwest::{RequestBuilder, StatusCode};
+
+use crate::data::DataSourceError;
+use crate::deferred_data::RequestToken;
+use crate::http::schema::{SCHEMA_VERSION, SCHEMA_VERSION_HEADER};
 
 pub struct DataSourceResponse {
     pub body: Bytes,
+    pub etag: Option<String>,
+    // The server answered a conditional request with 304, so the body is
+    // empty and the client should use its cached copy
+    pub not_modified: bool,
 }
 
+// A response whose body is handed over as it arrives, for bodies that are
+// useful before they're complete (see fetch_stream)
+pub enum StreamEvent {
+    // The status and headers (see check_response), with an empty body.
+    // Nothing follows an error.
+    Start(Result<DataSourceResponse, DataSourceError>),
+    Chunk(Bytes),
+    // The body is complete, or failed partway through
+    End(Result<(), DataSourceError>),
+}
+
+// Timeouts and retries for HTTP requests. Failed requests are retried after
+// an exponentially increasing, randomly jittered delay, but only when the
+// failure might be transient: connection errors, timeouts and 5xx
+// responses.
+#[derive(Debug, Clone)]
+pub struct RequestPolicy {
+    // Native only: the browser doesn't expose connection setup separately
+    pub connect_timeout: Duration,
+    // Time allowed for the entire request, including reading the body
+    pub read_timeout: Duration,
+    pub max_retries: u32,
+    // Delay before the first retry, doubled on each subsequent one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RequestPolicy {
+    fn backoff(&self, retries: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << retries.min(16))
+            .min(self.max_backoff);
+        // Jitter between half and all of the backoff, so that clients that
+        // failed together don't all retry together
+        backoff.mul_f64(0.5 + 0.5 * rand::random::<f64>())
+    }
+
+    // Returns how long to wait before retrying, or None if the result is
+    // final
+    pub fn retry_delay<T>(
+        &self,
+        retries: u32,
+        result: &Result<T, DataSourceError>,
+    ) -> Option<Duration> {
+        let Err(error) = result else {
+            return None;
+        };
+        let transient = matches!(error, DataSourceError::Io(..) | DataSourceError::Remote(..));
+        if !transient || retries >= self.max_retries {
+            return None;
+        }
+        let delay = self.backoff(retries);
+        warn!(
+            "request failed ({}), retry {} of {} in {:?}",
+            error,
+            retries + 1,
+            self.max_retries,
+            delay
+        );
+        Some(delay)
+    }
+}
+
+// Requests are retried according to the policy, so they must be idempotent.
+//
+// The tokens are for the tile requests served by this HTTP request (none if
+// it can't be cancelled). Once all of them are cancelled, the request is
+// aborted and on_done is never called.
 pub fn fetch(
     request: RequestBuilder,
-    on_done: impl 'static + Send + FnOnce(Result<DataSourceResponse, String>),
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_done: impl 'static + Send + FnOnce(Result<DataSourceResponse, DataSourceError>),
+) {
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::http::fetch_native::fetch(request, policy, tokens, Box::new(on_done));
+
+    #[cfg(target_arch = "wasm32")]
+    crate::http::fetch_web::fetch(request, policy, tokens, Box::new(on_done));
+}
+
+// Like fetch, but on_event receives the body in chunks as it arrives. Only
+// failures before the body starts are retried, since chunks that have
+// already been handed over can't be taken back. Aborted requests stop
+// producing events at any point.
+pub fn fetch_stream(
+    request: RequestBuilder,
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_event: impl 'static + Send + FnMut(StreamEvent),
 ) {
     #[cfg(not(target_arch = "wasm32"))]
-    crate::http::fetch_native::fetch(request, Box::new(on_done));
+    crate::http::fetch_native::fetch_stream(request, policy, tokens, Box::new(on_event));
 
     #[cfg(target_arch = "wasm32")]
-    crate::http::fetch_web::fetch(request, Box::new(on_done));
+    crate::http::fetch_web::fetch_stream(request, policy, tokens, Box::new(on_event));
+}
+
+// Abort once every tile request served by a fetch is cancelled. Dropping
+// the reqwest future aborts the underlying request (in the browser, the
+// fetch).
+pub fn abort_on_cancel(tokens: &[RequestToken]) -> AbortRegistration {
+    let (handle, registration) = AbortHandle::new_pair();
+    let remaining = Arc::new(AtomicUsize::new(tokens.len()));
+    for token in tokens {
+        let handle = handle.clone();
+        let remaining = remaining.clone();
+        token.on_cancel(move || {
+            if remaining.fetch_sub(1, Ordering::Relaxed) == 1 {
+                handle.abort();
+            }
+        });
+    }
+    registration
+}
+
+pub fn check_response(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<DataSourceResponse, DataSourceError> {
+    // Servers that don't send a version (e.g., static hosting) are assumed
+    // to be compatible
+    if let Some(version) = headers.get(SCHEMA_VERSION_HEADER) {
+        let version = version.to_str().unwrap_or("");
+        if version != SCHEMA_VERSION.to_string() {
+            return Err(DataSourceError::Decode(format!(
+                "server uses profile schema version {}, but this viewer supports version {}",
+                version, SCHEMA_VERSION
+            )));
+        }
+    }
+
+    let etag = headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned);
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(DataSourceResponse {
+            body,
+            etag,
+            not_modified: status == StatusCode::NOT_MODIFIED,
+        });
+    }
+
+    // The server reports the error message in the body
+    let message = format!("{}: {}", status, String::from_utf8_lossy(&body));
+    Err(match status {
+        // The route doesn't exist, which callers take to mean that the
+        // request isn't supported (see is_unsupported)
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+            DataSourceError::NotFound(message)
+        }
+        // Transient, so worth retrying
+        StatusCode::TOO_MANY_REQUESTS => DataSourceError::Remote(message),
+        s if s.is_client_error() => DataSourceError::BadRequest(message),
+        _ => DataSourceError::Remote(message),
+    })
+}
+
+pub fn request_error(e: reqwest::Error) -> DataSourceError {
+    // Io errors are retried, which is pointless for a malformed request
+    if e.is_builder() {
+        return DataSourceError::BadRequest(e.to_string());
+    }
+    DataSourceError::Io(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::deferred_data::is_unsupported;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RequestPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(150),
+            ..Default::default()
+        };
+        let io: Result<(), _> = Err(DataSourceError::Io("connection refused".to_owned()));
+        let delay = policy.retry_delay(0, &io).unwrap();
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        // Capped at max_backoff
+        assert!(policy.retry_delay(1, &io).unwrap() <= Duration::from_millis(150));
+        assert!(policy.retry_delay(2, &io).is_none());
+
+        let not_found: Result<(), _> = Err(DataSourceError::NotFound("no such tile".to_owned()));
+        assert!(policy.retry_delay(0, &not_found).is_none());
+        let ok = check_response(StatusCode::NOT_MODIFIED, &HeaderMap::new(), Bytes::new());
+        assert!(ok.as_ref().unwrap().not_modified);
+        assert!(policy.retry_delay(0, &ok).is_none());
+
+        let status = |code: u16| {
+            let status = StatusCode::from_u16(code).unwrap();
+            check_response(status, &HeaderMap::new(), Bytes::new())
+        };
+        for code in [404, 405, 501] {
+            assert!(is_unsupported(&status(code)), "{}", code);
+        }
+        for code in [400, 413, 429, 500] {
+            assert!(!is_unsupported(&status(code)), "{}", code);
+        }
+        assert!(policy.retry_delay(0, &status(429)).is_some());
+        assert!(policy.retry_delay(0, &status(413)).is_none());
+    }
 }
@@ -1,18 +1,153 @@
-use reqwest::blocking::RequestBuilder;
+use std::future::Future;
+use std::sync::Mutex;
 
-use crate::http::fetch::DataSourceResponse;
+use bytes::Bytes;
+
+use futures_util::future::Abortable;
+
+use reqwest::{RequestBuilder, Response};
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::data::DataSourceError;
+use crate::deferred_data::RequestToken;
+use crate::http::fetch::{
+    abort_on_cancel, check_response, request_error, DataSourceResponse, RequestPolicy, StreamEvent,
+};
+
+type OnDone = Box<dyn FnOnce(Result<DataSourceResponse, DataSourceError>) + Send>;
+type OnEvent = Box<dyn FnMut(StreamEvent) + Send>;
+
+// Shared by every client, and started on first use
+static RUNTIME: Mutex<Option<Runtime>> = Mutex::new(None);
+
+fn spawn_future<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mut runtime = RUNTIME.lock().unwrap();
+    let runtime = runtime.get_or_insert_with(|| {
+        Builder::new_multi_thread()
+            .thread_name("http-client")
+            .enable_all()
+            .build()
+            .expect("unable to start HTTP client runtime")
+    });
+    runtime.spawn(future);
+}
+
+async fn send(request: RequestBuilder) -> Result<DataSourceResponse, DataSourceError> {
+    let response = request.send().await.map_err(request_error)?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(request_error)?;
+    check_response(status, &headers, body)
+}
+
+// Timeouts are configured on the client, so there is nothing to do here
+// except retry
+async fn send_with_retry(
+    mut request: RequestBuilder,
+    policy: RequestPolicy,
+) -> Result<DataSourceResponse, DataSourceError> {
+    let mut retries = 0;
+    loop {
+        // Only requests with streaming bodies can't be cloned, and we don't
+        // send any of those
+        let next = request.try_clone();
+        let result = send(request).await;
+        let Some(next) = next else {
+            return result;
+        };
+        let Some(delay) = policy.retry_delay(retries, &result) else {
+            return result;
+        };
+        tokio::time::sleep(delay).await;
+        request = next;
+        retries += 1;
+    }
+}
+
+// Successful responses come back with the body still unread
+async fn start_stream(
+    request: RequestBuilder,
+) -> Result<(DataSourceResponse, Option<Response>), DataSourceError> {
+    let response = request.send().await.map_err(request_error)?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    if !status.is_success() {
+        let body = response.bytes().await.map_err(request_error)?;
+        return check_response(status, &headers, body).map(|head| (head, None));
+    }
+    check_response(status, &headers, Bytes::new()).map(|head| (head, Some(response)))
+}
+
+async fn read_stream(mut body: Response, on_event: &mut OnEvent) -> Result<(), DataSourceError> {
+    while let Some(chunk) = body.chunk().await.map_err(request_error)? {
+        on_event(StreamEvent::Chunk(chunk));
+    }
+    Ok(())
+}
+
+async fn stream_with_retry(
+    mut request: RequestBuilder,
+    policy: RequestPolicy,
+    mut on_event: OnEvent,
+) {
+    let mut retries = 0;
+    let result = loop {
+        let next = request.try_clone();
+        let result = start_stream(request).await;
+        let Some(next) = next else {
+            break result;
+        };
+        let Some(delay) = policy.retry_delay(retries, &result) else {
+            break result;
+        };
+        tokio::time::sleep(delay).await;
+        request = next;
+        retries += 1;
+    };
+
+    let body = match result {
+        Ok((head, body)) => {
+            on_event(StreamEvent::Start(Ok(head)));
+            body
+        }
+        Err(e) => {
+            on_event(StreamEvent::Start(Err(e)));
+            return;
+        }
+    };
+    let result = match body {
+        Some(body) => read_stream(body, &mut on_event).await,
+        None => Ok(()),
+    };
+    on_event(StreamEvent::End(result));
+}
 
 pub fn fetch(
     request: RequestBuilder,
-    on_done: Box<dyn FnOnce(Result<DataSourceResponse, String>) + Send>,
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_done: OnDone,
+) {
+    let registration = abort_on_cancel(&tokens);
+    spawn_future(async move {
+        if let Ok(res) = Abortable::new(send_with_retry(request, policy), registration).await {
+            on_done(res)
+        }
+    });
+}
+
+pub fn fetch_stream(
+    request: RequestBuilder,
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_event: OnEvent,
 ) {
-    rayon::spawn(move || {
-        let result = request
-            .send()
-            .expect("request failed")
-            .bytes()
-            .expect("unable to get bytes");
-
-        on_done(Ok(DataSourceResponse { body: result }))
+    let registration = abort_on_cancel(&tokens);
+    spawn_future(async move {
+        let _ = Abortable::new(stream_with_retry(request, policy, on_event), registration).await;
     });
 }
@@ -1,6 +1,19 @@
-use reqwest::RequestBuilder;
+use std::time::Duration;
 
-use crate::http::fetch::DataSourceResponse;
+use bytes::Bytes;
+
+use futures_util::future::{select, Abortable, Either};
+use futures_util::StreamExt;
+
+use reqwest::{RequestBuilder, Response};
+
+use crate::data::DataSourceError;
+use crate::deferred_data::RequestToken;
+use crate::http::fetch::{
+    abort_on_cancel, check_response, request_error, DataSourceResponse, RequestPolicy, StreamEvent,
+};
+
+type OnEvent = Box<dyn FnMut(StreamEvent) + Send>;
 
 /// Spawn an async task.
 ///
@@ -13,21 +26,140 @@ where
     wasm_bindgen_futures::spawn_local(future);
 }
 
+async fn sleep(duration: Duration) {
+    let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+async fn send(request: RequestBuilder) -> Result<DataSourceResponse, DataSourceError> {
+    let response = request.send().await.map_err(request_error)?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(request_error)?;
+    check_response(status, &headers, body)
+}
+
+// The browser has no request timeout, so race against a timer instead.
+// Dropping the losing future aborts the underlying fetch.
+async fn with_timeout<T>(
+    future: impl std::future::Future<Output = Result<T, DataSourceError>>,
+    timeout: Duration,
+) -> Result<T, DataSourceError> {
+    match select(Box::pin(future), Box::pin(sleep(timeout))).await {
+        Either::Left((result, _)) => result,
+        Either::Right(..) => Err(DataSourceError::Io(format!(
+            "request timed out after {:?}",
+            timeout
+        ))),
+    }
+}
+
+async fn send_with_retry(
+    mut request: RequestBuilder,
+    policy: RequestPolicy,
+) -> Result<DataSourceResponse, DataSourceError> {
+    let mut retries = 0;
+    loop {
+        let next = request.try_clone();
+        let result = with_timeout(send(request), policy.read_timeout).await;
+        let Some(next) = next else {
+            return result;
+        };
+        let Some(delay) = policy.retry_delay(retries, &result) else {
+            return result;
+        };
+        sleep(delay).await;
+        request = next;
+        retries += 1;
+    }
+}
+
+// Successful responses come back with the body still unread
+async fn start_stream(
+    request: RequestBuilder,
+) -> Result<(DataSourceResponse, Option<Response>), DataSourceError> {
+    let response = request.send().await.map_err(request_error)?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    if !status.is_success() {
+        let body = response.bytes().await.map_err(request_error)?;
+        return check_response(status, &headers, body).map(|head| (head, None));
+    }
+    check_response(status, &headers, Bytes::new()).map(|head| (head, Some(response)))
+}
+
+async fn read_stream(body: Response, on_event: &mut OnEvent) -> Result<(), DataSourceError> {
+    let mut chunks = body.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        on_event(StreamEvent::Chunk(chunk.map_err(request_error)?));
+    }
+    Ok(())
+}
+
+async fn stream_with_retry(
+    mut request: RequestBuilder,
+    policy: RequestPolicy,
+    mut on_event: OnEvent,
+) {
+    let mut retries = 0;
+    let result = loop {
+        let next = request.try_clone();
+        let result = with_timeout(start_stream(request), policy.read_timeout).await;
+        let Some(next) = next else {
+            break result;
+        };
+        let Some(delay) = policy.retry_delay(retries, &result) else {
+            break result;
+        };
+        sleep(delay).await;
+        request = next;
+        retries += 1;
+    };
+
+    let body = match result {
+        Ok((head, body)) => {
+            on_event(StreamEvent::Start(Ok(head)));
+            body
+        }
+        Err(e) => {
+            on_event(StreamEvent::Start(Err(e)));
+            return;
+        }
+    };
+    let result = match body {
+        Some(body) => with_timeout(read_stream(body, &mut on_event), policy.read_timeout).await,
+        None => Ok(()),
+    };
+    on_event(StreamEvent::End(result));
+}
+
 pub fn fetch(
     request: RequestBuilder,
-    on_done: Box<dyn FnOnce(Result<DataSourceResponse, String>) + Send>,
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_done: Box<dyn FnOnce(Result<DataSourceResponse, DataSourceError>) + Send>,
 ) {
+    let registration = abort_on_cancel(&tokens);
     spawn_future(async move {
-        let result = request
-            .send()
-            .await
-            .expect("request failed")
-            .bytes()
-            .await
-            .expect("unable to get bytes");
-
-        let res = Ok(DataSourceResponse { body: result });
+        if let Ok(res) = Abortable::new(send_with_retry(request, policy), registration).await {
+            on_done(res)
+        }
+    });
+}
 
-        on_done(res)
+pub fn fetch_stream(
+    request: RequestBuilder,
+    policy: RequestPolicy,
+    tokens: Vec<RequestToken>,
+    on_event: OnEvent,
+) {
+    let registration = abort_on_cancel(&tokens);
+    spawn_future(async move {
+        let _ = Abortable::new(stream_with_retry(request, policy, on_event), registration).await;
     });
 }
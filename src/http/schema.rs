@@ -1,6 +1,31 @@
-use serde::Deserialize;
+use bytes::{Bytes, BytesMut};
 
-use crate::data::{EntryID, EntryIDSlug, SlugParseError, TileID, TileIDSlug};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{DataSourceError, EntryID, EntryIDSlug, SlugParseError, TileID, TileIDSlug};
+
+// Version of the CBOR encoding of the types in crate::data, sent by the
+// server with every response. Bump this on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 2;
+pub const SCHEMA_VERSION_HEADER: &str = "x-legion-prof-schema";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum TileKind {
+    Summary,
+    Slot,
+    SlotMeta,
+}
+
+impl TileKind {
+    // Also the route (and archive directory) for tiles of this kind
+    pub fn name(self) -> &'static str {
+        match self {
+            TileKind::Summary => "summary_tile",
+            TileKind::Slot => "slot_tile",
+            TileKind::SlotMeta => "slot_meta_tile",
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TileRequestPath {
@@ -23,6 +48,17 @@ pub struct TileRequestRef<'a> {
 #[derive(Debug, Clone, Deserialize)]
 pub struct TileQuery {
     pub full: bool,
+    // Identity of the profile the client expects (see HTTPClientDataSource)
+    pub v: Option<String>,
+}
+
+// Body of POST /tiles is a CBOR-encoded Vec<BatchTileRequest>
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchTileRequest {
+    pub kind: TileKind,
+    pub entry_id: EntryID,
+    pub tile_id: TileID,
+    pub full: bool,
 }
 
 impl TileRequestPath {
@@ -43,3 +79,119 @@ impl<'a> TileRequestRef<'a> {
         )
     }
 }
+
+// The response to POST /tiles is a sequence of frames, one per request, in
+// whatever order the server finishes them:
+//
+//   index (u32 LE), status (u8), length (u32 LE), payload
+//
+// On success, the payload is the CBOR tile, compressed with the encoding
+// negotiated via Accept-Encoding (exactly what the single-tile route would
+// return). Otherwise, it is the error message.
+
+const FRAME_HEADER_SIZE: usize = 9;
+
+const FRAME_OK: u8 = 0;
+const FRAME_NOT_FOUND: u8 = 1;
+const FRAME_BAD_REQUEST: u8 = 2;
+const FRAME_ERROR: u8 = 3;
+
+pub fn encode_frame(out: &mut Vec<u8>, index: u32, result: Result<&[u8], &DataSourceError>) {
+    let message;
+    let (status, payload) = match result {
+        Ok(payload) => (FRAME_OK, payload),
+        Err(e) => {
+            message = e.to_string();
+            let status = match e {
+                DataSourceError::NotFound(..) => FRAME_NOT_FOUND,
+                DataSourceError::BadRequest(..) => FRAME_BAD_REQUEST,
+                _ => FRAME_ERROR,
+            };
+            (status, message.as_bytes())
+        }
+    };
+    out.extend_from_slice(&index.to_le_bytes());
+    out.push(status);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+pub type Frame = (u32, Result<Bytes, DataSourceError>);
+
+// Decodes frames from the body of a batch response as it arrives, so that
+// each tile can be used as soon as its frame is complete
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: BytesMut,
+}
+
+impl FrameDecoder {
+    // Returns the frames completed by the chunk
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Frame> {
+        self.buffer.extend_from_slice(chunk);
+        let mut result = Vec::new();
+        while self.buffer.len() >= FRAME_HEADER_SIZE {
+            let header = &self.buffer[..FRAME_HEADER_SIZE];
+            let index = u32::from_le_bytes(header[0..4].try_into().unwrap());
+            let status = header[4];
+            let len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+            if self.buffer.len() < FRAME_HEADER_SIZE + len {
+                break;
+            }
+            let payload =
+                (self.buffer.split_to(FRAME_HEADER_SIZE + len).freeze()).slice(FRAME_HEADER_SIZE..);
+            let message = || String::from_utf8_lossy(&payload).into_owned();
+            let frame = match status {
+                FRAME_OK => Ok(payload.clone()),
+                FRAME_NOT_FOUND => Err(DataSourceError::NotFound(message())),
+                FRAME_BAD_REQUEST => Err(DataSourceError::BadRequest(message())),
+                _ => Err(DataSourceError::Remote(message())),
+            };
+            result.push((index, frame));
+        }
+        result
+    }
+
+    // Checks that the body didn't end partway through a frame
+    pub fn finish(&self) -> Result<(), DataSourceError> {
+        if !self.buffer.is_empty() {
+            return Err(DataSourceError::Decode(
+                "truncated batch response".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let mut body = Vec::new();
+        encode_frame(&mut body, 1, Ok(b"tile"));
+        encode_frame(
+            &mut body,
+            0,
+            Err(&DataSourceError::NotFound("gone".to_owned())),
+        );
+
+        // Split across chunks at every possible point
+        for split in 0..=body.len() {
+            let mut decoder = FrameDecoder::default();
+            let mut frames = decoder.push(&body[..split]);
+            frames.extend(decoder.push(&body[split..]));
+            assert!(decoder.finish().is_ok());
+            assert_eq!(frames.len(), 2);
+            assert!(matches!(&frames[0], (1, Ok(tile)) if &tile[..] == b"tile"));
+            assert!(
+                matches!(&frames[1], (0, Err(DataSourceError::NotFound(m))) if m.contains("gone"))
+            );
+        }
+
+        let mut decoder = FrameDecoder::default();
+        assert_eq!(decoder.push(&body[..body.len() - 1]).len(), 1);
+        assert!(decoder.finish().is_err());
+    }
+}
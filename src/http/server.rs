@@ -1,19 +1,282 @@
+use std::io::Write;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use actix_cors::Cors;
 use actix_web::{
-    error, get, http, middleware,
-    web::{self, Data},
-    App, HttpServer, Responder, Result,
+    error, get,
+    http::{
+        self,
+        header::{
+            self, CacheControl, CacheDirective, ETag, EntityTag, Header, IfNoneMatch, LastModified,
+        },
+        StatusCode,
+    },
+    middleware, post,
+    web::{self, Bytes, Data},
+    App, HttpRequest, HttpResponse, HttpServer, Result,
 };
 
-use serde::Serialize;
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
 
-use crate::data::DataSource;
-use crate::http::schema::{TileQuery, TileRequestPath};
+use crate::archive_data::{ArchiveFile, DataSourceArchiveFileReader, DataSourceArchiveReader};
+use crate::data::{
+    DataSource, DataSourceError, SearchRequest, SearchResponse, INFO_UPDATE_TIMEOUT,
+};
+use crate::http::schema::{
+    encode_frame, BatchTileRequest, TileKind, TileQuery, TileRequest, TileRequestPath,
+    SCHEMA_VERSION, SCHEMA_VERSION_HEADER,
+};
 
 struct AppState {
     data_source: Box<dyn DataSource + Send + Sync + 'static>,
+    // When serving a single-file archive, tiles are already encoded and can
+    // be sent as-is (if the client accepts zstd)
+    archive: Option<Arc<ArchiveFile>>,
+    // A profile never changes while it's being served, so every response
+    // is identified by the profile as a whole (and its encoding). None if
+    // the profile can't be identified, in which case responses are not
+    // cacheable.
+    profile_hash: Option<u64>,
+    last_modified: SystemTime,
+    compression_level: i32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Encoding {
+    Zstd,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Identity => "identity",
+        }
+    }
+
+    // Picks the encoding with the highest q-value in an Accept-Encoding
+    // header, preferring zstd, then gzip on ties. Identity is used when
+    // nothing else is acceptable (or the header is missing).
+    fn negotiate(accept_encoding: Option<&str>) -> Encoding {
+        let Some(header) = accept_encoding else {
+            return Encoding::Identity;
+        };
+        let quality = |coding: &str| {
+            let mut wildcard = None;
+            for item in header.split(',') {
+                let mut parts = item.split(';');
+                let name = parts.next().unwrap_or("").trim();
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(1.0, |q| q.trim().parse().unwrap_or(0.0));
+                if name.eq_ignore_ascii_case(coding) {
+                    return Some(q);
+                }
+                if name == "*" {
+                    wildcard = Some(q);
+                }
+            }
+            wildcard
+        };
+
+        let mut best = (Encoding::Identity, 0.0);
+        for encoding in [Encoding::Zstd, Encoding::Gzip] {
+            let q = quality(encoding.name()).unwrap_or(0.0);
+            if q > best.1 {
+                best = (encoding, q);
+            }
+        }
+        best.0
+    }
+
+    fn compress(self, cbor: Vec<u8>, level: i32) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Zstd => zstd::encode_all(&cbor[..], level),
+            Encoding::Gzip => {
+                let level = flate2::Compression::new(level.clamp(0, 9) as u32);
+                let mut f = flate2::write::GzEncoder::new(Vec::new(), level);
+                f.write_all(&cbor)?;
+                f.finish()
+            }
+            Encoding::Identity => Ok(cbor),
+        }
+    }
+}
+
+// FNV-1a, because the hash must be stable across builds and platforms
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn to_cbor<T>(data: T) -> Vec<u8>
+where
+    T: Serialize,
+{
+    let mut cbor = Vec::new();
+    ciborium::into_writer(&data, &mut cbor).expect("ciborium encoding failed");
+    cbor
+}
+
+impl AppState {
+    fn new(
+        data_source: Box<dyn DataSource + Send + Sync + 'static>,
+        archive: Option<Arc<ArchiveFile>>,
+        last_modified: SystemTime,
+    ) -> Self {
+        // Live profiles change while being served, so they can't be
+        // identified (or cached) at all
+        let live = data_source.fetch_info_update(0, Duration::ZERO).is_ok();
+        let profile_hash = (data_source.fetch_info().ok())
+            .filter(|_| !live)
+            .map(|info| stable_hash(&to_cbor(info)));
+        Self {
+            data_source,
+            archive,
+            profile_hash,
+            last_modified,
+            compression_level: 1,
+        }
+    }
+
+    fn encode<T>(&self, data: T, encoding: Encoding) -> Result<Vec<u8>, DataSourceError>
+    where
+        T: Serialize,
+    {
+        (encoding.compress(to_cbor(data), self.compression_level))
+            .map_err(|e| DataSourceError::Io(e.to_string()))
+    }
+
+    fn etag(&self, encoding: Encoding) -> Option<EntityTag> {
+        let hash = self.profile_hash?;
+        Some(EntityTag::new_strong(format!(
+            "{:016x}-{}",
+            hash,
+            encoding.name()
+        )))
+    }
+
+    // Whether a version from a tile URL identifies the profile being served.
+    // Clients use the ETag of /info, in whichever encoding they got it.
+    fn is_current(&self, version: Option<&str>) -> bool {
+        let Some(version) = version else {
+            return false;
+        };
+        [Encoding::Zstd, Encoding::Gzip, Encoding::Identity]
+            .into_iter()
+            .filter_map(|encoding| self.etag(encoding))
+            .any(|etag| etag.tag() == version)
+    }
+
+    fn not_modified(&self, req: &HttpRequest, etag: &EntityTag) -> bool {
+        match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+            Err(_) => false,
+        }
+    }
+
+    // Responds with the body in the negotiated encoding, or 304 if the
+    // client already has it (in which case the body is never computed)
+    fn respond(
+        &self,
+        req: &HttpRequest,
+        cache_control: Vec<CacheDirective>,
+        body: impl FnOnce(Encoding) -> Result<Vec<u8>, DataSourceError>,
+    ) -> Result<HttpResponse> {
+        let encoding = negotiate(req);
+        let etag = self.etag(encoding);
+        let not_modified = etag
+            .as_ref()
+            .map_or(false, |etag| self.not_modified(req, etag));
+
+        let mut response = if not_modified {
+            HttpResponse::NotModified()
+        } else {
+            HttpResponse::Ok()
+        };
+        response
+            .insert_header((SCHEMA_VERSION_HEADER, SCHEMA_VERSION))
+            .insert_header((header::VARY, "Accept-Encoding"));
+        if let Some(etag) = etag {
+            response
+                .insert_header(ETag(etag))
+                .insert_header(LastModified(self.last_modified.into()))
+                .insert_header(CacheControl(cache_control));
+        }
+        if not_modified {
+            return Ok(response.finish());
+        }
+
+        let body = body(encoding)?;
+        if encoding != Encoding::Identity {
+            response.insert_header((header::CONTENT_ENCODING, encoding.name()));
+        }
+        Ok(response.content_type("application/cbor").body(body))
+    }
+
+    fn fetch_encoded(
+        &self,
+        kind: TileKind,
+        req: &TileRequest,
+        full: bool,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, DataSourceError> {
+        if let Some(archive) = &self.archive {
+            let blob = archive.tile_blob(kind, &req.entry_id, req.tile_id)?;
+            if encoding == Encoding::Zstd {
+                return Ok(blob.to_vec());
+            }
+            let cbor = zstd::decode_all(blob).map_err(|e| DataSourceError::Io(e.to_string()))?;
+            return (encoding.compress(cbor, self.compression_level))
+                .map_err(|e| DataSourceError::Io(e.to_string()));
+        }
+        let (entry_id, tile_id) = (&req.entry_id, req.tile_id);
+        match kind {
+            TileKind::Summary => self.encode(
+                self.data_source
+                    .fetch_summary_tile(entry_id, tile_id, full)?,
+                encoding,
+            ),
+            TileKind::Slot => self.encode(
+                self.data_source.fetch_slot_tile(entry_id, tile_id, full)?,
+                encoding,
+            ),
+            TileKind::SlotMeta => self.encode(
+                self.data_source
+                    .fetch_slot_meta_tile(entry_id, tile_id, full)?,
+                encoding,
+            ),
+        }
+    }
+}
+
+fn negotiate(req: &HttpRequest) -> Encoding {
+    let accept_encoding = req.headers().get(header::ACCEPT_ENCODING);
+    Encoding::negotiate(accept_encoding.and_then(|h| h.to_str().ok()))
+}
+
+// For responses that depend on more than the request's URL, and so get no
+// ETag or caching headers
+fn respond_uncached(encoding: Encoding, body: Vec<u8>) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((SCHEMA_VERSION_HEADER, SCHEMA_VERSION))
+        .insert_header((header::VARY, "Accept-Encoding"));
+    if encoding != Encoding::Identity {
+        response.insert_header((header::CONTENT_ENCODING, encoding.name()));
+    }
+    response.content_type("application/cbor").body(body)
 }
 
 pub struct DataSourceHTTPServer {
@@ -22,65 +285,189 @@ pub struct DataSourceHTTPServer {
     state: AppState,
 }
 
-fn encode<T>(data: T) -> Result<Vec<u8>>
-where
-    T: Serialize,
-{
-    let mut f = zstd::Encoder::new(Vec::new(), 1)?;
-    ciborium::into_writer(&data, &mut f).expect("ciborium encoding failed");
-    let f = f.finish()?;
-    Ok(f)
+impl error::ResponseError for DataSourceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataSourceError::NotFound(..) => StatusCode::NOT_FOUND,
+            DataSourceError::BadRequest(..) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
+// Always revalidated, since the client learns the profile's identity from
+// it. Tiles can be cached indefinitely when clients include that identity
+// in tile URLs (see HTTPClientDataSource).
 #[get("/info")]
-async fn fetch_info(state: web::Data<AppState>) -> Result<impl Responder> {
-    let result = state.data_source.fetch_info();
-    encode(result)
+async fn fetch_info(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    state.respond(&req, vec![CacheDirective::NoCache], |encoding| {
+        state.encode(state.data_source.fetch_info()?, encoding)
+    })
 }
 
-#[get("/summary_tile/{entry_id}/{tile_id}")]
-async fn fetch_summary_tile(
+#[derive(Deserialize)]
+struct InfoUpdateQuery {
+    since: u64,
+}
+
+// Long poll for live profiles (see DataSource::fetch_info_update). Waits on
+// the blocking thread pool, and answers 204 if nothing changed in time.
+#[get("/info/updates")]
+async fn fetch_info_update(
+    req: HttpRequest,
+    query: web::Query<InfoUpdateQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encoding = negotiate(&req);
+    let since = query.since;
+    let body = web::block(move || {
+        match state
+            .data_source
+            .fetch_info_update(since, INFO_UPDATE_TIMEOUT)?
+        {
+            Some(update) => state.encode(update, encoding).map(Some),
+            None => Ok(None),
+        }
+    })
+    .await??;
+    match body {
+        Some(body) => Ok(respond_uncached(encoding, body)),
+        None => Ok(HttpResponse::NoContent()
+            .insert_header((SCHEMA_VERSION_HEADER, SCHEMA_VERSION))
+            .finish()),
+    }
+}
+
+fn fetch_tile(
+    kind: TileKind,
+    req: HttpRequest,
     path: web::Path<TileRequestPath>,
     query: web::Query<TileQuery>,
     state: web::Data<AppState>,
-) -> Result<impl Responder> {
+) -> Result<HttpResponse> {
     let path = path
         .parse()
         .map_err(|e| error::ErrorBadRequest(format!("bad request: {}", e)))?;
-    let result = state
-        .data_source
-        .fetch_summary_tile(&path.entry_id, path.tile_id, query.full);
-    encode(result)
+    // A URL for another profile (or none in particular) could be answered
+    // differently once the profile changes, so it must be revalidated
+    let cache_control = if state.is_current(query.v.as_deref()) {
+        vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(31536000),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ]
+    } else {
+        vec![CacheDirective::NoCache]
+    };
+    state.respond(&req, cache_control, |encoding| {
+        state.fetch_encoded(kind, &path, query.full, encoding)
+    })
+}
+
+#[get("/summary_tile/{entry_id}/{tile_id}")]
+async fn fetch_summary_tile(
+    req: HttpRequest,
+    path: web::Path<TileRequestPath>,
+    query: web::Query<TileQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    fetch_tile(TileKind::Summary, req, path, query, state)
 }
 
 #[get("/slot_tile/{entry_id}/{tile_id}")]
 async fn fetch_slot_tile(
+    req: HttpRequest,
     path: web::Path<TileRequestPath>,
     query: web::Query<TileQuery>,
     state: web::Data<AppState>,
-) -> Result<impl Responder> {
-    let path = path
-        .parse()
-        .map_err(|e| error::ErrorBadRequest(format!("bad request: {}", e)))?;
-    let result = state
-        .data_source
-        .fetch_slot_tile(&path.entry_id, path.tile_id, query.full);
-    encode(result)
+) -> Result<HttpResponse> {
+    fetch_tile(TileKind::Slot, req, path, query, state)
 }
 
 #[get("/slot_meta_tile/{entry_id}/{tile_id}")]
 async fn fetch_slot_meta_tile(
+    req: HttpRequest,
     path: web::Path<TileRequestPath>,
     query: web::Query<TileQuery>,
     state: web::Data<AppState>,
-) -> Result<impl Responder> {
-    let path = path
-        .parse()
+) -> Result<HttpResponse> {
+    fetch_tile(TileKind::SlotMeta, req, path, query, state)
+}
+
+// Tiles of a batch fetched at once. Each occupies a blocking thread, so this
+// bounds how much of the pool a single batch can take.
+const BATCH_CONCURRENCY: usize = 8;
+
+// Batch endpoint: tiles are fetched (on the blocking thread pool) as the
+// response body is consumed, a few at a time, so the batch is never held in
+// memory at once. Each frame is sent as soon as its tile is ready, so a slow
+// tile doesn't hold up the rest.
+#[post("/tiles")]
+async fn fetch_tiles(
+    req: HttpRequest,
+    body: Bytes,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let requests: Vec<BatchTileRequest> = ciborium::from_reader(&body[..])
         .map_err(|e| error::ErrorBadRequest(format!("bad request: {}", e)))?;
-    let result = state
-        .data_source
-        .fetch_slot_meta_tile(&path.entry_id, path.tile_id, query.full);
-    encode(result)
+    let encoding = negotiate(&req);
+    let etag = state.etag(encoding);
+    let state = state.into_inner();
+    let frames = stream::iter(requests.into_iter().enumerate()).map(move |(index, req)| {
+        let state = state.clone();
+        async move {
+            let frame = web::block(move || {
+                let tile_req = TileRequest {
+                    entry_id: req.entry_id,
+                    tile_id: req.tile_id,
+                };
+                let result = state.fetch_encoded(req.kind, &tile_req, req.full, encoding);
+                let mut frame = Vec::new();
+                encode_frame(&mut frame, index as u32, result.as_deref());
+                frame
+            })
+            .await?;
+            Ok::<_, actix_web::Error>(Bytes::from(frame))
+        }
+    });
+    let frames = frames.buffer_unordered(BATCH_CONCURRENCY);
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((SCHEMA_VERSION_HEADER, SCHEMA_VERSION))
+        .insert_header((header::VARY, "Accept-Encoding"));
+    // Lets the client cache the tiles (but not the batch itself)
+    if let Some(etag) = etag {
+        response.insert_header(ETag(etag));
+    }
+    Ok(response
+        .content_type("application/octet-stream")
+        .streaming(frames))
+}
+
+// Search runs on the blocking thread pool, since it may read every slot
+// meta tile in the profile. Results depend on the request body, so they
+// aren't cacheable.
+#[post("/search")]
+async fn search(req: HttpRequest, body: Bytes, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let request: SearchRequest = ciborium::from_reader(&body[..])
+        .map_err(|e| error::ErrorBadRequest(format!("bad request: {}", e)))?;
+    let encoding = negotiate(&req);
+    let body = web::block(move || {
+        let results = state.data_source.search(&request)?;
+        state.encode(SearchResponse { request, results }, encoding)
+    })
+    .await??;
+    Ok(respond_uncached(encoding, body))
+}
+
+fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(fetch_info)
+        .service(fetch_info_update)
+        .service(fetch_summary_tile)
+        .service(fetch_slot_tile)
+        .service(fetch_slot_meta_tile)
+        .service(fetch_tiles)
+        .service(search);
 }
 
 impl DataSourceHTTPServer {
@@ -92,10 +479,48 @@ impl DataSourceHTTPServer {
         Self {
             host,
             port,
-            state: AppState { data_source },
+            state: AppState::new(data_source, None, SystemTime::now()),
         }
     }
 
+    // Serve an archive from disk under the usual routes: either a directory
+    // written by DataSourceArchiveWriter, or a single file (see ArchiveFile)
+    pub fn new_archive(
+        host: String,
+        port: u16,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, DataSourceError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let last_modified = std::fs::metadata(path.join("info"))
+                .and_then(|m| m.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            let data_source = DataSourceArchiveReader::new(path)?;
+            return Ok(Self {
+                host,
+                port,
+                state: AppState::new(Box::new(data_source), None, last_modified),
+            });
+        }
+
+        let last_modified = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        let archive = Arc::new(ArchiveFile::open(path)?);
+        let data_source = DataSourceArchiveFileReader::from_archive(archive.clone());
+        Ok(Self {
+            host,
+            port,
+            state: AppState::new(Box::new(data_source), Some(archive), last_modified),
+        })
+    }
+
+    // zstd level; gzip levels are clamped to 0-9
+    pub fn with_compression_level(mut self, level: i32) -> Self {
+        self.state.compression_level = level;
+        self
+    }
+
     #[actix_web::main]
     pub async fn run(self) -> std::io::Result<()> {
         let state = Data::from(Arc::new(self.state));
@@ -106,18 +531,347 @@ impl DataSourceHTTPServer {
                 .allowed_methods(vec!["GET", "POST"])
                 .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
                 .allowed_header(http::header::CONTENT_TYPE)
+                .allowed_header(http::header::IF_NONE_MATCH)
+                .expose_headers(vec![
+                    http::header::ETAG,
+                    header::HeaderName::from_static(SCHEMA_VERSION_HEADER),
+                ])
                 .max_age(3600);
             App::new()
                 .wrap(middleware::Logger::default())
                 .wrap(cors)
                 .app_data(state.clone())
-                .service(fetch_info)
-                .service(fetch_summary_tile)
-                .service(fetch_slot_tile)
-                .service(fetch_slot_meta_tile)
+                .configure(configure)
         })
         .bind((self.host.as_str(), self.port))?
         .run()
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::test::{call_and_read_body, call_service, init_service, TestRequest};
+
+    use crate::data::{
+        Color32, DataSourceInfo, EntryID, EntryInfo, FieldSchema, Item, ItemUID, SlotMetaTile,
+        SlotMetaTileData, SlotTile, SlotTileData, SummaryTile, SummaryTileData, TileID, TileSet,
+    };
+    use crate::http::schema::{FrameDecoder, TileRequestRef};
+    use crate::timestamp::{Interval, Timestamp};
+
+    // One slot, whose tiles exist only in the first half of the profile.
+    // Counts the slot tiles it is asked for.
+    #[derive(Default)]
+    struct TestDataSource {
+        slot_fetches: Arc<AtomicUsize>,
+    }
+
+    fn tile_id(i: i64) -> TileID {
+        TileID(Interval::new(Timestamp(i * 100), Timestamp((i + 1) * 100)))
+    }
+
+    fn slot_id() -> EntryID {
+        EntryID::root().child(0)
+    }
+
+    impl DataSource for TestDataSource {
+        fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+            Ok(DataSourceInfo {
+                entry_info: EntryInfo::Panel {
+                    short_name: "root".to_owned(),
+                    long_name: "root".to_owned(),
+                    summary: None,
+                    slots: vec![EntryInfo::Slot {
+                        short_name: "s0".to_owned(),
+                        long_name: "Slot 0".to_owned(),
+                        max_rows: 1,
+                    }],
+                },
+                interval: Interval::new(Timestamp(0), Timestamp(1000)),
+                tile_set: TileSet::default(),
+                field_schema: FieldSchema::new(),
+            })
+        }
+
+        fn fetch_summary_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SummaryTile, DataSourceError> {
+            Ok(SummaryTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SummaryTileData {
+                    utilization: Vec::new(),
+                },
+            })
+        }
+
+        fn fetch_slot_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotTile, DataSourceError> {
+            self.slot_fetches.fetch_add(1, Ordering::Relaxed);
+            if tile_id.0.start >= Timestamp(500) {
+                return Err(DataSourceError::NotFound("no such tile".to_owned()));
+            }
+            let item = Item {
+                item_uid: ItemUID(tile_id.0.start.0 as u64),
+                interval: tile_id.0,
+                color: Color32::RED,
+            };
+            Ok(SlotTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotTileData {
+                    items: vec![vec![item]],
+                },
+            })
+        }
+
+        fn fetch_slot_meta_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotMetaTile, DataSourceError> {
+            Ok(SlotMetaTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SlotMetaTileData { items: Vec::new() },
+            })
+        }
+    }
+
+    fn test_state() -> Data<AppState> {
+        test_state_with(TestDataSource::default())
+    }
+
+    fn test_state_with(data_source: TestDataSource) -> Data<AppState> {
+        Data::new(AppState::new(
+            Box::new(data_source),
+            None,
+            SystemTime::now(),
+        ))
+    }
+
+    fn batch_body(tiles: &[i64]) -> Vec<u8> {
+        let requests: Vec<_> = (tiles.iter())
+            .map(|i| BatchTileRequest {
+                kind: TileKind::Slot,
+                entry_id: slot_id(),
+                tile_id: tile_id(*i),
+                full: false,
+            })
+            .collect();
+        to_cbor(requests)
+    }
+
+    #[actix_web::test]
+    async fn test_batch_frames() {
+        let app = init_service(App::new().app_data(test_state()).configure(configure)).await;
+        let request = TestRequest::post()
+            .uri("/tiles")
+            .set_payload(batch_body(&[0, 7, 1]))
+            .to_request();
+        let body = call_and_read_body(&app, request).await;
+
+        let mut decoder = FrameDecoder::default();
+        let mut frames = decoder.push(&body);
+        assert!(decoder.finish().is_ok());
+        frames.sort_by_key(|(index, _)| *index);
+        assert_eq!(frames.len(), 3);
+        for (index, result) in frames {
+            match index {
+                1 => assert!(matches!(result, Err(DataSourceError::NotFound(..)))),
+                _ => {
+                    let tile: SlotTile = ciborium::from_reader(&result.unwrap()[..]).unwrap();
+                    assert_eq!(tile.tile_id, tile_id([0, 7, 1][index as usize]));
+                }
+            }
+        }
+    }
+
+    fn tile_uri(i: i64, query: &str) -> String {
+        let slot_id = slot_id();
+        let req = TileRequestRef {
+            entry_id: &slot_id,
+            tile_id: tile_id(i),
+        };
+        format!("/slot_tile/{}?full=false{}", req.to_slug(), query)
+    }
+
+    fn header<'a>(response: &'a actix_web::dev::ServiceResponse, name: &str) -> &'a str {
+        let value = response.headers().get(name);
+        value.map_or("", |value| value.to_str().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_tile_cache_control() {
+        let app = init_service(App::new().app_data(test_state()).configure(configure)).await;
+        let info = call_service(&app, TestRequest::get().uri("/info").to_request()).await;
+        let version = header(&info, "etag").trim_matches('"').to_owned();
+
+        // Only URLs for the profile being served are immutable
+        for (query, immutable) in [
+            (format!("&v={}", version), true),
+            ("&v=0123456789abcdef-identity".to_owned(), false),
+            (String::new(), false),
+        ] {
+            let request = TestRequest::get().uri(&tile_uri(0, &query)).to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let cache_control = header(&response, "cache-control");
+            assert_eq!(cache_control.contains("immutable"), immutable, "{}", query);
+            assert_eq!(cache_control.contains("no-cache"), !immutable, "{}", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_not_modified() {
+        let app = init_service(App::new().app_data(test_state()).configure(configure)).await;
+        let get = |encoding: &str, etag: Option<&str>| {
+            let mut request = TestRequest::get()
+                .uri(&tile_uri(0, ""))
+                .insert_header((header::ACCEPT_ENCODING, encoding));
+            if let Some(etag) = etag {
+                request = request.insert_header((header::IF_NONE_MATCH, etag));
+            }
+            request.to_request()
+        };
+
+        // The ETag covers the encoding, since the body depends on it
+        let zstd = call_service(&app, get("zstd", None)).await;
+        let gzip = call_service(&app, get("gzip", None)).await;
+        let zstd_etag = header(&zstd, "etag").to_owned();
+        let gzip_etag = header(&gzip, "etag").to_owned();
+        assert!(!zstd_etag.is_empty());
+        assert_ne!(zstd_etag, gzip_etag);
+
+        let response = call_service(&app, get("zstd", Some(&zstd_etag))).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&response, "etag"), zstd_etag);
+        assert!(actix_web::test::read_body(response).await.is_empty());
+
+        let response = call_service(&app, get("zstd", Some(&gzip_etag))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!actix_web::test::read_body(response).await.is_empty());
+    }
+
+    // Serves the routes on an unused local port, from a thread of its own
+    #[cfg(feature = "client")]
+    fn spawn_server(state: Data<AppState>, configure: fn(&mut web::ServiceConfig)) -> url::Url {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let server = HttpServer::new(move || {
+                    App::new().app_data(state.clone()).configure(configure)
+                })
+                .workers(1)
+                .bind(("127.0.0.1", 0))
+                .unwrap();
+                tx.send(server.addrs()[0]).unwrap();
+                server.run().await.unwrap();
+            })
+        });
+        let addr = rx.recv().unwrap();
+        url::Url::parse(&format!("http://{}/", addr)).unwrap()
+    }
+
+    // Requests tiles 0, 7 and 1 together (so they can be batched), and
+    // checks that each gets the right result
+    #[cfg(feature = "client")]
+    fn check_client(baseurl: url::Url) {
+        use crate::deferred_data::DeferredDataSource;
+        use crate::http::client::HTTPClientDataSource;
+
+        let mut client = HTTPClientDataSource::new(baseurl);
+        for i in [0, 7, 1] {
+            client.fetch_slot_tile(&slot_id(), tile_id(i), false);
+        }
+        let start = std::time::Instant::now();
+        let mut responses = Vec::new();
+        while responses.len() < 3 {
+            assert!(start.elapsed() < Duration::from_secs(30), "timed out");
+            responses.extend(client.get_slot_tiles());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        responses.sort_by_key(|response| response.tile_id);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0].result.as_ref().unwrap().tile_id, tile_id(0));
+        assert_eq!(responses[1].result.as_ref().unwrap().tile_id, tile_id(1));
+        assert_eq!(responses[2].tile_id, tile_id(7));
+        assert!(matches!(
+            responses[2].result,
+            Err(DataSourceError::NotFound(..))
+        ));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_batch_client() {
+        check_client(spawn_server(test_state(), configure));
+    }
+
+    // A server without POST /tiles answers the batch with 404, after which
+    // the client requests the tiles one by one
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_batch_client_fallback() {
+        check_client(spawn_server(test_state(), |cfg| {
+            cfg.service(fetch_info).service(fetch_slot_tile);
+        }));
+    }
+
+    // A tile requested again is revalidated, and the cached copy is used
+    // when the server answers 304 (without fetching the tile again)
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_client_not_modified() {
+        use crate::deferred_data::DeferredDataSource;
+        use crate::http::client::HTTPClientDataSource;
+
+        let data_source = TestDataSource::default();
+        let slot_fetches = data_source.slot_fetches.clone();
+        let baseurl = spawn_server(test_state_with(data_source), configure);
+        let mut client = HTTPClientDataSource::new(baseurl);
+
+        let mut tiles = Vec::new();
+        for _ in 0..2 {
+            client.fetch_slot_tile(&slot_id(), tile_id(0), false);
+            let start = std::time::Instant::now();
+            loop {
+                assert!(start.elapsed() < Duration::from_secs(30), "timed out");
+                if let Some(response) = client.get_slot_tiles().pop() {
+                    tiles.push(response.result.unwrap());
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        assert_eq!(tiles[0].tile_id, tile_id(0));
+        assert_eq!(tiles[1].tile_id, tile_id(0));
+        assert_eq!(tiles[1].data.items[0].len(), tiles[0].data.items[0].len());
+        assert_eq!(slot_fetches.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let negotiate = Encoding::negotiate;
+        assert_eq!(negotiate(None), Encoding::Identity);
+        assert_eq!(negotiate(Some("gzip, deflate, br")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("gzip, zstd")), Encoding::Zstd);
+        assert_eq!(negotiate(Some("zstd;q=0.5, gzip")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("*")), Encoding::Zstd);
+        assert_eq!(negotiate(Some("*, zstd;q=0")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("identity")), Encoding::Identity);
+    }
+}
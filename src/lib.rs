@@ -3,9 +3,18 @@
 pub mod app;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod archive_data;
+pub mod cached_data;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod chrome_trace;
+pub mod critical_path;
 pub mod data;
 pub mod deferred_data;
 pub mod http;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod live_data;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod parallel_data;
+pub mod query;
+pub mod search;
+pub mod statistics;
 pub mod timestamp;
@@ -0,0 +1,192 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::data::{DataSourceError, DataSourceInfo, InfoUpdate};
+
+// The latest info of a live profile, for implementing
+// DataSource::fetch_info_update: the profiler publishes a new info whenever
+// the profile grows, and readers wait for it.
+pub struct InfoPublisher {
+    latest: Mutex<InfoUpdate>,
+    changed: Condvar,
+}
+
+impl InfoPublisher {
+    pub fn new(info: DataSourceInfo) -> Self {
+        Self {
+            latest: Mutex::new(InfoUpdate { revision: 1, info }),
+            changed: Condvar::new(),
+        }
+    }
+
+    // Returns the new revision
+    pub fn publish(&self, info: DataSourceInfo) -> u64 {
+        let mut latest = self.latest.lock().unwrap();
+        latest.revision += 1;
+        latest.info = info;
+        self.changed.notify_all();
+        latest.revision
+    }
+
+    pub fn info(&self) -> DataSourceInfo {
+        self.latest.lock().unwrap().info.clone()
+    }
+
+    pub fn wait(
+        &self,
+        since: u64,
+        timeout: Duration,
+    ) -> Result<Option<InfoUpdate>, DataSourceError> {
+        let deadline = Instant::now() + timeout;
+        let mut latest = self.latest.lock().unwrap();
+        // Loop because of spurious wakeups
+        while latest.revision <= since {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            latest = self.changed.wait_timeout(latest, deadline - now).unwrap().0;
+        }
+        Ok(Some(latest.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::cached_data::CachingDeferredDataSource;
+    use crate::data::{
+        DataSource, EntryID, EntryInfo, FieldSchema, SlotMetaTile, SlotTile, SummaryTile,
+        SummaryTileData, TileID, TileSet, UtilPoint,
+    };
+    use crate::deferred_data::{DeferredDataSource, DeferredDataSourceWrapper};
+    use crate::timestamp::{Interval, Timestamp};
+
+    fn info(stop: i64) -> DataSourceInfo {
+        DataSourceInfo {
+            entry_info: EntryInfo::Panel {
+                short_name: "root".to_owned(),
+                long_name: "root".to_owned(),
+                summary: None,
+                slots: Vec::new(),
+            },
+            interval: Interval::new(Timestamp(0), Timestamp(stop)),
+            tile_set: TileSet::default(),
+            field_schema: FieldSchema::new(),
+        }
+    }
+
+    #[test]
+    fn test_info_publisher() {
+        let publisher = Arc::new(InfoPublisher::new(info(10)));
+
+        // Anything older than the current revision is answered right away
+        let update = publisher.wait(0, Duration::ZERO).unwrap().unwrap();
+        assert_eq!(update.revision, 1);
+        assert!(publisher.wait(1, Duration::ZERO).unwrap().is_none());
+
+        let waiter = {
+            let publisher = publisher.clone();
+            thread::spawn(move || publisher.wait(1, Duration::from_secs(60)))
+        };
+        assert_eq!(publisher.publish(info(20)), 2);
+        let update = waiter.join().unwrap().unwrap().unwrap();
+        assert_eq!(update.revision, 2);
+        assert_eq!(update.info.interval.stop, Timestamp(20));
+    }
+
+    // A profile whose summary is the current end of the profile everywhere
+    struct LiveSource {
+        publisher: Arc<InfoPublisher>,
+    }
+
+    impl DataSource for LiveSource {
+        fn fetch_info(&self) -> Result<DataSourceInfo, DataSourceError> {
+            Ok(self.publisher.info())
+        }
+        fn fetch_summary_tile(
+            &self,
+            entry_id: &EntryID,
+            tile_id: TileID,
+            _full: bool,
+        ) -> Result<SummaryTile, DataSourceError> {
+            let stop = self.publisher.info().interval.stop;
+            Ok(SummaryTile {
+                entry_id: entry_id.clone(),
+                tile_id,
+                data: SummaryTileData {
+                    utilization: vec![UtilPoint {
+                        time: tile_id.0.start,
+                        util: stop.0 as f32,
+                    }],
+                },
+            })
+        }
+        fn fetch_slot_tile(
+            &self,
+            _entry_id: &EntryID,
+            _tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotTile, DataSourceError> {
+            Err(DataSourceError::NotFound("no slots".to_owned()))
+        }
+        fn fetch_slot_meta_tile(
+            &self,
+            _entry_id: &EntryID,
+            _tile_id: TileID,
+            _full: bool,
+        ) -> Result<SlotMetaTile, DataSourceError> {
+            Err(DataSourceError::NotFound("no slots".to_owned()))
+        }
+        fn fetch_info_update(
+            &self,
+            since: u64,
+            timeout: Duration,
+        ) -> Result<Option<InfoUpdate>, DataSourceError> {
+            self.publisher.wait(since, timeout)
+        }
+    }
+
+    #[test]
+    fn test_live_deferred_data_source() {
+        let publisher = Arc::new(InfoPublisher::new(info(10)));
+        let source = LiveSource {
+            publisher: publisher.clone(),
+        };
+        let mut source =
+            CachingDeferredDataSource::new(DeferredDataSourceWrapper::new(source), 1 << 20);
+        assert!(source.supports_info_updates());
+
+        source.fetch_info_update(1);
+        assert!(source.get_info_updates().pop().unwrap().unwrap().is_none());
+
+        let entry_id = EntryID::root().summary();
+        let tile = |start, stop| TileID(Interval::new(Timestamp(start), Timestamp(stop)));
+        // The second tile extends past the end of the profile
+        let tile_ids = [tile(0, 5), tile(5, 15)];
+        let fetch = |source: &mut CachingDeferredDataSource<_>| {
+            for tile_id in tile_ids {
+                source.fetch_summary_tile(&entry_id, tile_id, false);
+            }
+            let mut tiles = source.get_summary_tiles();
+            tiles.sort_by_key(|tile| tile.tile_id);
+            (tiles.into_iter())
+                .map(|tile| tile.result.unwrap().data.utilization[0].util)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fetch(&mut source), [10.0, 10.0]);
+
+        publisher.publish(info(20));
+        source.fetch_info_update(1);
+        let update = source.get_info_updates().pop().unwrap().unwrap().unwrap();
+        assert_eq!(update.revision, 2);
+
+        // Only the tile overlapping the new part of the profile is fetched again
+        source.invalidate(Interval::new(Timestamp(10), update.info.interval.stop));
+        assert_eq!(fetch(&mut source), [10.0, 20.0]);
+    }
+}
@@ -6,13 +6,21 @@ use rand::Rng;
 use std::collections::BTreeMap;
 
 use legion_prof_viewer::data::{
-    DataSourceInfo, DataSourceMut, EntryID, EntryInfo, Field, FieldID, FieldSchema, Item, ItemMeta,
-    ItemUID, SlotMetaTile, SlotMetaTileData, SlotTile, SlotTileData, SummaryTile, SummaryTileData,
-    TileID, TileSet, UtilPoint,
+    DataSourceError, DataSourceInfo, DataSourceMut, EntryID, EntryInfo, Field, FieldID,
+    FieldSchema, Item, ItemMeta, ItemUID, SlotMetaTile, SlotMetaTileData, SlotTile, SlotTileData,
+    SummaryTile, SummaryTileData, TileID, TileSet, UtilPoint,
 };
 
 #[cfg(not(target_arch = "wasm32"))]
-use legion_prof_viewer::deferred_data::DeferredDataSourceWrapper;
+use legion_prof_viewer::archive_data::{
+    is_archive_file, DataSourceArchiveFileReader, DataSourceArchiveReader,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use legion_prof_viewer::chrome_trace::ChromeTraceDataSource;
+#[cfg(not(target_arch = "wasm32"))]
+use legion_prof_viewer::deferred_data::{DeferredDataSource, DeferredDataSourceWrapper};
+#[cfg(not(target_arch = "wasm32"))]
+use legion_prof_viewer::parallel_data::ParallelDeferredDataSource;
 use legion_prof_viewer::timestamp::{Interval, Timestamp};
 
 #[cfg(target_arch = "wasm32")]
@@ -23,11 +31,48 @@ use url::Url;
 #[cfg(target_arch = "wasm32")]
 const DEFAULT_URL: &str = "http://127.0.0.1:8080";
 
+// Archives (directories or single files written by DataSourceArchiveWriter)
+// or Chrome Trace JSON files
+#[cfg(not(target_arch = "wasm32"))]
+fn open_data_source(
+    path: impl AsRef<std::path::Path>,
+) -> Result<Box<dyn DeferredDataSource>, DataSourceError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let data_source = DataSourceArchiveReader::new(path)?;
+        return Ok(Box::new(ParallelDeferredDataSource::new(data_source)));
+    }
+    if is_archive_file(path) {
+        let data_source = DataSourceArchiveFileReader::new(path)?;
+        return Ok(Box::new(ParallelDeferredDataSource::new(data_source)));
+    }
+    let data_source = ChromeTraceDataSource::new(path)?;
+    Ok(Box::new(ParallelDeferredDataSource::new(data_source)))
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    legion_prof_viewer::app::start(vec![Box::new(DeferredDataSourceWrapper::new(
-        RandomDataSource::new(),
-    ))]);
+    // Any arguments are treated as profiles to open (see open_data_source);
+    // otherwise, fall back to random data
+    let paths: Vec<_> = std::env::args_os().skip(1).collect();
+    if paths.is_empty() {
+        legion_prof_viewer::app::start(vec![Box::new(DeferredDataSourceWrapper::new(
+            RandomDataSource::new(),
+        ))]);
+        return;
+    }
+
+    let mut data_sources: Vec<Box<dyn DeferredDataSource>> = Vec::new();
+    for path in paths {
+        match open_data_source(&path) {
+            Ok(data_source) => data_sources.push(data_source),
+            Err(e) => {
+                eprintln!("error: unable to load {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    legion_prof_viewer::app::start(data_sources);
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -152,14 +197,16 @@ impl RandomDataSource {
         self.summary_cache.get(entry_id).unwrap()
     }
 
-    fn generate_slot(&mut self, entry_id: &EntryID) -> &SlotCacheTile {
+    fn generate_slot(&mut self, entry_id: &EntryID) -> Result<&SlotCacheTile, DataSourceError> {
         if !self.slot_cache.contains_key(entry_id) {
             let entry = self.info.entry_info.get(entry_id);
 
-            let max_rows = if let EntryInfo::Slot { max_rows, .. } = entry.unwrap() {
+            let max_rows = if let Some(EntryInfo::Slot { max_rows, .. }) = entry {
                 max_rows
             } else {
-                panic!("trying to fetch tile on something that is not a slot")
+                return Err(DataSourceError::BadRequest(
+                    "trying to fetch tile on something that is not a slot".to_owned(),
+                ));
             };
 
             let mut items = Vec::new();
@@ -209,11 +256,11 @@ impl RandomDataSource {
             self.slot_cache
                 .insert(entry_id.clone(), (items, item_metas));
         }
-        self.slot_cache.get(entry_id).unwrap()
+        Ok(self.slot_cache.get(entry_id).unwrap())
     }
 
     fn entry_info(rng: &mut rand::rngs::ThreadRng) -> EntryInfo {
-        let kinds = vec![
+        let kinds = [
             "CPU".to_string(),
             "GPU".to_string(),
             "OMP".to_string(),
@@ -268,8 +315,8 @@ impl RandomDataSource {
 }
 
 impl DataSourceMut for RandomDataSource {
-    fn fetch_info(&mut self) -> DataSourceInfo {
-        self.info.clone()
+    fn fetch_info(&mut self) -> Result<DataSourceInfo, DataSourceError> {
+        Ok(self.info.clone())
     }
 
     fn fetch_summary_tile(
@@ -277,7 +324,7 @@ impl DataSourceMut for RandomDataSource {
         entry_id: &EntryID,
         tile_id: TileID,
         _full: bool,
-    ) -> SummaryTile {
+    ) -> Result<SummaryTile, DataSourceError> {
         let utilization = self.generate_summary(entry_id);
 
         let mut tile_utilization = Vec::new();
@@ -314,17 +361,22 @@ impl DataSourceMut for RandomDataSource {
 
             last_point = Some(*point);
         }
-        SummaryTile {
+        Ok(SummaryTile {
             entry_id: entry_id.clone(),
             tile_id,
             data: SummaryTileData {
                 utilization: tile_utilization,
             },
-        }
+        })
     }
 
-    fn fetch_slot_tile(&mut self, entry_id: &EntryID, tile_id: TileID, _full: bool) -> SlotTile {
-        let items = &self.generate_slot(entry_id).0;
+    fn fetch_slot_tile(
+        &mut self,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        _full: bool,
+    ) -> Result<SlotTile, DataSourceError> {
+        let items = &self.generate_slot(entry_id)?.0;
 
         let mut slot_items = Vec::new();
         for row in items {
@@ -341,11 +393,11 @@ impl DataSourceMut for RandomDataSource {
             slot_items.push(slot_row);
         }
 
-        SlotTile {
+        Ok(SlotTile {
             entry_id: entry_id.clone(),
             tile_id,
             data: SlotTileData { items: slot_items },
-        }
+        })
     }
 
     fn fetch_slot_meta_tile(
@@ -353,8 +405,8 @@ impl DataSourceMut for RandomDataSource {
         entry_id: &EntryID,
         tile_id: TileID,
         _full: bool,
-    ) -> SlotMetaTile {
-        let (items, item_metas) = &self.generate_slot(entry_id);
+    ) -> Result<SlotMetaTile, DataSourceError> {
+        let (items, item_metas) = self.generate_slot(entry_id)?;
 
         let mut slot_items = Vec::new();
         for (row, row_meta) in items.iter().zip(item_metas.iter()) {
@@ -369,10 +421,58 @@ impl DataSourceMut for RandomDataSource {
             slot_items.push(slot_row);
         }
 
-        SlotMetaTile {
+        Ok(SlotMetaTile {
             entry_id: entry_id.clone(),
             tile_id,
             data: SlotMetaTileData { items: slot_items },
+        })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    use legion_prof_viewer::archive_data::{ArchiveFormat, DataSourceArchiveWriter};
+
+    const TRACE: &str = r#"{
+        "traceEvents": [
+            {"name": "a", "ph": "X", "ts": 0, "dur": 10, "pid": 1, "tid": 1},
+            {"name": "b", "ph": "X", "ts": 20, "dur": 5, "pid": 1, "tid": 1}
+        ]
+    }"#;
+
+    fn fetch_info(mut data_source: Box<dyn DeferredDataSource>) -> DataSourceInfo {
+        data_source.fetch_info();
+        loop {
+            if let Some(info) = data_source.get_infos().pop() {
+                return info.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
         }
     }
+
+    #[test]
+    fn test_open_data_source() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let trace_path = dir.join(format!("legion_prof_viewer_open_{}.json", id));
+        let archive_path = dir.join(format!("legion_prof_viewer_open_{}.archive", id));
+        std::fs::write(&trace_path, TRACE).unwrap();
+
+        let source =
+            DeferredDataSourceWrapper::new(ChromeTraceDataSource::new(&trace_path).unwrap());
+        DataSourceArchiveWriter::new(source, 1, 2, &archive_path, true, 1)
+            .with_format(ArchiveFormat::SingleFile)
+            .write()
+            .unwrap();
+
+        let trace_info = fetch_info(open_data_source(&trace_path).unwrap());
+        let archive_info = fetch_info(open_data_source(&archive_path).unwrap());
+        assert_eq!(archive_info.interval, trace_info.interval);
+        assert_eq!(archive_info.tile_set.tiles.len(), 1);
+
+        std::fs::remove_file(&trace_path).unwrap();
+        std::fs::remove_file(&archive_path).unwrap();
+    }
 }
@@ -1,16 +1,28 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use crate::data::{
-    DataSource, DataSourceInfo, EntryID, SlotMetaTile, SlotTile, SummaryTile, TileID,
+    DataSource, DataSourceError, DataSourceInfo, EntryID, InfoUpdate, SearchRequest,
+    SearchResponse, SlotMetaTile, SlotTile, SummaryTile, TileID, INFO_UPDATE_TIMEOUT,
 };
-use crate::deferred_data::DeferredDataSource;
+use crate::deferred_data::{is_unsupported, DeferredDataSource, RequestTracker, TileResponse};
+use crate::http::schema::TileKind;
+
+type Results<T> = Arc<Mutex<Vec<Result<T, DataSourceError>>>>;
+type TileResults<T> = Arc<Mutex<Vec<TileResponse<T>>>>;
 
 pub struct ParallelDeferredDataSource<T: DataSource + Send + Sync + 'static> {
     data_source: Arc<T>,
-    infos: Arc<Mutex<Vec<DataSourceInfo>>>,
-    summary_tiles: Arc<Mutex<Vec<SummaryTile>>>,
-    slot_tiles: Arc<Mutex<Vec<SlotTile>>>,
-    slot_meta_tiles: Arc<Mutex<Vec<SlotMetaTile>>>,
+    infos: Results<DataSourceInfo>,
+    summary_tiles: TileResults<SummaryTile>,
+    slot_tiles: TileResults<SlotTile>,
+    slot_meta_tiles: TileResults<SlotMetaTile>,
+    search_supported: Arc<AtomicBool>,
+    search_results: Results<SearchResponse>,
+    info_updates_supported: Arc<AtomicBool>,
+    info_updates: Results<Option<InfoUpdate>>,
+    requests: RequestTracker,
 }
 
 impl<T: DataSource + Send + Sync + 'static> ParallelDeferredDataSource<T> {
@@ -21,6 +33,11 @@ impl<T: DataSource + Send + Sync + 'static> ParallelDeferredDataSource<T> {
             summary_tiles: Arc::new(Mutex::new(Vec::new())),
             slot_tiles: Arc::new(Mutex::new(Vec::new())),
             slot_meta_tiles: Arc::new(Mutex::new(Vec::new())),
+            search_supported: Arc::new(AtomicBool::new(true)),
+            search_results: Arc::new(Mutex::new(Vec::new())),
+            info_updates_supported: Arc::new(AtomicBool::new(true)),
+            info_updates: Arc::new(Mutex::new(Vec::new())),
+            requests: RequestTracker::default(),
         }
     }
 }
@@ -35,7 +52,7 @@ impl<T: DataSource + Send + Sync + 'static> DeferredDataSource for ParallelDefer
         });
     }
 
-    fn get_infos(&mut self) -> Vec<DataSourceInfo> {
+    fn get_infos(&mut self) -> Vec<Result<DataSourceInfo, DataSourceError>> {
         std::mem::take(&mut self.infos.lock().unwrap())
     }
 
@@ -43,13 +60,21 @@ impl<T: DataSource + Send + Sync + 'static> DeferredDataSource for ParallelDefer
         let entry_id = entry_id.clone();
         let data_source = self.data_source.clone();
         let summary_tiles = self.summary_tiles.clone();
+        let token = self
+            .requests
+            .start(TileKind::Summary, &entry_id, tile_id, full);
         rayon::spawn(move || {
+            // Skip the work entirely if cancelled while queued
+            if token.is_cancelled() {
+                return;
+            }
             let result = data_source.fetch_summary_tile(&entry_id, tile_id, full);
-            summary_tiles.lock().unwrap().push(result);
+            let response = TileResponse::new(&entry_id, tile_id, full, result);
+            token.finish(|| summary_tiles.lock().unwrap().push(response));
         });
     }
 
-    fn get_summary_tiles(&mut self) -> Vec<SummaryTile> {
+    fn get_summary_tiles(&mut self) -> Vec<TileResponse<SummaryTile>> {
         std::mem::take(&mut self.summary_tiles.lock().unwrap())
     }
 
@@ -57,13 +82,21 @@ impl<T: DataSource + Send + Sync + 'static> DeferredDataSource for ParallelDefer
         let entry_id = entry_id.clone();
         let data_source = self.data_source.clone();
         let slot_tiles = self.slot_tiles.clone();
+        let token = self
+            .requests
+            .start(TileKind::Slot, &entry_id, tile_id, full);
         rayon::spawn(move || {
+            // Skip the work entirely if cancelled while queued
+            if token.is_cancelled() {
+                return;
+            }
             let result = data_source.fetch_slot_tile(&entry_id, tile_id, full);
-            slot_tiles.lock().unwrap().push(result);
+            let response = TileResponse::new(&entry_id, tile_id, full, result);
+            token.finish(|| slot_tiles.lock().unwrap().push(response));
         });
     }
 
-    fn get_slot_tiles(&mut self) -> Vec<SlotTile> {
+    fn get_slot_tiles(&mut self) -> Vec<TileResponse<SlotTile>> {
         std::mem::take(&mut self.slot_tiles.lock().unwrap())
     }
 
@@ -71,13 +104,76 @@ impl<T: DataSource + Send + Sync + 'static> DeferredDataSource for ParallelDefer
         let entry_id = entry_id.clone();
         let data_source = self.data_source.clone();
         let slot_meta_tiles = self.slot_meta_tiles.clone();
+        let token = self
+            .requests
+            .start(TileKind::SlotMeta, &entry_id, tile_id, full);
         rayon::spawn(move || {
+            // Skip the work entirely if cancelled while queued
+            if token.is_cancelled() {
+                return;
+            }
             let result = data_source.fetch_slot_meta_tile(&entry_id, tile_id, full);
-            slot_meta_tiles.lock().unwrap().push(result);
+            let response = TileResponse::new(&entry_id, tile_id, full, result);
+            token.finish(|| slot_meta_tiles.lock().unwrap().push(response));
         });
     }
 
-    fn get_slot_meta_tiles(&mut self) -> Vec<SlotMetaTile> {
+    fn get_slot_meta_tiles(&mut self) -> Vec<TileResponse<SlotMetaTile>> {
         std::mem::take(&mut self.slot_meta_tiles.lock().unwrap())
     }
+
+    fn cancel_tile(
+        &mut self,
+        kind: TileKind,
+        entry_id: &EntryID,
+        tile_id: TileID,
+        full: bool,
+    ) -> u64 {
+        self.requests.cancel_tile(kind, entry_id, tile_id, full)
+    }
+
+    fn supports_search(&self) -> bool {
+        self.search_supported.load(Ordering::Relaxed)
+    }
+
+    fn fetch_search(&mut self, request: &SearchRequest) {
+        let request = request.clone();
+        let data_source = self.data_source.clone();
+        let search_supported = self.search_supported.clone();
+        let search_results = self.search_results.clone();
+        rayon::spawn(move || {
+            let result = data_source.search(&request);
+            if is_unsupported(&result) {
+                search_supported.store(false, Ordering::Relaxed);
+            }
+            let result = result.map(|results| SearchResponse { request, results });
+            search_results.lock().unwrap().push(result);
+        });
+    }
+
+    fn get_search_results(&mut self) -> Vec<Result<SearchResponse, DataSourceError>> {
+        std::mem::take(&mut self.search_results.lock().unwrap())
+    }
+
+    fn supports_info_updates(&self) -> bool {
+        self.info_updates_supported.load(Ordering::Relaxed)
+    }
+
+    fn fetch_info_update(&mut self, since: u64) {
+        let data_source = self.data_source.clone();
+        let info_updates_supported = self.info_updates_supported.clone();
+        let info_updates = self.info_updates.clone();
+        // Not on the rayon pool, where waiting would hold up tile requests
+        thread::spawn(move || {
+            let result = data_source.fetch_info_update(since, INFO_UPDATE_TIMEOUT);
+            if is_unsupported(&result) {
+                info_updates_supported.store(false, Ordering::Relaxed);
+            }
+            info_updates.lock().unwrap().push(result);
+        });
+    }
+
+    fn get_info_updates(&mut self) -> Vec<Result<Option<InfoUpdate>, DataSourceError>> {
+        std::mem::take(&mut self.info_updates.lock().unwrap())
+    }
 }
@@ -0,0 +1,653 @@
+use std::fmt;
+
+use crate::data::{EntryID, Field, FieldID, FieldSchema, ItemLink, ItemMeta};
+use crate::timestamp::Timestamp;
+
+// A small query language for searching over item fields, e.g.:
+//
+//     title ~ "gemm" and duration > 5ms and node = 3
+//
+// Grammar:
+//
+//     expr       := and_expr ("or" and_expr)*
+//     and_expr   := unary ("and" unary)*
+//     unary      := "not" unary | "(" expr ")" | comparison
+//     comparison := name op value
+//     op         := "=" | "!=" | "<" | "<=" | ">" | ">=" | "~"
+//     value      := string | number | duration | word
+//
+// Names are matched case-insensitively. The names title, duration, start
+// and stop refer to properties of the item itself, and node to the node of
+// the slot containing it (unless the profile has a field by that name); any
+// other name is looked up in the FieldSchema. Names containing spaces may be
+// quoted.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParseError {
+    // 1-based, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldRef {
+    Title,
+    Duration,
+    Start,
+    Stop,
+    Node,
+    Field(FieldID),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    // Durations are converted to nanoseconds at parse time. Wide enough for
+    // both I64 and U64 fields.
+    Number(i128),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: FieldRef,
+        op: CompareOp,
+        value: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+    // As written, so that integers are parsed exactly
+    Number(String),
+    Op(CompareOp),
+    LParen,
+    RParen,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "'{w}'"),
+            Token::String(s) => write!(f, "\"{s}\""),
+            Token::Number(n) => write!(f, "'{n}'"),
+            Token::Op(_) => write!(f, "operator"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::End => write!(f, "end of query"),
+        }
+    }
+}
+
+fn error<T>(column: usize, message: impl Into<String>) -> Result<T, QueryParseError> {
+    Err(QueryParseError {
+        column,
+        message: message.into(),
+    })
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, QueryParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = match c {
+            '(' => {
+                i += 1;
+                Token::LParen
+            }
+            ')' => {
+                i += 1;
+                Token::RParen
+            }
+            '~' => {
+                i += 1;
+                Token::Op(CompareOp::Contains)
+            }
+            '=' => {
+                // Accept both = and ==
+                i += 1;
+                if chars.get(i) == Some(&'=') {
+                    i += 1;
+                }
+                Token::Op(CompareOp::Eq)
+            }
+            '!' | '<' | '>' => {
+                i += 1;
+                let eq = chars.get(i) == Some(&'=');
+                if eq {
+                    i += 1;
+                }
+                Token::Op(match (c, eq) {
+                    ('!', true) => CompareOp::Ne,
+                    ('!', false) => return error(column, "expected '=' after '!'"),
+                    ('<', true) => CompareOp::Le,
+                    ('<', false) => CompareOp::Lt,
+                    ('>', true) => CompareOp::Ge,
+                    ('>', false) => CompareOp::Gt,
+                    _ => unreachable!(),
+                })
+            }
+            '"' => {
+                i += 1;
+                let mut value = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return error(column, "unterminated string"),
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some('\\') if i + 1 < chars.len() => {
+                            value.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::String(value)
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let unit_start = i;
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                let suffix: String = chars[unit_start..i].iter().collect();
+                // A number directly followed by anything but a unit is a
+                // word (e.g., 3d_gemm)
+                if !suffix.is_empty() && !is_unit(&suffix) {
+                    Token::Word(number + &suffix)
+                } else {
+                    if number.parse::<f64>().is_err() {
+                        return error(column, format!("invalid number '{number}'"));
+                    }
+                    if suffix.is_empty() {
+                        Token::Number(number)
+                    } else {
+                        tokens.push((column, Token::Number(number)));
+                        tokens.push((unit_start + 1, Token::Word(suffix)));
+                        continue;
+                    }
+                }
+            }
+            c if is_word_char(c) => {
+                let start = i;
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                Token::Word(chars[start..i].iter().collect())
+            }
+            c => return error(column, format!("unexpected character '{c}'")),
+        };
+        tokens.push((column, token));
+    }
+    tokens.push((chars.len() + 1, Token::End));
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The units accepted by Timestamp::parse
+fn is_unit(s: &str) -> bool {
+    ["ns", "us", "ms", "s"].contains(&s.to_lowercase().as_str())
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    schema: &'a FieldSchema,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].1
+    }
+
+    fn column(&self) -> usize {
+        self.tokens[self.position].0
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let result = self.tokens[self.position].clone();
+        if self.position + 1 < self.tokens.len() {
+            self.position += 1;
+        }
+        result
+    }
+
+    fn expr(&mut self) -> Result<Expr, QueryParseError> {
+        let mut lhs = self.and_expr()?;
+        while is_keyword(self.peek(), "or") {
+            self.next();
+            let rhs = self.and_expr()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, QueryParseError> {
+        let mut lhs = self.unary()?;
+        while is_keyword(self.peek(), "and") {
+            self.next();
+            let rhs = self.unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryParseError> {
+        if is_keyword(self.peek(), "not") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if *self.peek() == Token::LParen {
+            self.next();
+            let expr = self.expr()?;
+            let (column, token) = self.next();
+            if token != Token::RParen {
+                return error(column, format!("expected ')', found {token}"));
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, QueryParseError> {
+        let (column, token) = self.next();
+        let name = match token {
+            Token::Word(name) | Token::String(name) => name,
+            token => return error(column, format!("expected field name, found {token}")),
+        };
+        let field = self.field(column, &name)?;
+
+        let (column, token) = self.next();
+        let Token::Op(op) = token else {
+            return error(
+                column,
+                format!("expected comparison operator, found {token}"),
+            );
+        };
+
+        let value = self.value()?;
+        Ok(Expr::Compare { field, op, value })
+    }
+
+    fn field(&self, column: usize, name: &str) -> Result<FieldRef, QueryParseError> {
+        let lower = name.to_lowercase();
+        match lower.as_str() {
+            "title" => return Ok(FieldRef::Title),
+            "duration" => return Ok(FieldRef::Duration),
+            "start" => return Ok(FieldRef::Start),
+            "stop" => return Ok(FieldRef::Stop),
+            _ => {}
+        }
+        if let Some(field_id) = self.schema_field(name) {
+            return Ok(FieldRef::Field(field_id));
+        }
+        if lower == "node" {
+            return Ok(FieldRef::Node);
+        }
+        error(column, format!("unknown field '{name}'"))
+    }
+
+    fn schema_field(&self, name: &str) -> Option<FieldID> {
+        if let Some(field_id) = self.schema.get_id(name) {
+            return Some(field_id);
+        }
+        // Fall back to a case-insensitive search over the schema
+        let lower = name.to_lowercase();
+        self.schema
+            .field_ids()
+            .find(|field_id| self.schema.get_name(*field_id).unwrap().to_lowercase() == lower)
+    }
+
+    fn value(&mut self) -> Result<Value, QueryParseError> {
+        let (column, token) = self.next();
+        if is_keyword(&token, "and") || is_keyword(&token, "or") {
+            return error(column, format!("expected value, found {token}"));
+        }
+        match token {
+            Token::String(s) | Token::Word(s) => Ok(Value::String(s)),
+            Token::Number(n) => {
+                // A number followed by a unit is a duration. Reuse
+                // Timestamp::parse so that units match the rest of the UI.
+                let unit_column = self.column();
+                if let Token::Word(unit) = self.peek().clone() {
+                    if is_unit(&unit) {
+                        self.next();
+                        return match Timestamp::parse(&format!("{n}{unit}")) {
+                            Ok(t) => Ok(Value::Number(t.0 as i128)),
+                            Err(_) => error(unit_column, format!("invalid unit '{unit}'")),
+                        };
+                    }
+                }
+                if n.contains('.') {
+                    return error(column, format!("expected integer, found '{n}'"));
+                }
+                match n.parse::<i128>() {
+                    Ok(x) if (i64::MIN as i128..=u64::MAX as i128).contains(&x) => {
+                        Ok(Value::Number(x))
+                    }
+                    _ => error(column, format!("integer '{n}' is out of range")),
+                }
+            }
+            token => error(column, format!("expected value, found {token}")),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str, schema: &FieldSchema) -> Result<Self, QueryParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            schema,
+        };
+        let expr = parser.expr()?;
+        let (column, token) = parser.next();
+        if token != Token::End {
+            return error(column, format!("expected 'and' or 'or', found {token}"));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn is_match(&self, entry_id: &EntryID, item: &ItemMeta) -> bool {
+        self.expr.is_match(entry_id, item)
+    }
+}
+
+fn compare_numbers<T: PartialOrd + ToString>(lhs: T, op: CompareOp, rhs: T) -> bool {
+    match op {
+        CompareOp::Eq => lhs == rhs,
+        CompareOp::Ne => lhs != rhs,
+        CompareOp::Lt => lhs < rhs,
+        CompareOp::Le => lhs <= rhs,
+        CompareOp::Gt => lhs > rhs,
+        CompareOp::Ge => lhs >= rhs,
+        CompareOp::Contains => lhs.to_string().contains(&rhs.to_string()),
+    }
+}
+
+// Profiles are laid out as root -> node -> kind -> slot (see
+// EntryInfo::kinds), so only slots at that depth belong to a node. Any other
+// layout (e.g., a Chrome trace) has no nodes.
+const NODE_SLOT_LEVEL: u64 = 3;
+
+fn node(entry_id: &EntryID) -> Option<u64> {
+    if entry_id.level() != NODE_SLOT_LEVEL {
+        return None;
+    }
+    entry_id.slot_index(0)
+}
+
+fn compare_strings(lhs: &str, op: CompareOp, rhs: &str) -> bool {
+    match op {
+        CompareOp::Eq => lhs == rhs,
+        CompareOp::Ne => lhs != rhs,
+        CompareOp::Lt => lhs < rhs,
+        CompareOp::Le => lhs <= rhs,
+        CompareOp::Gt => lhs > rhs,
+        CompareOp::Ge => lhs >= rhs,
+        CompareOp::Contains => lhs.contains(rhs),
+    }
+}
+
+fn compare_string_value(lhs: &str, op: CompareOp, value: &Value) -> bool {
+    match value {
+        Value::String(rhs) => compare_strings(lhs, op, rhs),
+        Value::Number(rhs) => compare_strings(lhs, op, &rhs.to_string()),
+    }
+}
+
+fn compare_value(lhs: i128, op: CompareOp, value: &Value) -> bool {
+    match value {
+        Value::Number(rhs) => compare_numbers(lhs, op, *rhs),
+        Value::String(rhs) => compare_strings(&lhs.to_string(), op, rhs),
+    }
+}
+
+fn compare_field(field: &Field, op: CompareOp, value: &Value) -> bool {
+    match field {
+        Field::I64(x) => compare_value(*x as i128, op, value),
+        Field::U64(x) => compare_value(*x as i128, op, value),
+        Field::F64(x) => match value {
+            Value::Number(rhs) => compare_numbers(*x, op, *rhs as f64),
+            Value::String(rhs) => compare_strings(&x.to_string(), op, rhs),
+        },
+        Field::String(s) | Field::ItemLink(ItemLink { title: s, .. }) => {
+            compare_string_value(s, op, value)
+        }
+        Field::Interval(interval) => compare_value(interval.duration_ns() as i128, op, value),
+        Field::Vec(fields) => {
+            // != must hold for every element, everything else for any
+            if op == CompareOp::Ne {
+                fields.iter().all(|f| compare_field(f, op, value))
+            } else {
+                fields.iter().any(|f| compare_field(f, op, value))
+            }
+        }
+        Field::Empty => false,
+    }
+}
+
+impl Expr {
+    fn is_match(&self, entry_id: &EntryID, item: &ItemMeta) -> bool {
+        match self {
+            Expr::And(lhs, rhs) => lhs.is_match(entry_id, item) && rhs.is_match(entry_id, item),
+            Expr::Or(lhs, rhs) => lhs.is_match(entry_id, item) || rhs.is_match(entry_id, item),
+            Expr::Not(expr) => !expr.is_match(entry_id, item),
+            Expr::Compare { field, op, value } => {
+                let interval = item.original_interval;
+                match field {
+                    FieldRef::Title => compare_string_value(&item.title, *op, value),
+                    FieldRef::Duration => compare_value(interval.duration_ns() as i128, *op, value),
+                    FieldRef::Start => compare_value(interval.start.0 as i128, *op, value),
+                    FieldRef::Stop => compare_value(interval.stop.0 as i128, *op, value),
+                    FieldRef::Node => {
+                        node(entry_id).map_or(false, |node| compare_value(node as i128, *op, value))
+                    }
+                    FieldRef::Field(field_id) => item
+                        .fields
+                        .iter()
+                        .filter(|(x, _)| x == field_id)
+                        .any(|(_, f)| compare_field(f, *op, value)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::ItemUID;
+    use crate::timestamp::Interval;
+
+    fn schema() -> FieldSchema {
+        let mut schema = FieldSchema::new();
+        schema.insert("Title".to_owned(), true);
+        schema.insert("Item UID".to_owned(), false);
+        schema.insert("Provenance".to_owned(), true);
+        schema
+    }
+
+    fn item(schema: &FieldSchema, title: &str, start: i64, stop: i64) -> ItemMeta {
+        ItemMeta {
+            item_uid: ItemUID(7),
+            original_interval: Interval::new(Timestamp(start), Timestamp(stop)),
+            title: title.to_owned(),
+            fields: vec![
+                (schema.get_id("Item UID").unwrap(), Field::U64(7)),
+                (
+                    schema.get_id("Provenance").unwrap(),
+                    Field::String("main.cc:42".to_owned()),
+                ),
+            ],
+        }
+    }
+
+    fn is_match(query: &str, entry_id: &EntryID, item: &ItemMeta) -> bool {
+        Query::parse(query, &schema())
+            .unwrap()
+            .is_match(entry_id, item)
+    }
+
+    #[test]
+    fn test_match() {
+        let schema = schema();
+        let node3 = EntryID::root().child(3).child(0).child(1);
+        let gemm = item(&schema, "gemm_kernel", 0, 10_000_000);
+        let copy = item(&schema, "copy", 0, 1_000);
+
+        let query = r#"title ~ "gemm" and duration > 5ms and node = 3"#;
+        assert!(is_match(query, &node3, &gemm));
+        assert!(!is_match(query, &node3, &copy));
+        assert!(!is_match(query, &EntryID::root().child(2), &gemm));
+        // Not a slot under a node (e.g., a thread in a Chrome trace)
+        assert!(!is_match(query, &EntryID::root().child(3).child(0), &gemm));
+
+        assert!(is_match("title = copy or duration >= 10 ms", &node3, &gemm));
+        assert!(is_match("not (title ~ gemm)", &node3, &copy));
+        assert!(is_match(
+            r#""item uid" = 7 and provenance ~ main"#,
+            &node3,
+            &copy
+        ));
+        assert!(is_match("start = 0 and stop < 1.5us", &node3, &copy));
+
+        // Only a unit directly after a number makes it a duration
+        let gemm3d = item(&schema, "3d_gemm", 0, 1_000);
+        assert!(is_match("title = 3d_gemm", &node3, &gemm3d));
+        assert!(is_match("title = 3d_gemm or duration > 5ms", &node3, &gemm));
+    }
+
+    #[test]
+    fn test_node_field() {
+        // A field named node takes precedence over the node of the slot
+        let mut schema = schema();
+        let node_id = schema.insert("Node".to_owned(), true);
+        let mut gemm = item(&schema, "gemm", 0, 10);
+        gemm.fields.push((node_id, Field::U64(5)));
+        let node3 = EntryID::root().child(3).child(0).child(1);
+        let query = Query::parse("node = 5", &schema).unwrap();
+        assert_eq!(
+            query.expr,
+            Expr::Compare {
+                field: FieldRef::Field(node_id),
+                op: CompareOp::Eq,
+                value: Value::Number(5),
+            }
+        );
+        assert!(query.is_match(&node3, &gemm));
+    }
+
+    #[test]
+    fn test_units() {
+        let schema = schema();
+        // Only a unit after a number is consumed with it
+        let query = Query::parse("duration > 5 ms", &schema).unwrap();
+        assert!(matches!(
+            query.expr,
+            Expr::Compare {
+                value: Value::Number(5_000_000),
+                ..
+            }
+        ));
+        let e = Query::parse("title = 5 gemm", &schema).unwrap_err();
+        assert_eq!(e.column, 11);
+        assert!(e.message.contains("'gemm'"));
+    }
+
+    #[test]
+    fn test_large_integers() {
+        let schema = schema();
+        let uid_id = schema.get_id("Item UID").unwrap();
+        let mut copy = item(&schema, "copy", 0, 10);
+        copy.fields = vec![(uid_id, Field::U64(u64::MAX - 1))];
+        let entry_id = EntryID::root().child(0);
+
+        // Beyond what an f64 (or i64) can represent exactly
+        let query = format!("\"item uid\" = {}", u64::MAX - 1);
+        assert!(is_match(&query, &entry_id, &copy));
+        let query = format!("\"item uid\" = {}", u64::MAX);
+        assert!(!is_match(&query, &entry_id, &copy));
+
+        let e = Query::parse("\"item uid\" = 18446744073709551616", &schema).unwrap_err();
+        assert_eq!(e.column, 14);
+        assert!(e.message.contains("out of range"));
+        assert!(Query::parse("duration > 1.5", &schema).is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let schema = schema();
+        let a = Query::parse("title = a or title = b and title = c", &schema).unwrap();
+        let b = Query::parse("title = a or (title = b and title = c)", &schema).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let schema = schema();
+        let column = |s| Query::parse(s, &schema).unwrap_err().column;
+        assert_eq!(column("foo = 1"), 1);
+        assert_eq!(column("title ="), 8);
+        assert_eq!(column("title = 1 duration"), 11);
+        assert_eq!(column("(title = 1"), 11);
+        assert_eq!(column("duration > 5 parsecs"), 14);
+        assert_eq!(column("title ~ \"abc"), 9);
+        assert_eq!(column("title # 1"), 7);
+    }
+}
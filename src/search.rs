@@ -0,0 +1,571 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{
+    DataSource, DataSourceError, DataSourceInfo, EntryID, EntryInfo, Field, FieldID, FieldSchema,
+    ItemLink, ItemMeta, ItemUID, SearchMode, SearchRequest, SearchResult, SlotMetaTile, TileID,
+};
+use crate::query::Query;
+use crate::timestamp::Interval;
+
+// Searching for items, either in the viewer (by scanning slot meta tiles as
+// they are loaded) or in the data source itself (see DataSource::search).
+// Both use ItemMatcher, so they agree on what matches.
+
+impl SearchMode {
+    // Substring search is a plain str::contains, so there is nothing to
+    // compile. Queries are parsed separately (see query.rs). Every other
+    // mode is expressed as a regex.
+    pub fn compile(self, query: &str) -> Result<Option<Regex>, regex::Error> {
+        let pattern = match self {
+            SearchMode::Substring | SearchMode::Query => return Ok(None),
+            SearchMode::CaseInsensitive => regex::escape(query),
+            SearchMode::WholeWord => format!(r"(?:^|\W){}(?:\W|$)", regex::escape(query)),
+            SearchMode::Regex => query.to_owned(),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self == SearchMode::CaseInsensitive)
+            .build()
+            .map(Some)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Substring(String),
+    Regex(Regex),
+    Query(Query),
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemMatcher {
+    pattern: Pattern,
+    field: Option<FieldID>,
+}
+
+impl ItemMatcher {
+    // Errors are suitable for display to the user
+    pub fn new(
+        mode: SearchMode,
+        query: &str,
+        field: Option<FieldID>,
+        field_schema: &FieldSchema,
+    ) -> Result<Self, String> {
+        let pattern = if mode == SearchMode::Query {
+            match Query::parse(query, field_schema) {
+                Ok(query) => Pattern::Query(query),
+                Err(e) => return Err(format!("Invalid query: {e}")),
+            }
+        } else {
+            match mode.compile(query) {
+                Ok(Some(matcher)) => Pattern::Regex(matcher),
+                Ok(None) => Pattern::Substring(query.to_owned()),
+                Err(e) => return Err(format!("Invalid regex: {e}")),
+            }
+        };
+        Ok(Self { pattern, field })
+    }
+
+    pub fn from_request(
+        request: &SearchRequest,
+        field_schema: &FieldSchema,
+    ) -> Result<Self, String> {
+        Self::new(request.mode, &request.query, request.field, field_schema)
+    }
+
+    fn is_string_match(&self, s: &str) -> bool {
+        match &self.pattern {
+            Pattern::Substring(query) => s.contains(query),
+            Pattern::Regex(matcher) => matcher.is_match(s),
+            Pattern::Query(_) => unreachable!(),
+        }
+    }
+
+    fn is_field_match(&self, field: &Field) -> bool {
+        match field {
+            Field::String(s) => self.is_string_match(s),
+            Field::ItemLink(ItemLink { title, .. }) => self.is_string_match(title),
+            Field::Vec(fields) => fields.iter().any(|f| self.is_field_match(f)),
+            _ => false,
+        }
+    }
+
+    pub fn is_match(&self, entry_id: &EntryID, item: &ItemMeta) -> bool {
+        if let Pattern::Query(query) = &self.pattern {
+            return query.is_match(entry_id, item);
+        }
+
+        match self.field {
+            None => self.is_string_match(&item.title),
+            Some(field) => item
+                .fields
+                .iter()
+                .find(|(x, _)| *x == field)
+                .map_or(false, |(_, value)| self.is_field_match(value)),
+        }
+    }
+}
+
+fn slot_ids(info: &EntryInfo, entry_id: EntryID, result: &mut Vec<EntryID>) {
+    match info {
+        EntryInfo::Panel { slots, .. } => {
+            for (i, slot) in slots.iter().enumerate() {
+                slot_ids(slot, entry_id.child(i as u64), result);
+            }
+        }
+        EntryInfo::Slot { .. } => result.push(entry_id),
+        EntryInfo::Summary { .. } => {}
+    }
+}
+
+// Accumulates the results of a search, one slot meta tile at a time
+pub struct SearchCollector {
+    matcher: ItemMatcher,
+    interval: Interval,
+    max_results: usize,
+    seen: BTreeSet<ItemUID>,
+    results: Vec<SearchResult>,
+}
+
+impl SearchCollector {
+    pub fn new(
+        request: &SearchRequest,
+        field_schema: &FieldSchema,
+    ) -> Result<Self, DataSourceError> {
+        let matcher = ItemMatcher::from_request(request, field_schema)
+            .map_err(DataSourceError::BadRequest)?;
+        Ok(Self {
+            matcher,
+            interval: request.interval,
+            max_results: request.max_results,
+            seen: BTreeSet::new(),
+            results: Vec::new(),
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.results.len() >= self.max_results
+    }
+
+    // If candidates are given, only those items are considered
+    pub fn add_tile(&mut self, tile: &SlotMetaTile, candidates: Option<&BTreeSet<ItemUID>>) {
+        let rows = tile.data.items.len();
+        for (row, row_items) in tile.data.items.iter().enumerate() {
+            for item in row_items {
+                if self.is_full() {
+                    return;
+                }
+                if candidates.map_or(false, |c| !c.contains(&item.item_uid))
+                    || !item.original_interval.overlaps(self.interval)
+                    || !self.matcher.is_match(&tile.entry_id, item)
+                {
+                    continue;
+                }
+                // Items that cross tile boundaries appear in every tile
+                if !self.seen.insert(item.item_uid) {
+                    continue;
+                }
+                self.results.push(SearchResult {
+                    item_uid: item.item_uid,
+                    entry_id: tile.entry_id.clone(),
+                    // Reverse rows because we're in screen space
+                    row: rows - row - 1,
+                    original_interval: item.original_interval,
+                    title: item.title.clone(),
+                });
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<SearchResult> {
+        self.results
+    }
+}
+
+// A straightforward implementation of DataSource::search: scan the full
+// resolution slot meta tiles of every requested slot. This is the same
+// work the viewer would do, but without sending the tiles anywhere.
+pub fn scan_slot_meta_tiles<T>(
+    data_source: &T,
+    request: &SearchRequest,
+) -> Result<Vec<SearchResult>, DataSourceError>
+where
+    T: DataSource + ?Sized,
+{
+    let info = data_source.fetch_info()?;
+    let mut collector = SearchCollector::new(request, &info.field_schema)?;
+    for (entry_id, tile_id) in scan_tiles(&info, request) {
+        if collector.is_full() {
+            break;
+        }
+        let tile = data_source.fetch_slot_meta_tile(&entry_id, tile_id, true)?;
+        collector.add_tile(&tile, None);
+    }
+    Ok(collector.finish())
+}
+
+// The full resolution slot meta tiles that may contain matches for the
+// request, in the order they're scanned
+pub fn scan_tiles(info: &DataSourceInfo, request: &SearchRequest) -> Vec<(EntryID, TileID)> {
+    let entry_ids = match &request.entry_ids {
+        Some(entry_ids) => entry_ids.clone(),
+        None => {
+            let mut entry_ids = Vec::new();
+            slot_ids(&info.entry_info, EntryID::root(), &mut entry_ids);
+            entry_ids
+        }
+    };
+    let tile_ids: Vec<_> = (info.tile_set.full_tiles(request.interval).into_iter())
+        .filter(|tile_id| tile_id.0.overlaps(request.interval))
+        .collect();
+
+    let mut result = Vec::new();
+    for entry_id in entry_ids {
+        if !matches!(info.entry_info.get(&entry_id), Some(EntryInfo::Slot { .. })) {
+            continue;
+        }
+        result.extend(tile_ids.iter().map(|tile_id| (entry_id.clone(), *tile_id)));
+    }
+    result
+}
+
+// Search index: an inverted index from tokens to the items containing them,
+// so that a search only needs to read the tiles of candidate items. Tokens
+// are the (lowercased) trigrams of an item's title and searchable fields,
+// so any substring, case-insensitive or whole word search for three or more
+// characters can be answered: every trigram of the query must occur in a
+// match. Candidates are then checked against the actual items, so results
+// are those of a scan. (Except that a case-insensitive search may miss the
+// few characters whose case folding disagrees with lowercasing, e.g. long
+// s.)
+//
+// Postings are grouped into shards by field and token prefix, so that a
+// client only needs the shards for the tokens in its query.
+
+const SHARD_PREFIX_CHARS: usize = 2;
+
+// Each trigram may cost a shard, so longer queries only use the trigrams
+// that look rarest. Every match still contains those, so the candidates are
+// a superset of the matches.
+const MAX_QUERY_TOKENS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Posting {
+    pub entry_id: EntryID,
+    // The first (full resolution) tile containing the item
+    pub tile_id: TileID,
+    pub item_uid: ItemUID,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct ShardID {
+    // None for item titles
+    pub field: Option<FieldID>,
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SearchIndexInfo {
+    // Searchable fields that were indexed (titles always are)
+    pub fields: BTreeSet<FieldID>,
+    // Shards are stored by their position in this list
+    pub shards: Vec<ShardID>,
+    // Postings in each shard (by position), as an estimate of how common
+    // its tokens are
+    pub shard_postings: Vec<usize>,
+}
+
+// The tokens every match must contain, each with the shard (by position)
+// holding its postings, or None if no item contains it
+#[derive(Debug, Clone)]
+pub struct IndexLookup {
+    pub tokens: Vec<(String, Option<usize>)>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SearchIndexShard {
+    pub postings: BTreeMap<String, Vec<Posting>>,
+}
+
+fn tokens(s: &str) -> BTreeSet<String> {
+    // Lowercase character by character, so that a substring of s always
+    // lowercases to a substring of the lowercased s
+    let chars: Vec<_> = s.chars().flat_map(char::to_lowercase).collect();
+    chars.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+fn shard_id(field: Option<FieldID>, token: &str) -> ShardID {
+    ShardID {
+        field,
+        prefix: token.chars().take(SHARD_PREFIX_CHARS).collect(),
+    }
+}
+
+fn field_strings<'a>(field: &'a Field, result: &mut Vec<&'a str>) {
+    // Same as ItemMatcher::is_field_match
+    match field {
+        Field::String(s) => result.push(s),
+        Field::ItemLink(ItemLink { title, .. }) => result.push(title),
+        Field::Vec(fields) => fields.iter().for_each(|f| field_strings(f, result)),
+        _ => {}
+    }
+}
+
+pub struct SearchIndexBuilder {
+    fields: BTreeSet<FieldID>,
+    postings: BTreeMap<ShardID, BTreeMap<String, BTreeSet<Posting>>>,
+}
+
+impl SearchIndexBuilder {
+    pub fn new(field_schema: &FieldSchema) -> Self {
+        Self {
+            fields: field_schema.searchable().clone(),
+            postings: BTreeMap::new(),
+        }
+    }
+
+    fn add_text(&mut self, field: Option<FieldID>, text: &str, posting: &Posting) {
+        for token in tokens(text) {
+            self.postings
+                .entry(shard_id(field, &token))
+                .or_default()
+                .entry(token)
+                .or_default()
+                .insert(posting.clone());
+        }
+    }
+
+    // Tiles must be at full resolution, so that no items are left out
+    pub fn add_tile(&mut self, tile: &SlotMetaTile) {
+        for item in tile.data.items.iter().flatten() {
+            let posting = Posting {
+                entry_id: tile.entry_id.clone(),
+                tile_id: tile.tile_id,
+                item_uid: item.item_uid,
+            };
+            self.add_text(None, &item.title, &posting);
+            for (field_id, field) in &item.fields {
+                if !self.fields.contains(field_id) {
+                    continue;
+                }
+                let mut strings = Vec::new();
+                field_strings(field, &mut strings);
+                for s in strings {
+                    self.add_text(Some(*field_id), s, &posting);
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> (SearchIndexInfo, Vec<SearchIndexShard>) {
+        let mut info = SearchIndexInfo {
+            fields: self.fields,
+            shards: Vec::new(),
+            shard_postings: Vec::new(),
+        };
+        let mut shards = Vec::new();
+        for (shard_id, postings) in self.postings {
+            let postings = postings
+                .into_iter()
+                .map(|(token, postings)| {
+                    // Items that cross tile boundaries were added once per
+                    // tile, keep only the first
+                    let mut seen = BTreeSet::new();
+                    let postings = postings
+                        .into_iter()
+                        .filter(|p| seen.insert((p.entry_id.clone(), p.item_uid)))
+                        .collect();
+                    (token, postings)
+                })
+                .collect::<BTreeMap<_, Vec<_>>>();
+            info.shards.push(shard_id);
+            info.shard_postings
+                .push(postings.values().map(Vec::len).sum());
+            shards.push(SearchIndexShard { postings });
+        }
+        (info, shards)
+    }
+}
+
+impl SearchIndexInfo {
+    // None if the index can't answer the request
+    pub fn lookup(&self, request: &SearchRequest) -> Option<IndexLookup> {
+        match request.mode {
+            SearchMode::Substring | SearchMode::CaseInsensitive | SearchMode::WholeWord => {}
+            SearchMode::Regex | SearchMode::Query => return None,
+        }
+        if let Some(field) = request.field {
+            if !self.fields.contains(&field) {
+                return None;
+            }
+        }
+        let mut tokens: Vec<_> = (tokens(&request.query).into_iter())
+            .map(|token| {
+                let shard_id = shard_id(request.field, &token);
+                let shard = self.shards.iter().position(|x| *x == shard_id);
+                (token, shard)
+            })
+            .collect();
+        if tokens.is_empty() {
+            return None;
+        }
+        // A token in no shard is the rarest of all, since nothing matches
+        tokens.sort_by_key(|(_, shard)| match shard {
+            Some(i) => self.shard_postings.get(*i).copied().unwrap_or(usize::MAX),
+            None => 0,
+        });
+        tokens.truncate(MAX_QUERY_TOKENS);
+        Some(IndexLookup { tokens })
+    }
+}
+
+impl IndexLookup {
+    pub fn shards(&self) -> BTreeSet<usize> {
+        self.tokens.iter().filter_map(|(_, shard)| *shard).collect()
+    }
+}
+
+// Items containing every token, grouped by the tile to check them in (only
+// tiles overlapping the request's interval), from the shards (by position)
+// that could be read. Tokens in shards that couldn't be read don't narrow
+// the search. If that leaves no tokens, returns None: every tile has to be
+// scanned.
+pub fn candidates(
+    request: &SearchRequest,
+    lookup: &IndexLookup,
+    shards: &BTreeMap<usize, SearchIndexShard>,
+) -> Option<BTreeMap<(EntryID, TileID), BTreeSet<ItemUID>>> {
+    let entry_ids: Option<BTreeSet<_>> = request.entry_ids.as_ref().map(|x| x.iter().collect());
+    let mut result: Option<BTreeMap<(&EntryID, ItemUID), &Posting>> = None;
+    for (token, shard) in &lookup.tokens {
+        let shard = match shard {
+            Some(i) => match shards.get(i) {
+                Some(shard) => Some(shard),
+                None => continue,
+            },
+            None => None,
+        };
+        let Some(postings) = shard.and_then(|shard| shard.postings.get(token)) else {
+            // Nothing contains this token, so nothing matches
+            return Some(BTreeMap::new());
+        };
+        let postings = postings
+            .iter()
+            .filter(|p| entry_ids.as_ref().map_or(true, |x| x.contains(&p.entry_id)))
+            .filter(|p| p.tile_id.0.overlaps(request.interval))
+            .map(|p| ((&p.entry_id, p.item_uid), p));
+        result = Some(match result {
+            None => postings.collect(),
+            Some(result) => postings.filter(|(k, _)| result.contains_key(k)).collect(),
+        });
+    }
+
+    let mut tiles: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for posting in result?.into_values() {
+        tiles
+            .entry((posting.entry_id.clone(), posting.tile_id))
+            .or_default()
+            .insert(posting.item_uid);
+    }
+    Some(tiles)
+}
+
+// Answers the request from a search index, reading shards (by position)
+// with read_shard, or returns None if the index can't answer it. Parts of
+// the index or profile that fail to load are scanned instead or skipped, so
+// that they don't cost the rest of the results.
+pub fn indexed_search<T>(
+    data_source: &T,
+    index: &SearchIndexInfo,
+    read_shard: impl Fn(usize) -> Result<SearchIndexShard, DataSourceError>,
+    request: &SearchRequest,
+) -> Option<Result<Vec<SearchResult>, DataSourceError>>
+where
+    T: DataSource + ?Sized,
+{
+    let lookup = index.lookup(request)?;
+    let search = || {
+        let info = data_source.fetch_info()?;
+        let mut collector = SearchCollector::new(request, &info.field_schema)?;
+        let shards = (lookup.shards().into_iter())
+            .filter_map(|i| Some((i, read_shard(i).ok()?)))
+            .collect();
+        let tiles = match candidates(request, &lookup, &shards) {
+            Some(candidates) => (candidates.into_iter())
+                .map(|(tile, item_uids)| (tile, Some(item_uids)))
+                .collect(),
+            None => (scan_tiles(&info, request).into_iter())
+                .map(|tile| (tile, None))
+                .collect(),
+        };
+        collect_tiles(&mut collector, tiles, |(entry_id, tile_id)| {
+            data_source.fetch_slot_meta_tile(entry_id, *tile_id, true)
+        })?;
+        Ok(collector.finish())
+    };
+    Some(search())
+}
+
+// A tile to search, with the candidates in it (or None to check every item)
+pub type SearchTile = ((EntryID, TileID), Option<BTreeSet<ItemUID>>);
+
+// Adds the tiles to the collector, in the order given. A tile that fails to
+// load is skipped, unless they all do.
+pub fn collect_tiles(
+    collector: &mut SearchCollector,
+    tiles: Vec<SearchTile>,
+    mut fetch: impl FnMut(&(EntryID, TileID)) -> Result<SlotMetaTile, DataSourceError>,
+) -> Result<(), DataSourceError> {
+    let mut error = None;
+    let mut loaded = false;
+    for (key, item_uids) in tiles {
+        if collector.is_full() {
+            break;
+        }
+        match fetch(&key) {
+            Ok(tile) => {
+                loaded = true;
+                collector.add_tile(&tile, item_uids.as_ref());
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+    match error {
+        Some(e) if !loaded => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(mode: SearchMode, query: &str, s: &str) -> bool {
+        match mode.compile(query).unwrap() {
+            Some(matcher) => matcher.is_match(s),
+            None => s.contains(query),
+        }
+    }
+
+    #[test]
+    fn test_search_modes() {
+        assert!(is_match(SearchMode::Substring, "copy", "copy_in_gpu"));
+        assert!(!is_match(SearchMode::Substring, "COPY", "copy_in_gpu"));
+        assert!(is_match(SearchMode::CaseInsensitive, "COPY", "copy_in_gpu"));
+        assert!(is_match(SearchMode::CaseInsensitive, "a.b", "A.B"));
+        assert!(!is_match(SearchMode::CaseInsensitive, "a.b", "axb"));
+        assert!(is_match(SearchMode::WholeWord, "task", "task 1"));
+        assert!(is_match(SearchMode::WholeWord, "task", "my task"));
+        assert!(!is_match(SearchMode::WholeWord, "task", "tasks"));
+        assert!(is_match(SearchMode::Regex, "^copy_.*_gpu$", "copy_in_gpu"));
+        assert!(!is_match(SearchMode::Regex, "^copy_.*_gpu$", "copy_in_cpu"));
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        assert!(SearchMode::Regex.compile("copy_(").is_err());
+        assert!(SearchMode::CaseInsensitive.compile("copy_(").is_ok());
+    }
+}
@@ -0,0 +1,333 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::timestamp::Interval;
+
+// Aggregate statistics over the items in an interval. Slots are grouped
+// (e.g., by slot, or by kind of slot), and for each group we report busy
+// time (covered by at least one item), idle time, and the count and
+// duration distribution of its items, both overall and for each item
+// title.
+
+#[derive(Debug, Clone)]
+pub struct StatsItem {
+    pub interval: Interval,
+    pub title: String,
+}
+
+// The items of one slot (each item once), and the group it belongs to
+#[derive(Debug, Clone)]
+pub struct SlotItems {
+    pub group: String,
+    pub items: Vec<StatsItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsRow {
+    pub group: String,
+    // None for the row covering every item in the group
+    pub title: Option<String>,
+    pub count: u64,
+    pub busy_ns: i64,
+    // Summed over the group's slots. Only for the row covering every item,
+    // since time not spent on one title isn't idle.
+    pub idle_ns: Option<i64>,
+    pub mean_ns: i64,
+    pub median_ns: i64,
+    pub p99_ns: i64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatsColumn {
+    Group,
+    Title,
+    Count,
+    Busy,
+    Idle,
+    Mean,
+    Median,
+    P99,
+}
+
+impl StatsColumn {
+    pub const ALL: [StatsColumn; 8] = [
+        StatsColumn::Group,
+        StatsColumn::Title,
+        StatsColumn::Count,
+        StatsColumn::Busy,
+        StatsColumn::Idle,
+        StatsColumn::Mean,
+        StatsColumn::Median,
+        StatsColumn::P99,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatsColumn::Group => "Group",
+            StatsColumn::Title => "Title",
+            StatsColumn::Count => "Count",
+            StatsColumn::Busy => "Busy",
+            StatsColumn::Idle => "Idle",
+            StatsColumn::Mean => "Mean",
+            StatsColumn::Median => "Median",
+            StatsColumn::P99 => "p99",
+        }
+    }
+
+    fn compare(self, a: &StatsRow, b: &StatsRow) -> Ordering {
+        match self {
+            StatsColumn::Group => a.group.cmp(&b.group),
+            StatsColumn::Title => a.title.cmp(&b.title),
+            StatsColumn::Count => a.count.cmp(&b.count),
+            StatsColumn::Busy => a.busy_ns.cmp(&b.busy_ns),
+            StatsColumn::Idle => a.idle_ns.cmp(&b.idle_ns),
+            StatsColumn::Mean => a.mean_ns.cmp(&b.mean_ns),
+            StatsColumn::Median => a.median_ns.cmp(&b.median_ns),
+            StatsColumn::P99 => a.p99_ns.cmp(&b.p99_ns),
+        }
+    }
+}
+
+// Stable, so ties stay in group and title order
+pub fn sort_rows(rows: &mut [StatsRow], column: StatsColumn, descending: bool) {
+    rows.sort_by(|a, b| {
+        let ordering = column.compare(a, b);
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+// Time covered by at least one of the intervals, within bounds
+fn busy_ns<'a>(intervals: impl Iterator<Item = &'a Interval>, bounds: Interval) -> i64 {
+    let mut intervals: Vec<_> = intervals
+        .filter(|i| i.overlaps(bounds))
+        .map(|i| i.intersection(bounds))
+        .collect();
+    intervals.sort_by_key(|i| i.start);
+
+    let mut busy = 0;
+    let mut current: Option<Interval> = None;
+    for interval in intervals {
+        match &mut current {
+            Some(c) if interval.start <= c.stop => c.stop = c.stop.max(interval.stop),
+            _ => {
+                busy += current.map_or(0, |c| c.duration_ns());
+                current = Some(interval);
+            }
+        }
+    }
+    busy + current.map_or(0, |c| c.duration_ns())
+}
+
+// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[i64], p: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Default)]
+struct Accumulator {
+    slots: u64,
+    busy_ns: i64,
+    durations: Vec<i64>,
+}
+
+impl Accumulator {
+    fn add_slot(&mut self, items: &[&StatsItem], bounds: Interval) {
+        self.slots += 1;
+        self.busy_ns += busy_ns(items.iter().map(|item| &item.interval), bounds);
+        self.durations
+            .extend(items.iter().map(|item| item.interval.duration_ns()));
+    }
+
+    fn finish(mut self, group: &str, title: Option<&str>, bounds: Interval) -> StatsRow {
+        self.durations.sort_unstable();
+        let count = self.durations.len() as u64;
+        let total: i64 = self.durations.iter().sum();
+        let idle_ns = self.slots as i64 * bounds.duration_ns() - self.busy_ns;
+        StatsRow {
+            group: group.to_owned(),
+            title: title.map(str::to_owned),
+            count,
+            busy_ns: self.busy_ns,
+            idle_ns: title.is_none().then_some(idle_ns),
+            mean_ns: if count > 0 { total / count as i64 } else { 0 },
+            median_ns: percentile(&self.durations, 0.5),
+            p99_ns: percentile(&self.durations, 0.99),
+        }
+    }
+}
+
+// Items are counted (with their full durations) if they overlap bounds,
+// but only the part within bounds is busy time. Rows are ordered by group,
+// then title, with each group's overall row first.
+pub fn statistics(slots: &[SlotItems], bounds: Interval) -> Vec<StatsRow> {
+    let mut groups: BTreeMap<&str, (Accumulator, BTreeMap<&str, Accumulator>)> = BTreeMap::new();
+    for slot in slots {
+        let items: Vec<_> = (slot.items.iter())
+            .filter(|item| item.interval.overlaps(bounds))
+            .collect();
+        let mut titles: BTreeMap<&str, Vec<&StatsItem>> = BTreeMap::new();
+        for item in &items {
+            titles.entry(&item.title).or_default().push(item);
+        }
+
+        let (all, by_title) = groups.entry(&slot.group).or_default();
+        all.add_slot(&items, bounds);
+        for (title, items) in titles {
+            by_title.entry(title).or_default().add_slot(&items, bounds);
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (group, (all, by_title)) in groups {
+        rows.push(all.finish(group, None, bounds));
+        for (title, acc) in by_title {
+            rows.push(acc.finish(group, Some(title), bounds));
+        }
+    }
+    rows
+}
+
+// Counts of durations in logarithmic bins, with BINS_PER_DECADE bins per
+// power of ten, covering every bin from the first non-empty one to the last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub first_bin: i32,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub const BINS_PER_DECADE: i32 = 4;
+
+    pub fn new(durations: impl IntoIterator<Item = i64>) -> Self {
+        let bins: Vec<_> = durations.into_iter().map(Self::bin).collect();
+        let (Some(&first), Some(&last)) = (bins.iter().min(), bins.iter().max()) else {
+            return Self {
+                first_bin: 0,
+                counts: Vec::new(),
+            };
+        };
+        let mut counts = vec![0; (last - first + 1) as usize];
+        for bin in bins {
+            counts[(bin - first) as usize] += 1;
+        }
+        Self {
+            first_bin: first,
+            counts,
+        }
+    }
+
+    // Zero-length items go in the same bin as 1 ns ones
+    pub fn bin(duration_ns: i64) -> i32 {
+        let duration_ns = duration_ns.max(1);
+        let mut bin = ((duration_ns as f64).log10() * Self::BINS_PER_DECADE as f64).floor() as i32;
+        // Fix up rounding error, so that bins agree with bin_start
+        while Self::bin_start(bin + 1) <= duration_ns {
+            bin += 1;
+        }
+        while Self::bin_start(bin) > duration_ns {
+            bin -= 1;
+        }
+        bin
+    }
+
+    // The shortest duration in the bin
+    pub fn bin_start(bin: i32) -> i64 {
+        10f64.powf(bin as f64 / Self::BINS_PER_DECADE as f64).ceil() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timestamp::Timestamp;
+
+    fn item(start: i64, stop: i64, title: &str) -> StatsItem {
+        StatsItem {
+            interval: Interval::new(Timestamp(start), Timestamp(stop)),
+            title: title.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let slots = vec![
+            SlotItems {
+                group: "cpu".to_owned(),
+                items: vec![
+                    item(0, 10, "a"),
+                    // Overlaps the previous item, so busy time counts once
+                    item(5, 20, "b"),
+                    item(40, 50, "a"),
+                    // Outside the bounds
+                    item(200, 210, "a"),
+                ],
+            },
+            SlotItems {
+                group: "cpu".to_owned(),
+                // Clipped to the bounds for busy time, but not duration
+                items: vec![item(90, 130, "a")],
+            },
+            SlotItems {
+                group: "gpu".to_owned(),
+                items: Vec::new(),
+            },
+        ];
+        let bounds = Interval::new(Timestamp(0), Timestamp(100));
+        let rows = statistics(&slots, bounds);
+        let summary: Vec<_> = (rows.iter())
+            .map(|r| {
+                (
+                    r.group.as_str(),
+                    r.title.as_deref(),
+                    r.count,
+                    r.busy_ns,
+                    r.idle_ns,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("cpu", None, 4, 40, Some(160)),
+                ("cpu", Some("a"), 3, 30, None),
+                ("cpu", Some("b"), 1, 15, None),
+                ("gpu", None, 0, 0, Some(100)),
+            ]
+        );
+
+        // Durations 10, 10, 15, 40
+        assert_eq!(rows[0].mean_ns, 18);
+        assert_eq!(rows[0].median_ns, 10);
+        assert_eq!(rows[0].p99_ns, 40);
+        assert_eq!(rows[1].median_ns, 10);
+
+        let mut rows = rows;
+        sort_rows(&mut rows, StatsColumn::Count, true);
+        let counts: Vec<_> = rows.iter().map(|r| r.count).collect();
+        assert_eq!(counts, [4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::new([1, 10, 100, 150, 1000, 0]);
+        assert_eq!(histogram.first_bin, 0);
+        assert_eq!(histogram.counts.len(), 13);
+        assert_eq!(histogram.counts[0], 2);
+        assert_eq!(histogram.counts[8], 2);
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 6);
+
+        for bin in 0..60 {
+            assert_eq!(Histogram::bin(Histogram::bin_start(bin)), bin);
+        }
+        assert!(Histogram::new(Vec::new()).counts.is_empty());
+    }
+}
@@ -164,6 +164,24 @@ impl Interval {
             stop: Timestamp(self.stop.0 + duration_ns),
         }
     }
+    // Shift interval by duration_ns (to the left if negative).
+    pub fn translate(self, duration_ns: i64) -> Self {
+        Self {
+            start: Timestamp(self.start.0 + duration_ns),
+            stop: Timestamp(self.stop.0 + duration_ns),
+        }
+    }
+    // Like translate, but stops at the edge of bounds in the direction of
+    // travel. An interval already past that edge (e.g., after zooming out)
+    // stays put rather than jumping back the other way.
+    pub fn translate_within(self, duration_ns: i64, bounds: Interval) -> Self {
+        let duration_ns = if duration_ns < 0 {
+            duration_ns.max((bounds.start.0 - self.start.0).min(0))
+        } else {
+            duration_ns.min((bounds.stop.0 - self.stop.0).max(0))
+        };
+        self.translate(duration_ns)
+    }
 }
 
 #[cfg(test)]
@@ -308,4 +326,26 @@ mod tests {
             Err(TimestampParseError::InvalidUnit)
         );
     }
+
+    #[test]
+    fn test_translate_within() {
+        let interval = |start, stop| Interval::new(Timestamp(start), Timestamp(stop));
+        let bounds = interval(0, 100);
+        let view = interval(10, 30);
+        assert_eq!(view.translate_within(20, bounds), interval(30, 50));
+        assert_eq!(view.translate_within(-20, bounds), interval(0, 20));
+        assert_eq!(view.translate_within(500, bounds), interval(80, 100));
+        assert_eq!(bounds.translate_within(10, bounds), bounds);
+        let wide = interval(-50, 150);
+        assert_eq!(wide.translate_within(20, bounds), wide);
+        assert_eq!(wide.translate_within(-20, bounds), wide);
+
+        // Partly out of bounds: free to move back in, but not further out
+        let left = interval(-10, 40);
+        assert_eq!(left.translate_within(-20, bounds), left);
+        assert_eq!(left.translate_within(20, bounds), interval(10, 60));
+        let right = interval(70, 120);
+        assert_eq!(right.translate_within(20, bounds), right);
+        assert_eq!(right.translate_within(-20, bounds), interval(50, 100));
+    }
 }